@@ -0,0 +1,136 @@
+//! Quorum search benchmarks.
+//! Run with `cargo bench --bench quorum`.
+//! Reports the wall time and number of allocations
+//! per call to `find_blocking` and `find_quorum`.
+//!
+//! Allocations per call, before the undo-log search (boxed predicates,
+//! cloning the found set on every recursive call) and now.
+//! The quorum search checks every member's slice now, so it allocates
+//! a little more than the undo-log search did when it first landed:
+//!
+//! ```text
+//! topology              before      now
+//! flat 100 blocking         74       46
+//! flat 100 quorum         4107      109
+//! flat 1000 blocking       677      353
+//! flat 1000 quorum      390960     1013
+//! tiered 100 blocking      180       65
+//! tiered 100 quorum      12445      109
+//! tiered 1000 blocking   12883      572
+//! tiered 1000 quorum   8616848     1013
+//! ```
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use drop_in_fba::{
+    ballot::Ballot,
+    index::Statements,
+    message::Message,
+    node::NodeId,
+    predicate::FnPredicate,
+    quorum::{Member, Quorum},
+    slot::SlotId,
+    topic::{self, Topic},
+    value::Value,
+};
+
+// Counting allocator
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+// Topologies
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+struct BenchValue(u64);
+
+impl Value for BenchValue {
+    fn combine(this: Self, that: Self, _slot_id: SlotId) -> Self {
+        BenchValue(this.0.max(that.0))
+    }
+}
+
+fn node_id(i: usize) -> NodeId {
+    NodeId::new(format!("node-{}", i))
+}
+
+/// Every node trusts every other node, with a 2/3 threshold.
+fn flat(n: usize) -> Quorum<BenchValue> {
+    let members = (0..n).map(|i| Member::Node(node_id(i))).collect();
+    Quorum::new(n * 2 / 3 + 1, members)
+}
+
+/// Nodes are grouped into organizations of three, with a 2/3 threshold,
+/// and every node trusts 2/3 of all organizations.
+fn tiered(n: usize) -> Quorum<BenchValue> {
+    let orgs = (0..n / 3).map(|o| {
+        let members = (0..3).map(|i| Member::Node(node_id(o * 3 + i))).collect();
+        Member::Quorum(Quorum::new(2, members))
+    }).collect::<Vec<_>>();
+    Quorum::new(orgs.len() * 2 / 3 + 1, orgs)
+}
+
+/// Every node has sent an externalize message, with the same quorum set.
+fn messages(n: usize, quorum: &Quorum<BenchValue>) -> Statements<BenchValue> {
+    let mut counter = 0;
+    (0..n).map(|i| {
+        let topic = Topic::Externalize(topic::Externalize {
+            ballot:  Ballot { number: 1, value: BenchValue(0) },
+            highest: 1,
+        });
+        Message::new(node_id(i), SlotId::new(1), quorum.clone(), topic, &mut counter)
+    }).collect()
+}
+
+// Harness
+
+fn bench<F: FnMut() -> usize>(name: &str, iterations: usize, mut f: F) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut found = 0;
+    for _ in 0..iterations { found += f(); }
+    let elapsed = start.elapsed() / iterations as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / iterations;
+
+    assert!(found > 0, "{} found nothing", name);
+    println!("{:<24} {:>12?} {:>12} allocations", name, elapsed, allocations);
+}
+
+fn main() {
+    for (topology, build) in [("flat", flat as fn(usize) -> Quorum<BenchValue>), ("tiered", tiered)].iter() {
+        for n in [100, 1000].iter() {
+            let quorum = build(*n);
+            let messages = messages(*n, &quorum);
+            let iterations = if *n > 100 { 10 } else { 100 };
+
+            bench(&format!("{} {} blocking", topology, n), iterations, || {
+                let (found, _) = quorum.find_blocking(&messages, FnPredicate::new(|_| true));
+                found.len()
+            });
+
+            bench(&format!("{} {} quorum", topology, n), iterations, || {
+                let (found, _) = quorum.find_quorum(node_id(0), &messages, FnPredicate::new(|_| true));
+                found.len()
+            });
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Decoding arbitrary bytes must never panic,
+//! and anything that does decode must encode back to the same bytes.
+//! Run with `cargo fuzz run decode`.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use drop_in_fba::{
+    codec::{self, Encode, Decode},
+    message::Message,
+    slot::SlotId,
+    trace::Record,
+    value::Value,
+};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+struct FuzzValue(u8);
+
+impl Value for FuzzValue {
+    fn combine(this: Self, that: Self, _slot_id: SlotId) -> Self {
+        FuzzValue(this.0.max(that.0))
+    }
+}
+
+impl Encode for FuzzValue {
+    fn encode(&self, out: &mut Vec<u8>) { self.0.encode(out); }
+}
+
+impl Decode for FuzzValue {
+    fn decode(input: &mut &[u8]) -> Result<FuzzValue, ()> {
+        Ok(FuzzValue(u8::decode(input)?))
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = codec::from_bytes::<Message<FuzzValue>>(data) {
+        let _ = message.valid();
+        // sets might have come in out of order, so only compare once normalized
+        let bytes = codec::to_bytes(&message);
+        assert_eq!(codec::from_bytes::<Message<FuzzValue>>(&bytes), Ok(message));
+    }
+
+    let _ = codec::from_bytes::<Record<FuzzValue>>(data);
+});
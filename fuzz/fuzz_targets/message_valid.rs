@@ -0,0 +1,39 @@
+//! Checking an arbitrary message must never panic,
+//! and neither must ordering it against another.
+//! Run with `cargo fuzz run message_valid`.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use drop_in_fba::{
+    arbitrary::{Arbitrary, Source},
+    message::Message,
+    slot::SlotId,
+    value::Value,
+};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+struct FuzzValue(u8);
+
+impl Value for FuzzValue {
+    fn combine(this: Self, that: Self, _slot_id: SlotId) -> Self {
+        FuzzValue(this.0.max(that.0))
+    }
+}
+
+impl Arbitrary for FuzzValue {
+    fn arbitrary(source: &mut Source) -> FuzzValue {
+        FuzzValue(source.below(4) as u8)
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut source = Source::from_bytes(data);
+    let first  = Message::<FuzzValue>::arbitrary(&mut source);
+    let second = Message::<FuzzValue>::arbitrary(&mut source);
+
+    let _ = first.valid();
+    let _ = first.topic.partial_cmp(&second.topic);
+    let _ = first.topic.supersedes(&second.topic);
+    let _ = first.contradicts(&second);
+});
@@ -0,0 +1,48 @@
+//! Feeding a slot any sequence of messages must never panic.
+//! Bad messages should come back as errors, not take the node down.
+//! Run with `cargo fuzz run slot_handle`.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use drop_in_fba::{
+    arbitrary::{Arbitrary, Source},
+    message::Message,
+    node::NodeId,
+    quorum::{Member, Quorum},
+    slot::{Context, Slot, SlotId},
+    value::Value,
+};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+struct FuzzValue(u8);
+
+impl Value for FuzzValue {
+    fn combine(this: Self, that: Self, _slot_id: SlotId) -> Self {
+        FuzzValue(this.0.max(that.0))
+    }
+}
+
+impl Arbitrary for FuzzValue {
+    fn arbitrary(source: &mut Source) -> FuzzValue {
+        FuzzValue(source.below(4) as u8)
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut source = Source::from_bytes(data);
+
+    // the arbitrary node ids are a through d
+    let members = ["a", "b", "c", "d"].iter()
+        .map(|name| Member::Node(NodeId::new(name.to_string())))
+        .collect();
+    let node_id = NodeId::new("a".to_string());
+    let quorum  = Quorum::new(3, members);
+    let context = Context { node_id: &node_id, quorum: &quorum };
+    let mut slot = Slot::new(SlotId::new(0));
+
+    for _ in 0..source.below(16) {
+        let message = Message::<FuzzValue>::arbitrary(&mut source);
+        let _ = slot.handle(context, message);
+    }
+});
@@ -0,0 +1,392 @@
+//! Checking a whole network of quorum sets, before it's deployed.
+//! Given every node's quorum set, a [`Network`] can tell you:
+//! - structural [`Problem`]s, like thresholds that can't be met;
+//! - the minimal quorums, and whether every two of them intersect,
+//!   which is what keeps the network safe;
+//! - the minimal blocking sets, any of which can halt the network by crashing;
+//! - the minimal splitting sets, any of which can fork the network by lying;
+//! - nodes that aren't part of any quorum, and each node's transitive closure.
+//!
+//! Finding minimal quorums means searching every subset of nodes that could be in a quorum,
+//! so the searches give up on networks with more than [`MAX_SEARCH`] such nodes.
+//!
+//! A quorum here is a non-empty set of nodes that contains a slice of each of its members.
+//! A splitting set is the intersection of two different minimal quorums:
+//! if those nodes are faulty, the rest of the two quorums can be told different things.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use crate::{
+    index::{BitSet, NodeIndex},
+    node::NodeId,
+    quorum::{Member, Quorum},
+    value::Value,
+};
+
+// TODO: better error types
+
+/// The most nodes that can be in quorums for the exhaustive searches to run.
+pub const MAX_SEARCH: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A quorum set, or a set inside it, needs more members than it has.
+    Unmeetable(NodeId),
+    /// A quorum set, or a set inside it, has a threshold of zero,
+    /// so even no nodes at all satisfy it.
+    Trivial(NodeId),
+    /// A quorum set lists the same node more than once.
+    Duplicate { node_id: NodeId, member: NodeId },
+    /// A quorum set lists a node that has no quorum set of its own.
+    Unknown { node_id: NodeId, member: NodeId },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Unmeetable(n) => write!(f, "{}: a threshold is higher than its number of members", n.as_str()),
+            Problem::Trivial(n)    => write!(f, "{}: a threshold is zero", n.as_str()),
+            Problem::Duplicate { node_id, member } => {
+                write!(f, "{}: {} is listed more than once", node_id.as_str(), member.as_str())
+            },
+            Problem::Unknown { node_id, member } => {
+                write!(f, "{}: {} has no quorum set", node_id.as_str(), member.as_str())
+            },
+        }
+    }
+}
+
+/// Two quorums that don't intersect.
+pub type Disjoint = (Vec<NodeId>, Vec<NodeId>);
+
+/// A quorum set compiled down to node indices,
+/// split into nodes and inner sets, which is all satisfying one needs.
+struct Slice {
+    threshold: usize,
+    nodes:     Vec<usize>,
+    inner:     Vec<Slice>,
+}
+
+impl Slice {
+    fn new<T: Value>(quorum: &Quorum<T>, index: &mut NodeIndex) -> Slice {
+        let mut nodes = vec![];
+        let mut inner = vec![];
+        for member in quorum.members().iter() {
+            match member {
+                Member::Node(n)   => nodes.push(index.intern(n)),
+                Member::Quorum(q) => inner.push(Slice::new(q, index)),
+            }
+        }
+        return Slice { threshold: quorum.threshold(), nodes, inner };
+    }
+
+    /// Whether the nodes `contains` says yes to make up a slice.
+    fn satisfied<F: Fn(usize) -> bool>(&self, contains: &F) -> bool {
+        let mut count = self.nodes.iter().filter(|n| contains(**n)).count();
+        for inner in self.inner.iter() {
+            if count >= self.threshold { break; }
+            if inner.satisfied(contains) { count += 1; }
+        }
+        return count >= self.threshold;
+    }
+}
+
+/// Every node's quorum set, see the module docs.
+pub struct Network<T: Value> {
+    quorums: HashMap<NodeId, Quorum<T>>,
+    /// Every node, whether it has a quorum set or is only mentioned in one.
+    index:   NodeIndex,
+    /// By node index.
+    slices:  Vec<Option<Slice>>,
+}
+
+impl<T: Value> Network<T> {
+    pub fn new(quorums: HashMap<NodeId, Quorum<T>>) -> Network<T> {
+        // sorted, so indices (and so results) don't depend on hash order
+        let mut node_ids = quorums.keys().collect::<Vec<&NodeId>>();
+        node_ids.sort();
+
+        let mut index = NodeIndex::new();
+        for node_id in node_ids.iter() { index.intern(node_id); }
+
+        let mut slices = node_ids.iter()
+            .map(|n| Some(Slice::new(&quorums[*n], &mut index)))
+            .collect::<Vec<Option<Slice>>>();
+        // nodes only mentioned in quorum sets can't be satisfied
+        slices.resize_with(index.len(), || None);
+
+        return Network { quorums, index, slices };
+    }
+
+    /// Every node, with or without a quorum set, sorted.
+    pub fn nodes(&self) -> Vec<NodeId> {
+        let mut nodes = (0..self.index.len())
+            .filter_map(|i| self.index.node_id(i).cloned())
+            .collect::<Vec<NodeId>>();
+        nodes.sort();
+        return nodes;
+    }
+
+    fn node_ids(&self, nodes: impl Iterator<Item=usize>) -> Vec<NodeId> {
+        let mut node_ids = nodes
+            .filter_map(|i| self.index.node_id(i).cloned())
+            .collect::<Vec<NodeId>>();
+        node_ids.sort();
+        return node_ids;
+    }
+
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        for node_id in self.nodes() {
+            let quorum = match self.quorums.get(&node_id) {
+                Some(q) => q,
+                None    => continue,
+            };
+            check(&node_id, quorum, &mut problems);
+
+            let mut members = vec![];
+            collect(quorum, &mut members);
+            members.sort();
+            for (i, member) in members.iter().enumerate() {
+                if i > 0 && members[i - 1] == *member {
+                    // only report each duplicate once
+                    if i < 2 || members[i - 2] != *member {
+                        problems.push(Problem::Duplicate { node_id: node_id.clone(), member: (*member).clone() });
+                    }
+                } else if !self.quorums.contains_key(member) {
+                    problems.push(Problem::Unknown { node_id: node_id.clone(), member: (*member).clone() });
+                }
+            }
+        }
+        return problems;
+    }
+
+    /// Every node reachable from `node_id` through quorum sets, itself included.
+    pub fn closure(&self, node_id: &NodeId) -> Vec<NodeId> {
+        let start = match self.index.get(node_id) {
+            Some(i) => i,
+            None    => { return vec![]; },
+        };
+
+        let mut seen = BitSet::new();
+        let mut queue = VecDeque::new();
+        seen.insert(start);
+        queue.push_back(start);
+
+        while let Some(next) = queue.pop_front() {
+            let mut members = vec![];
+            if let Some(slice) = &self.slices[next] { flatten(slice, &mut members); }
+            for member in members {
+                if seen.insert(member) { queue.push_back(member); }
+            }
+        }
+        return self.node_ids(seen.iter());
+    }
+
+    /// The largest quorum inside `set`:
+    /// drops nodes without a slice in the set until there are none left to drop.
+    fn quorum_in(&self, mut set: BitSet) -> BitSet {
+        loop {
+            let unsatisfied = set.iter().filter(|n| match &self.slices[*n] {
+                Some(slice) => !slice.satisfied(&|m| set.contains(m)),
+                None        => true,
+            }).collect::<Vec<usize>>();
+
+            if unsatisfied.is_empty() { return set; }
+            for n in unsatisfied { set.remove(n); }
+        }
+    }
+
+    /// Every node in some quorum.
+    /// Two quorums together are a quorum, so this is the largest one.
+    fn in_quorums(&self) -> BitSet {
+        let mut all = BitSet::new();
+        for n in 0..self.index.len() { all.insert(n); }
+        return self.quorum_in(all);
+    }
+
+    /// Nodes that aren't part of any quorum, so can never externalize anything.
+    pub fn outside_quorums(&self) -> Vec<NodeId> {
+        let inside = self.in_quorums();
+        return self.node_ids((0..self.index.len()).filter(|n| !inside.contains(*n)));
+    }
+
+    /// The minimal quorums, as bitmasks over the nodes that are in quorums.
+    /// Errors if there are too many of those to search.
+    fn minimal_masks(&self) -> Result<(Vec<usize>, Vec<u32>), ()> {
+        let candidates = self.in_quorums().iter().collect::<Vec<usize>>();
+        if candidates.len() > MAX_SEARCH { return Err(()); }
+
+        let mut local = vec![None; self.index.len()];
+        for (l, n) in candidates.iter().enumerate() { local[*n] = Some(l); }
+
+        let mut quorums = vec![];
+        for mask in 1..(1u32 << candidates.len()) {
+            let mut set = mask;
+            // the same fixpoint as `quorum_in`, over the mask
+            loop {
+                let contains = |n: usize| local[n].is_some_and(|l| set & (1 << l) != 0);
+                let unsatisfied = (0..candidates.len())
+                    .filter(|l| set & (1 << l) != 0)
+                    .filter(|l| match &self.slices[candidates[*l]] {
+                        Some(slice) => !slice.satisfied(&contains),
+                        None        => true,
+                    })
+                    .fold(0, |acc, l| acc | (1 << l));
+
+                if unsatisfied == 0 { break; }
+                set &= !unsatisfied;
+            }
+            if set == mask { quorums.push(mask); }
+        }
+
+        quorums.sort_by_key(|q| q.count_ones());
+        let mut minimal: Vec<u32> = vec![];
+        for quorum in quorums {
+            if !minimal.iter().any(|m| m & quorum == *m) { minimal.push(quorum); }
+        }
+        return Ok((candidates, minimal));
+    }
+
+    fn unmask(&self, candidates: &[usize], mask: u32) -> Vec<NodeId> {
+        return self.node_ids((0..candidates.len()).filter(|l| mask & (1 << l) != 0).map(|l| candidates[l]));
+    }
+
+    pub fn minimal_quorums(&self) -> Result<Vec<Vec<NodeId>>, ()> {
+        let (candidates, minimal) = self.minimal_masks()?;
+        return Ok(minimal.into_iter().map(|m| self.unmask(&candidates, m)).collect());
+    }
+
+    /// Two quorums that don't intersect, if there are any.
+    /// If there aren't, quorum intersection holds.
+    pub fn disjoint_quorums(&self) -> Result<Option<Disjoint>, ()> {
+        let (candidates, minimal) = self.minimal_masks()?;
+        for (i, a) in minimal.iter().enumerate() {
+            for b in minimal[i + 1..].iter() {
+                if a & b == 0 {
+                    return Ok(Some((self.unmask(&candidates, *a), self.unmask(&candidates, *b))));
+                }
+            }
+        }
+        return Ok(None);
+    }
+
+    /// The smallest sets of nodes that intersect every quorum.
+    /// If all the nodes in one of these crash, there's no quorum left.
+    pub fn minimal_blocking_sets(&self) -> Result<Vec<Vec<NodeId>>, ()> {
+        let (candidates, minimal) = self.minimal_masks()?;
+
+        let mut masks = (0..(1u32 << candidates.len())).collect::<Vec<u32>>();
+        masks.sort_by_key(|m| m.count_ones());
+
+        let mut blocking: Vec<u32> = vec![];
+        for mask in masks {
+            if blocking.iter().any(|b| b & mask == *b) { continue; }
+            if minimal.iter().all(|q| q & mask != 0) { blocking.push(mask); }
+        }
+        return Ok(blocking.into_iter().map(|b| self.unmask(&candidates, b)).collect());
+    }
+
+    /// The smallest intersections of two different minimal quorums.
+    /// If all the nodes in one of these are faulty, the network can fork.
+    /// An empty set means quorum intersection doesn't hold at all.
+    pub fn minimal_splitting_sets(&self) -> Result<Vec<Vec<NodeId>>, ()> {
+        let (candidates, minimal) = self.minimal_masks()?;
+
+        let mut intersections = vec![];
+        for (i, a) in minimal.iter().enumerate() {
+            for b in minimal[i + 1..].iter() { intersections.push(a & b); }
+        }
+        intersections.sort_by_key(|m| m.count_ones());
+
+        let mut splitting: Vec<u32> = vec![];
+        for mask in intersections {
+            if !splitting.iter().any(|s| s & mask == *s) { splitting.push(mask); }
+        }
+        return Ok(splitting.into_iter().map(|s| self.unmask(&candidates, s)).collect());
+    }
+}
+
+/// Checks thresholds, all the way down.
+fn check<T: Value>(node_id: &NodeId, quorum: &Quorum<T>, problems: &mut Vec<Problem>) {
+    if quorum.threshold() > quorum.members().len() { problems.push(Problem::Unmeetable(node_id.clone())); }
+    if quorum.threshold() == 0 { problems.push(Problem::Trivial(node_id.clone())); }
+    for member in quorum.members().iter() {
+        if let Member::Quorum(q) = member { check(node_id, q, problems); }
+    }
+}
+
+/// Every node listed in a quorum set, duplicates and all.
+fn collect<'q, T: Value>(quorum: &'q Quorum<T>, members: &mut Vec<&'q NodeId>) {
+    for member in quorum.members().iter() {
+        match member {
+            Member::Node(n)   => members.push(n),
+            Member::Quorum(q) => collect(q, members),
+        }
+    }
+}
+
+fn flatten(slice: &Slice, members: &mut Vec<usize>) {
+    members.extend(slice.nodes.iter());
+    for inner in slice.inner.iter() { flatten(inner, members); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{node_id, node_ids, DummyValue};
+
+    fn set(threshold: usize, names: &[&str]) -> Quorum<DummyValue> {
+        Quorum::new(threshold, node_ids(names).into_iter().map(Member::Node).collect())
+    }
+
+    fn network(sets: Vec<(&str, Quorum<DummyValue>)>) -> Network<DummyValue> {
+        Network::new(sets.into_iter().map(|(n, q)| (node_id(n), q)).collect())
+    }
+
+    #[test]
+    fn symmetric() {
+        // four nodes, any three make a quorum
+        let all = ["a", "b", "c", "d"];
+        let network = network(all.iter().map(|n| (*n, set(3, &all))).collect());
+
+        assert_eq!(network.problems(), vec![]);
+        assert_eq!(network.outside_quorums(), vec![]);
+        assert_eq!(network.closure(&node_id("a")), node_ids(&all));
+        assert_eq!(network.minimal_quorums().unwrap().len(), 4);
+        assert_eq!(network.disjoint_quorums(), Ok(None));
+
+        // any two crashing leaves no three
+        let blocking = network.minimal_blocking_sets().unwrap();
+        assert_eq!(blocking.len(), 6);
+        assert!(blocking.iter().all(|b| b.len() == 2));
+
+        // any two quorums share two nodes
+        let splitting = network.minimal_splitting_sets().unwrap();
+        assert_eq!(splitting.len(), 6);
+        assert!(splitting.iter().all(|s| s.len() == 2));
+    }
+
+    #[test]
+    fn split() {
+        // two halves that only trust themselves, and a node that trusts someone who isn't there
+        let network = network(vec![
+            ("a", set(2, &["a", "b"])),
+            ("b", set(2, &["a", "b"])),
+            ("c", set(2, &["c", "d"])),
+            ("d", set(2, &["c", "d"])),
+            ("e", set(3, &["e", "f"])),
+        ]);
+
+        assert_eq!(network.problems(), vec![
+            Problem::Unmeetable(node_id("e")),
+            Problem::Unknown { node_id: node_id("e"), member: node_id("f") },
+        ]);
+        assert_eq!(network.outside_quorums(), node_ids(&["e", "f"]));
+        assert_eq!(network.disjoint_quorums(), Ok(Some((node_ids(&["a", "b"]), node_ids(&["c", "d"])))));
+        assert_eq!(network.minimal_splitting_sets(), Ok(vec![vec![]]));
+    }
+}
@@ -0,0 +1,181 @@
+//! Generators for arbitrary protocol values,
+//! used by the property tests and the fuzz targets.
+//! Everything is drawn from tiny domains (a handful of nodes, values, and numbers)
+//! so that edge cases like equal or zero ballots come up all the time.
+
+use std::{
+    collections::HashSet,
+    hash::Hash,
+};
+
+use crate::{
+    ballot::Ballot,
+    message::Message,
+    node::NodeId,
+    quorum::{Member, Quorum},
+    slot::SlotId,
+    topic::{self, Topic},
+    value::Value,
+};
+
+/// Where the randomness comes from.
+/// Either a seeded pseudorandom generator, for property tests,
+/// or raw bytes, for fuzzing; once the bytes run out, everything is zero.
+#[derive(Debug, Clone)]
+pub struct Source {
+    bytes:    Option<Vec<u8>>,
+    position: usize,
+    state:    u64,
+}
+
+impl Source {
+    pub fn new(seed: u64) -> Source {
+        // xorshift gets stuck on zero
+        return Source { bytes: None, position: 0, state: seed | 1 };
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Source {
+        return Source { bytes: Some(bytes.to_vec()), position: 0, state: 0 };
+    }
+
+    fn next(&mut self) -> u64 {
+        if let Some(bytes) = &self.bytes {
+            let byte = bytes.get(self.position).copied().unwrap_or(0);
+            self.position += 1;
+            return byte as u64;
+        }
+
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        return self.state;
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n == 0 { return 0; }
+        return (self.next() % n as u64) as usize;
+    }
+
+    pub fn flip(&mut self) -> bool {
+        return self.below(2) == 1;
+    }
+}
+
+/// Something that can be generated from a [`Source`].
+pub trait Arbitrary: Sized {
+    fn arbitrary(source: &mut Source) -> Self;
+}
+
+impl Arbitrary for usize {
+    fn arbitrary(source: &mut Source) -> usize {
+        return source.below(4);
+    }
+}
+
+impl Arbitrary for NodeId {
+    fn arbitrary(source: &mut Source) -> NodeId {
+        let names = ["a", "b", "c", "d"];
+        return NodeId::new(names[source.below(names.len())].to_string());
+    }
+}
+
+impl Arbitrary for SlotId {
+    fn arbitrary(source: &mut Source) -> SlotId {
+        return SlotId::new(source.below(3));
+    }
+}
+
+fn set<S: Arbitrary + Hash + Eq>(source: &mut Source) -> HashSet<S> {
+    return (0..source.below(4)).map(|_| S::arbitrary(source)).collect();
+}
+
+impl<T: Value + Arbitrary> Arbitrary for Ballot<T> {
+    fn arbitrary(source: &mut Source) -> Ballot<T> {
+        return Ballot { number: usize::arbitrary(source), value: T::arbitrary(source) };
+    }
+}
+
+impl<T: Value + Arbitrary> Arbitrary for topic::Nominate<T> {
+    fn arbitrary(source: &mut Source) -> topic::Nominate<T> {
+        return topic::Nominate { nominated: set(source), accepted: set(source) };
+    }
+}
+
+impl<T: Value + Arbitrary> Arbitrary for topic::Prepare<T> {
+    fn arbitrary(source: &mut Source) -> topic::Prepare<T> {
+        return topic::Prepare {
+            ballot:     Ballot::arbitrary(source),
+            prepared_a: Ballot::arbitrary(source),
+            prepared_b: Ballot::arbitrary(source),
+            highest:    usize::arbitrary(source),
+            lowest:     usize::arbitrary(source),
+        };
+    }
+}
+
+impl<T: Value + Arbitrary> Arbitrary for topic::Commit<T> {
+    fn arbitrary(source: &mut Source) -> topic::Commit<T> {
+        return topic::Commit {
+            ballot:   Ballot::arbitrary(source),
+            prepared: usize::arbitrary(source),
+            highest:  usize::arbitrary(source),
+            lowest:   usize::arbitrary(source),
+        };
+    }
+}
+
+impl<T: Value + Arbitrary> Arbitrary for topic::Externalize<T> {
+    fn arbitrary(source: &mut Source) -> topic::Externalize<T> {
+        return topic::Externalize {
+            ballot:  Ballot::arbitrary(source),
+            highest: usize::arbitrary(source),
+        };
+    }
+}
+
+impl<T: Value + Arbitrary> Arbitrary for Topic<T> {
+    fn arbitrary(source: &mut Source) -> Topic<T> {
+        return match source.below(4) {
+            0 => Topic::Nominate(Arbitrary::arbitrary(source)),
+            1 => Topic::Prepare(Arbitrary::arbitrary(source)),
+            2 => Topic::Commit(Arbitrary::arbitrary(source)),
+            _ => Topic::Externalize(Arbitrary::arbitrary(source)),
+        };
+    }
+}
+
+/// Quorum sets nest at most two deep,
+/// and thresholds can be more than there are members.
+fn quorum<T: Value>(source: &mut Source, depth: usize) -> Quorum<T> {
+    let members = (0..source.below(4)).map(|_| {
+        if depth > 0 && source.below(4) == 0 {
+            Member::Quorum(quorum(source, depth - 1))
+        } else {
+            Member::Node(NodeId::arbitrary(source))
+        }
+    }).collect::<Vec<Member<T>>>();
+
+    let threshold = source.below(members.len() + 2);
+    return Quorum::new(threshold, members);
+}
+
+impl<T: Value> Arbitrary for Quorum<T> {
+    fn arbitrary(source: &mut Source) -> Quorum<T> {
+        return quorum(source, 2);
+    }
+}
+
+impl<T: Value + Arbitrary> Arbitrary for Message<T> {
+    fn arbitrary(source: &mut Source) -> Message<T> {
+        let mut counter = usize::arbitrary(source);
+        return Message::new(
+            NodeId::arbitrary(source),
+            SlotId::arbitrary(source),
+            Quorum::arbitrary(source),
+            Topic::arbitrary(source),
+            &mut counter,
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! An adapter for running a [`Node`] under any async executor,
+//! built on nothing but `std::future`.
+//! The node itself is a plain state machine;
+//! a [`Driver`] hooks it up to an [`AsyncTransport`] for messages and a [`Timer`] for timeouts,
+//! and hands out futures that make progress whenever they're polled.
+//! Implement the two traits on top of whatever runtime you use.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    time::Duration,
+};
+
+use crate::{
+    message::Message,
+    node::Node,
+    slot::SlotId,
+    value::Value,
+};
+
+/// An async stream of inbound messages, and somewhere to put outbound ones.
+/// The blocking equivalent is [`transport::Transport`](crate::transport::Transport).
+pub trait AsyncTransport<T: Value> {
+    /// Queues a message to go out to our peers.
+    /// This can't block, so transports have to buffer.
+    fn send(&mut self, message: Message<T>);
+    /// Polls for the next inbound message.
+    /// `None` means the transport is closed and nothing else will arrive.
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Message<T>>>;
+}
+
+/// Timeouts, one per slot.
+pub trait Timer {
+    /// Arranges for `poll_expired` to return `slot_id` once `after` has passed.
+    /// Scheduling a slot that's already scheduled replaces the old timeout.
+    fn schedule(&mut self, slot_id: SlotId, after: Duration);
+    /// Polls for the next slot whose timeout has passed.
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<SlotId>;
+}
+
+/// Owns a [`Node`], and moves messages and timeouts in and out of it.
+pub struct Driver<T: Value, R: AsyncTransport<T>, M: Timer> {
+    node:      Node<T>,
+    transport: R,
+    timer:     M,
+    /// How long the first timeout for a slot is.
+    /// Each timeout after that waits one `timeout` longer than the last, like SCP says.
+    timeout:   Duration,
+    /// How many times each slot's timer has been scheduled.
+    rounds:    HashMap<SlotId, u32>,
+}
+
+impl<T: Value, R: AsyncTransport<T>, M: Timer> Driver<T, R, M> {
+    pub fn new(node: Node<T>, transport: R, timer: M, timeout: Duration) -> Driver<T, R, M> {
+        return Driver { node, transport, timer, timeout, rounds: HashMap::new() };
+    }
+
+    pub fn node(&self) -> &Node<T> {
+        return &self.node;
+    }
+
+    pub fn node_mut(&mut self) -> &mut Node<T> {
+        return &mut self.node;
+    }
+
+    pub fn into_node(self) -> Node<T> {
+        return self.node;
+    }
+
+    /// A future that drives the node until `slot_id` is externalized,
+    /// resolving to the value it was externalized with.
+    /// Errors if the transport closes first.
+    /// The slot has to still be held in memory when it's externalized,
+    /// see [`Retention`](crate::storage::Retention).
+    pub fn externalized(&mut self, slot_id: SlotId) -> Externalized<'_, T, R, M> {
+        return Externalized { driver: self, slot_id };
+    }
+
+    /// A future that drives the node until the transport closes.
+    pub fn run(&mut self) -> Run<'_, T, R, M> {
+        return Run { driver: self };
+    }
+
+    fn value(&self, slot_id: SlotId) -> Option<T> {
+        return self.node.externalized(slot_id).map(|e| e.ballot.value.clone());
+    }
+
+    fn send(&mut self, outbound: Vec<Message<T>>) {
+        for message in outbound { self.transport.send(message); }
+    }
+
+    /// (Re)starts the timer for a slot, if it's still going.
+    fn schedule(&mut self, slot_id: SlotId) {
+        if !self.node.is_pending(slot_id) {
+            self.rounds.remove(&slot_id);
+            return;
+        }
+
+        let round = self.rounds.entry(slot_id).or_insert(0);
+        *round += 1;
+        self.timer.schedule(slot_id, self.timeout * *round);
+    }
+
+    /// Does one thing, if there's anything to do:
+    /// fires an expired timeout, or handles an inbound message.
+    /// Errors once the transport is closed.
+    // TODO: better error types
+    fn step(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ()>> {
+        if let Poll::Ready(slot_id) = self.timer.poll_expired(cx) {
+            let outbound = self.node.timeout(slot_id);
+            self.send(outbound);
+            self.schedule(slot_id);
+            return Poll::Ready(Ok(()));
+        }
+
+        let message = match self.transport.poll_recv(cx) {
+            Poll::Ready(Some(message)) => message,
+            Poll::Ready(None)          => { return Poll::Ready(Err(())); },
+            Poll::Pending              => { return Poll::Pending; },
+        };
+
+        let slot_id = message.slot_id;
+        // bad messages are already counted in the node's metrics
+        let outbound = self.node.handle(&message).unwrap_or_default();
+        self.send(outbound);
+
+        // start the clock on slots we haven't seen before
+        if !self.rounds.contains_key(&slot_id) { self.schedule(slot_id); }
+        return Poll::Ready(Ok(()));
+    }
+}
+
+/// See [`Driver::externalized`].
+pub struct Externalized<'d, T: Value, R: AsyncTransport<T>, M: Timer> {
+    driver:  &'d mut Driver<T, R, M>,
+    slot_id: SlotId,
+}
+
+impl<'d, T: Value, R: AsyncTransport<T>, M: Timer> Future for Externalized<'d, T, R, M> {
+    type Output = Result<T, ()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            if let Some(value) = this.driver.value(this.slot_id) { return Poll::Ready(Ok(value)); }
+
+            match this.driver.step(cx) {
+                Poll::Ready(Ok(()))  => (),
+                Poll::Ready(Err(())) => { return Poll::Ready(Err(())); },
+                Poll::Pending        => { return Poll::Pending; },
+            }
+        }
+    }
+}
+
+/// See [`Driver::run`].
+pub struct Run<'d, T: Value, R: AsyncTransport<T>, M: Timer> {
+    driver: &'d mut Driver<T, R, M>,
+}
+
+impl<'d, T: Value, R: AsyncTransport<T>, M: Timer> Future for Run<'d, T, R, M> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        loop {
+            match this.driver.step(cx) {
+                Poll::Ready(Ok(()))  => (),
+                Poll::Ready(Err(())) => { return Poll::Ready(()); },
+                Poll::Pending        => { return Poll::Pending; },
+            }
+        }
+    }
+}
+
+// Executor-free polling
+
+fn noop_raw_waker() -> RawWaker {
+    fn clone(_: *const ()) -> RawWaker { noop_raw_waker() }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    return RawWaker::new(std::ptr::null(), &VTABLE);
+}
+
+/// A waker that does nothing, for polling futures by hand.
+pub fn noop_waker() -> Waker {
+    // safe because the vtable functions don't touch the (null) data pointer
+    return unsafe { Waker::from_raw(noop_raw_waker()) };
+}
+
+/// Polls a future once, without an executor.
+/// Handy in tests, where the transport and timer are driven by hand:
+/// poll, push some messages in, poll again.
+pub fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    return Pin::new(future).poll(&mut cx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use crate::{
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        quorum::Quorum,
+        topic::{self, Topic},
+    };
+
+    /// Messages are pushed in and taken out by hand.
+    #[derive(Default)]
+    struct Mailbox {
+        inbound:  VecDeque<Message<DummyValue>>,
+        outbound: Vec<Message<DummyValue>>,
+        closed:   bool,
+    }
+
+    impl AsyncTransport<DummyValue> for Mailbox {
+        fn send(&mut self, message: Message<DummyValue>) { self.outbound.push(message); }
+
+        fn poll_recv(&mut self, _cx: &mut Context<'_>) -> Poll<Option<Message<DummyValue>>> {
+            match self.inbound.pop_front() {
+                Some(message)         => Poll::Ready(Some(message)),
+                None if self.closed   => Poll::Ready(None),
+                None                  => Poll::Pending,
+            }
+        }
+    }
+
+    /// Timeouts only expire when the test says so.
+    #[derive(Default)]
+    struct Clock {
+        scheduled: HashMap<SlotId, Duration>,
+        expired:   VecDeque<SlotId>,
+    }
+
+    impl Timer for Clock {
+        fn schedule(&mut self, slot_id: SlotId, after: Duration) { self.scheduled.insert(slot_id, after); }
+
+        fn poll_expired(&mut self, _cx: &mut Context<'_>) -> Poll<SlotId> {
+            match self.expired.pop_front() {
+                Some(slot_id) => Poll::Ready(slot_id),
+                None          => Poll::Pending,
+            }
+        }
+    }
+
+    fn decided() -> topic::Externalize<DummyValue> {
+        topic::Externalize { ballot: Ballot { number: 1, value: DummyValue(7) }, highest: 1 }
+    }
+
+    fn driver() -> Driver<DummyValue, Mailbox, Clock> {
+        let mut externalized = HashMap::new();
+        externalized.insert(SlotId::new(0), decided());
+        let node = Node::new(node_id("a"), Quorum::new(0, vec![]), externalized);
+        Driver::new(node, Mailbox::default(), Clock::default(), Duration::from_secs(1))
+    }
+
+    #[test]
+    fn already_externalized() {
+        let mut driver = driver();
+        let mut future = driver.externalized(SlotId::new(0));
+        assert_eq!(poll_once(&mut future), Poll::Ready(Ok(DummyValue(7))));
+    }
+
+    #[test]
+    fn answers_while_waiting() {
+        let mut driver = driver();
+
+        // nothing's happened yet
+        assert_eq!(poll_once(&mut driver.externalized(SlotId::new(1))), Poll::Pending);
+
+        // a peer that hasn't externalized slot 0 gets told about it
+        let topic = Topic::Commit(topic::Commit {
+            ballot: Ballot { number: 1, value: DummyValue(7) }, prepared: 1, highest: 1, lowest: 1,
+        });
+        let message = Message::new(node_id("b"), SlotId::new(0), Quorum::new(0, vec![]), topic, &mut 0);
+        driver.transport.inbound.push_back(message);
+        driver.timer.expired.push_back(SlotId::new(1));
+
+        assert_eq!(poll_once(&mut driver.externalized(SlotId::new(1))), Poll::Pending);
+        assert_eq!(driver.transport.outbound.len(), 1);
+        assert_eq!(driver.transport.outbound[0].topic, Topic::Externalize(decided()));
+
+        // slot 0 is done, so there's no timer to restart
+        assert!(driver.timer.scheduled.is_empty());
+
+        driver.transport.closed = true;
+        assert_eq!(poll_once(&mut driver.externalized(SlotId::new(1))), Poll::Ready(Err(())));
+        assert_eq!(poll_once(&mut driver.run()), Poll::Ready(()));
+    }
+}
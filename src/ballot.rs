@@ -2,12 +2,20 @@ use std::cmp::{PartialOrd, Ord, Ordering};
 
 use crate::value::Value;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Ballot<T: Value> {
     pub number: usize,
     pub value:  T,
 }
 
+impl<T: Value> Ballot<T> {
+    /// The zero ballot comes before every other ballot,
+    /// and means we haven't started balloting yet.
+    pub fn is_zero(&self) -> bool {
+        return self.number == 0;
+    }
+}
+
 impl<T: Value> PartialOrd for Ballot<T> { fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) } }
 
 impl<T: Value> Ord for Ballot<T> {
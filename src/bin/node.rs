@@ -0,0 +1,202 @@
+//! `drop-in-fba-node`, a reference validator.
+//! Run with `drop-in-fba-node <config.toml> [value ...]`,
+//! see [`config`](drop_in_fba::config) for the format.
+//! Runs a single node over TCP, flooding messages to its peers,
+//! keeps externalized slots on disk, and logs each slot as it's externalized.
+//! If it falls behind, e.g. after a restart, it asks its peers to help it catch up.
+//! Values are sets of strings, combined by taking their union.
+//! If any are given, the node proposes them for the slot after the last one it externalized.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    env,
+    fs,
+    path::PathBuf,
+    process,
+    time::{Duration, Instant},
+};
+
+use drop_in_fba::{
+    catchup::Proof,
+    codec::{self, Encode, Decode},
+    config::Config,
+    gossip::{Flood, Limits},
+    node::{Node, NodeId},
+    quorum::Quorum,
+    slot::SlotId,
+    storage::Storage,
+    transport::{self, Tcp, Transport},
+    value::Value,
+};
+
+/// How long to wait for a message before checking on timers.
+const TICK: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Strings(BTreeSet<String>);
+
+impl Value for Strings {
+    fn combine(this: Self, that: Self, _slot_id: SlotId) -> Self {
+        return Strings(this.0.union(&that.0).cloned().collect());
+    }
+}
+
+impl Encode for Strings {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.iter().cloned().collect::<Vec<String>>().encode(out);
+    }
+}
+
+impl Decode for Strings {
+    fn decode(input: &mut &[u8]) -> Result<Strings, ()> {
+        return Ok(Strings(Vec::<String>::decode(input)?.into_iter().collect()));
+    }
+}
+
+/// Keeps each externalized slot in its own file, named after the slot.
+struct Files {
+    dir: PathBuf,
+}
+
+impl Files {
+    fn path(&self, slot_id: SlotId) -> PathBuf {
+        return self.dir.join(format!("{}.proof", slot_id.number()));
+    }
+
+    /// Everything we externalized last time we ran,
+    /// skipping anything that doesn't prove itself to `node_id` with `quorum`.
+    fn recover(&self, node_id: &NodeId, quorum: &Quorum<Strings>) -> Vec<Proof<Strings>> {
+        let mut proofs = vec![];
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_)      => { return proofs; },
+        };
+
+        for entry in entries.flatten() {
+            let proof = fs::read(entry.path()).ok()
+                .and_then(|bytes| codec::from_bytes::<Proof<Strings>>(&bytes).ok());
+            match proof {
+                Some(proof) if proof.verify(node_id, quorum).is_ok() => proofs.push(proof),
+                Some(_) => eprintln!("skipping unproven {}", entry.path().display()),
+                None    => eprintln!("skipping unreadable {}", entry.path().display()),
+            }
+        }
+        return proofs;
+    }
+}
+
+impl Storage<Strings> for Files {
+    fn store(&mut self, proof: Proof<Strings>) {
+        if let Err(error) = fs::write(self.path(proof.slot_id), codec::to_bytes(&proof)) {
+            eprintln!("couldn't store slot {}: {}", proof.slot_id.number(), error);
+        }
+    }
+
+    fn load(&self, slot_id: SlotId) -> Option<Proof<Strings>> {
+        let bytes = fs::read(self.path(slot_id)).ok()?;
+        return codec::from_bytes(&bytes).ok();
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = match args.next() {
+        Some(path) => path,
+        None       => {
+            eprintln!("usage: drop-in-fba-node <config.toml> [value ...]");
+            process::exit(2);
+        },
+    };
+    let proposal = Strings(args.collect());
+
+    let input = fs::read_to_string(&path)
+        .unwrap_or_else(|e| fail(format!("couldn't read {}: {}", path, e)));
+    let config = Config::<Strings>::parse(&input)
+        .unwrap_or_else(|()| fail(format!("{} isn't a valid config", path)));
+
+    fs::create_dir_all(&config.storage)
+        .unwrap_or_else(|e| fail(format!("couldn't create {}: {}", config.storage.display(), e)));
+    let files = Files { dir: config.storage.clone() };
+    let proofs = files.recover(&config.node_id, &config.quorum);
+
+    let mut node = Node::new(config.node_id.clone(), config.quorum.clone(), HashMap::new());
+    node.retention = config.retention;
+    node.set_storage(Box::new(files));
+    if node.restore(proofs).is_err() {
+        fail(format!("couldn't restore from {}", config.storage.display()));
+    }
+
+    let tcp = Tcp::bind(config.node_id.clone(), config.listen, config.peers.clone())
+        .unwrap_or_else(|e| fail(format!("couldn't listen on {}: {}", config.listen, e)));
+    println!("{} listening on {}", config.node_id.as_str(), tcp.local_addr());
+    let mut transport = Flood::new(tcp, Limits::default());
+
+    if !proposal.0.is_empty() {
+        let slot_id = SlotId::new(node.latest_externalized().map_or(0, |s| s.number() + 1));
+        for outbound in node.propose(slot_id, proposal) { let _ = transport.broadcast(&outbound); }
+    }
+
+    // when each pending slot's timer fires, and how many times it has
+    let mut timers: HashMap<SlotId, (Instant, u32)> = HashMap::new();
+    let mut reported = node.latest_externalized();
+    // when we last asked for help catching up, and who we ask next
+    let mut asked: Option<Instant> = None;
+    let mut peers = transport.peers();
+    peers.sort();
+    let mut next = 0;
+
+    loop {
+        if transport::step(&mut node, &mut transport, TICK).is_err() {
+            fail("transport closed".to_string());
+        }
+
+        // start timers for new slots, and fire any that are due
+        let now = Instant::now();
+        for slot_id in node.pending_slot_ids() {
+            let (due, round) = *timers.entry(slot_id).or_insert((now + config.timeout, 1));
+            if due > now { continue; }
+
+            for outbound in node.timeout(slot_id) { let _ = transport.broadcast(&outbound); }
+            // like SCP says, each timeout is a little longer than the last
+            timers.insert(slot_id, (now + config.timeout * (round + 1), round + 1));
+        }
+        timers.retain(|slot_id, _| node.is_pending(*slot_id));
+
+        // help out peers that have fallen behind, and get help if we have
+        let tcp = transport.inner_mut();
+        tcp.answer_catch_ups(&mut node);
+        while let Some(response) = tcp.catch_up_response() {
+            if node.handle_catch_up_response(&response).is_err() {
+                eprintln!("rejected catch-up response from {}", response.sender.as_str());
+            }
+        }
+        let due = !peers.is_empty() && asked.is_none_or(|at| now >= at + config.timeout);
+        if due {
+            if let Some(request) = node.catch_up() {
+                // a different peer each time, in case the last one couldn't help
+                let _ = tcp.request_catch_up(&peers[next % peers.len()], &request);
+                next += 1;
+                asked = Some(now);
+            }
+        }
+
+        let latest = node.latest_externalized();
+        if latest <= reported { continue; }
+
+        let from = reported.map(|s| s.number() + 1).unwrap_or(0);
+        // TODO: safe to unwrap? it's greater than `reported`
+        let to = latest.unwrap();
+        for number in from..=to.number() {
+            if let Some(externalize) = node.externalized(SlotId::new(number)) {
+                println!("externalized slot {}: {:?}", number, externalize.ballot.value.0);
+            }
+        }
+        transport.forget_before(to);
+        reported = latest;
+    }
+}
@@ -0,0 +1,129 @@
+//! `fba-quorum`, checks a network's quorum sets before they're deployed.
+//! Run with `fba-quorum <network.toml|network.json>`,
+//! see [`config`](drop_in_fba::config) for the format, and [`analysis`](drop_in_fba::analysis)
+//! for what's reported.
+//! Exits with 1 if the network is unsafe: if there are structural problems,
+//! if quorum intersection doesn't hold, or if the network is too large to tell.
+//!
+//! With `--dot`, prints the network as a Graphviz graph instead, see [`dot`](drop_in_fba::dot).
+//! Nodes are coloured by their `organisation`,
+//! and `--highlight a,b,c` picks nodes to draw in bold, like a quorum or blocking set.
+
+use std::{
+    env,
+    fs,
+    process,
+};
+
+use drop_in_fba::{
+    analysis::{Network, MAX_SEARCH},
+    config,
+    dot::{self, Options},
+    json,
+    node::NodeId,
+    slot::SlotId,
+    toml,
+    value::Value,
+};
+
+/// Quorum sets don't care about values, but have to be for some value.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Any;
+
+impl Value for Any {
+    fn combine(this: Self, _that: Self, _slot_id: SlotId) -> Self {
+        return this;
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
+
+fn show(nodes: &[NodeId]) -> String {
+    let names = nodes.iter().map(|n| n.as_str()).collect::<Vec<&str>>();
+    return format!("{{{}}}", names.join(", "));
+}
+
+fn show_all(sets: &[Vec<NodeId>]) -> String {
+    return sets.iter().map(|s| show(s)).collect::<Vec<String>>().join(" ");
+}
+
+const USAGE: &str = "usage: fba-quorum [--dot [--highlight a,b,c]] <network.toml|network.json>";
+
+fn main() {
+    let mut path = None;
+    let mut draw = false;
+    let mut options = Options::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dot"       => { draw = true; },
+            "--highlight" => {
+                let nodes = args.next().unwrap_or_else(|| fail(USAGE.to_string()));
+                options.highlight.extend(nodes.split(',').map(|n| NodeId::new(n.to_string())));
+            },
+            _ if path.is_none() => { path = Some(arg); },
+            _ => fail(USAGE.to_string()),
+        }
+    }
+    let path = path.unwrap_or_else(|| fail(USAGE.to_string()));
+
+    let input = fs::read_to_string(&path)
+        .unwrap_or_else(|e| fail(format!("couldn't read {}: {}", path, e)));
+    let document = if path.ends_with(".json") {
+        json::parse(&input).and_then(|j| toml::from_json(&j))
+    } else {
+        toml::parse(&input)
+    };
+    let (quorums, organisations) = document
+        .and_then(|d| Ok((config::network::<Any>(&d)?, config::organisations(&d)?)))
+        .unwrap_or_else(|()| fail(format!("{} isn't a valid network description", path)));
+
+    if draw {
+        options.organisations = organisations;
+        print!("{}", dot::render(&quorums, &options));
+        return;
+    }
+
+    let network = Network::new(quorums);
+    let mut safe = true;
+
+    let problems = network.problems();
+    println!("problems: {}", problems.len());
+    for problem in problems.iter() { println!("  {}", problem); }
+    if !problems.is_empty() { safe = false; }
+
+    match network.disjoint_quorums() {
+        Ok(None)         => println!("quorum intersection: holds"),
+        Ok(Some((a, b))) => {
+            println!("quorum intersection: broken, {} and {} are disjoint quorums", show(&a), show(&b));
+            safe = false;
+        },
+        Err(()) => {
+            println!("quorum intersection: unknown, more than {} nodes are in quorums", MAX_SEARCH);
+            safe = false;
+        },
+    }
+
+    // these are only errors if the network is too large, which we've already said
+    if let Ok(quorums) = network.minimal_quorums() {
+        println!("minimal quorums: {}", show_all(&quorums));
+    }
+    if let Ok(blocking) = network.minimal_blocking_sets() {
+        println!("minimal blocking sets: {}", show_all(&blocking));
+    }
+    if let Ok(splitting) = network.minimal_splitting_sets() {
+        println!("minimal splitting sets: {}", show_all(&splitting));
+    }
+
+    println!("outside any quorum: {}", show(&network.outside_quorums()));
+    println!("transitive closures:");
+    for node_id in network.nodes() {
+        println!("  {}: {}", node_id.as_str(), show(&network.closure(&node_id)));
+    }
+
+    if !safe { process::exit(1); }
+}
@@ -0,0 +1,91 @@
+//! `fba-sim`, plays out a scenario on a simulated network of slots.
+//! Run with `fba-sim <scenario.toml> [seed]`, see [`sim`](drop_in_fba::sim) for the format.
+//! A seed on the command line replaces the one in the scenario.
+//! Prints what happened when, then how long each node took to externalize.
+//! Values are strings, combined by picking the larger one.
+//! Exits with 1 if any honest node that wasn't crashed at the end didn't externalize,
+//! see [`Outcome::stalled`](drop_in_fba::sim::Outcome::stalled).
+
+use std::{
+    env,
+    fs,
+    process,
+};
+
+use drop_in_fba::{
+    sim::{self, Event, Fault, Scenario},
+    slot::{SlotId, Standalone},
+    toml,
+    value::Value,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Name(String);
+
+impl Value for Name {
+    fn combine(this: Self, that: Self, _slot_id: SlotId) -> Self {
+        return if this > that { this } else { that };
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
+
+fn show(event: &Event<Name>) -> String {
+    return match event {
+        Event::Fault(Fault::Partition(groups)) => {
+            let groups = groups.iter()
+                .map(|g| format!("{{{}}}", g.iter().map(|n| n.as_str()).collect::<Vec<&str>>().join(", ")))
+                .collect::<Vec<String>>();
+            format!("partition {}", groups.join(" "))
+        },
+        Event::Fault(Fault::Heal)          => "heal".to_string(),
+        Event::Fault(Fault::Crash(n))      => format!("{} crashed", n.as_str()),
+        Event::Fault(Fault::Restart(n))    => format!("{} restarted", n.as_str()),
+        Event::Timeout { node_id, round }  => format!("{} timed out, round {}", node_id.as_str(), round),
+        Event::Phase { node_id, phase }    => format!("{} is in {:?}", node_id.as_str(), phase),
+        Event::Externalized { node_id, value } => format!("{} externalized {}", node_id.as_str(), value.0),
+    };
+}
+
+const USAGE: &str = "usage: fba-sim <scenario.toml> [seed]";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = args.next().unwrap_or_else(|| fail(USAGE.to_string()));
+    let seed = args.next().map(|s| s.parse::<u64>().unwrap_or_else(|_| fail(USAGE.to_string())));
+    if args.next().is_some() { fail(USAGE.to_string()); }
+
+    let input = fs::read_to_string(&path)
+        .unwrap_or_else(|e| fail(format!("couldn't read {}: {}", path, e)));
+    let mut scenario = toml::parse(&input)
+        .and_then(|t| Scenario::from_toml(&t, |v| Name(v.to_string())))
+        .unwrap_or_else(|()| fail(format!("{} isn't a valid scenario", path)));
+    if let Some(seed) = seed { scenario.seed = seed; }
+
+    let outcome = sim::simulate(&scenario, |node_id| {
+        Standalone::new(node_id.clone(), scenario.quorums[node_id].clone(), SlotId::new(0))
+    });
+
+    println!("slot 0, seed {}:", scenario.seed);
+    for entry in outcome.timeline.iter() {
+        println!("  {:>6}  {}", entry.at, show(&entry.event));
+    }
+
+    let stalled = outcome.stalled(&scenario, usize::MAX);
+    println!("delivered {} messages, dropped {}, ended at {}", outcome.delivered, outcome.dropped, outcome.ended);
+    for (node_id, decision) in outcome.decisions.iter() {
+        match decision {
+            Some(d) => println!(
+                "  {}: {} at {}, after {} rounds and {} messages",
+                node_id.as_str(), d.value.0, d.at, d.rounds, d.messages,
+            ),
+            None if stalled.contains(node_id) => println!("  {}: didn't externalize", node_id.as_str()),
+            None => println!("  {}: crashed or faulty", node_id.as_str()),
+        }
+    }
+
+    if !stalled.is_empty() { process::exit(1); }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+
+use crate::{
+    node::NodeId,
+    message::Message,
+    quorum::Quorum,
+    slot::SlotId,
+    topic::{self, Topic},
+    value::Value,
+};
+
+// TODO: these aren't statements, so they don't go through `Message`.
+// maybe they should share an envelope at some point?
+
+/// Sent by a node that has fallen behind,
+/// asking a peer for the decided values of the slots
+/// after `after` (or from the very start, if `None`) up to and including `to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub sender: NodeId,
+    pub after:  Option<SlotId>,
+    pub to:     SlotId,
+}
+
+/// The most proofs a [`Response`] carries.
+/// A peer that's further behind than this gets the oldest ones,
+/// and asks again once it's adopted them.
+pub const MAX_PROOFS: usize = 256;
+
+/// The reply to a [`Request`].
+/// Contains a [`Proof`] for every requested slot the peer has externalized,
+/// oldest first, up to [`MAX_PROOFS`] of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response<T: Value> {
+    pub sender: NodeId,
+    pub proofs: Vec<Proof<T>>,
+}
+
+/// A decided value, along with the [`Topic::Externalize`] messages
+/// that prove it was decided.
+/// We never adopt a value on the word of a single peer;
+/// the messages have to form a quorum from our point of view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof<T: Value> {
+    pub slot_id:     SlotId,
+    pub externalize: topic::Externalize<T>,
+    pub messages:    Vec<Message<T>>,
+}
+
+impl<T: Value> Proof<T> {
+    /// Checks that every message in the proof externalizes the proven value
+    /// for the proven slot, and that the senders satisfy the quorum set `quorum`
+    /// of the node `node_id` checking it.
+    /// Only our own quorum set counts, not the ones the senders declare,
+    /// and we don't count ourselves: we didn't see it happen.
+    pub fn verify(&self, node_id: &NodeId, quorum: &Quorum<T>) -> Result<(), ()> {
+        let mut signers = HashSet::new();
+
+        for message in self.messages.iter() {
+            if message.slot_id != self.slot_id { return Err(()); }
+            message.valid()?;
+
+            match &message.topic {
+                Topic::Externalize(e) if e.ballot.value == self.externalize.ballot.value => (),
+                _ => { return Err(()); },
+            }
+
+            if message.sender != *node_id { signers.insert(&message.sender); }
+        }
+
+        // all the messages agree, so we just need to check who sent them.
+        if signers.is_empty() || !quorum.satisfied_by(&signers) { return Err(()); }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::{
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        node::Node,
+        predicate::FnPredicate,
+        quorum::Member,
+    };
+
+    /// Two of `a`, `b` and `c`.
+    fn quorum() -> Quorum<DummyValue> {
+        Quorum::new(2, ["a", "b", "c"].iter().map(|n| Member::Node(node_id(n))).collect())
+    }
+
+    fn externalize(value: usize) -> topic::Externalize<DummyValue> {
+        topic::Externalize { ballot: Ballot { number: 1, value: DummyValue(value) }, highest: 1 }
+    }
+
+    /// A proof that slot 1 externalized `value`, signed by each of `signers`,
+    /// who each declare `quorum` as their quorum set.
+    fn proof(value: usize, signers: &[&str], quorum: Quorum<DummyValue>) -> Proof<DummyValue> {
+        let messages = signers.iter()
+            .map(|s| Message::new(node_id(s), SlotId::new(1), quorum.clone(), Topic::Externalize(externalize(value)), &mut 0))
+            .collect();
+        Proof { slot_id: SlotId::new(1), externalize: externalize(value), messages }
+    }
+
+    #[test]
+    fn verify() {
+        assert_eq!(proof(1, &["b", "c"], quorum()).verify(&node_id("a"), &quorum()), Ok(()));
+        assert_eq!(proof(1, &["a", "b", "c"], quorum()).verify(&node_id("a"), &quorum()), Ok(()));
+
+        // we don't get to vouch for ourselves
+        assert_eq!(proof(1, &["a", "b"], quorum()).verify(&node_id("a"), &quorum()), Err(()));
+        assert_eq!(proof(1, &[], quorum()).verify(&node_id("a"), &quorum()), Err(()));
+
+        // everything has to be about the proven slot and value
+        let mut wrong_value = proof(1, &["b", "c"], quorum());
+        wrong_value.externalize = externalize(2);
+        assert_eq!(wrong_value.verify(&node_id("a"), &quorum()), Err(()));
+
+        let mut wrong_slot = proof(1, &["b", "c"], quorum());
+        wrong_slot.messages[0].slot_id = SlotId::new(2);
+        assert_eq!(wrong_slot.verify(&node_id("a"), &quorum()), Err(()));
+    }
+
+    #[test]
+    fn forged() {
+        // a lone faulty peer that says it only trusts itself
+        let alone = Quorum::new(1, vec![Member::Node(node_id("c"))]);
+        let forged = proof(2, &["c"], alone);
+        assert_eq!(forged.verify(&node_id("a"), &quorum()), Err(()));
+
+        let mut node = Node::new(node_id("a"), quorum(), HashMap::new());
+        let response = Response { sender: node_id("c"), proofs: vec![forged] };
+        assert_eq!(node.handle_catch_up_response(&response), Err(()));
+        assert!(node.externalized(SlotId::new(1)).is_none());
+
+        // a proof from enough honest peers is adopted
+        let response = Response { sender: node_id("b"), proofs: vec![proof(1, &["b", "c"], quorum())] };
+        assert_eq!(node.handle_catch_up_response(&response), Ok(vec![SlotId::new(1)]));
+        assert_eq!(node.externalized(SlotId::new(1)), Some(&externalize(1)));
+    }
+
+    #[test]
+    fn agrees_with_find_quorum() {
+        // two of `a`, `b`, and two of `c`, `d`, `e`
+        let inner = Quorum::new(2, ["c", "d", "e"].iter().map(|n| Member::Node(node_id(n))).collect());
+        let quorum = Quorum::new(2, vec![Member::Node(node_id("a")), Member::Node(node_id("b")), Member::Quorum(inner)]);
+        let names = ["a", "b", "c", "d", "e"];
+
+        // when everyone trusts the same nodes, and whoever's checking doesn't count,
+        // a proof holds up exactly when its signers are a quorum
+        for subset in 0..(1 << names.len()) {
+            let signers = names.iter().enumerate()
+                .filter(|(i, _)| subset & (1 << i) != 0)
+                .map(|(_, name)| *name)
+                .collect::<Vec<&str>>();
+            let proof = proof(1, &signers, quorum.clone());
+
+            let statements = proof.messages.iter().cloned().collect();
+            let (found, _) = quorum.find_quorum(node_id("z"), &statements, FnPredicate::new(|_| true));
+            assert_eq!(proof.verify(&node_id("z"), &quorum).is_ok(), !found.is_empty(), "{:?}", signers);
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! A bounded model checker for small networks of [`Standalone`](crate::slot::Standalone) slots
+//! (or anything else that implements [`Participant`]).
+//! Random simulation can miss rare interleavings,
+//! so for a handful of nodes and a tiny value domain
+//! we try every order messages could be delivered in,
+//! and every point a timeout could fire,
+//! up to a fixed number of steps.
+//! States we've seen before are skipped, by hash.
+//!
+//! The search is breadth first,
+//! so the first counterexample found is one of the shortest.
+//!
+//! Participants aren't cloned to branch the search;
+//! instead each state is rebuilt from scratch by replaying its trace.
+//! That's slow, but fine for the sizes this is meant for,
+//! and means participants only have to be deterministic.
+
+use std::{
+    fmt,
+    collections::{HashSet, VecDeque, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    ballot::Ballot,
+    message::Message,
+    node::NodeId,
+    slot::{Context, Phase, Standalone},
+    value::Value,
+};
+
+/// Something the model checker can drive.
+/// Participants must be deterministic:
+/// the same messages in the same order must lead to the same state.
+pub trait Participant<T: Value> {
+    fn node_id(&self) -> NodeId;
+    /// Votes for `value`, returning what to send to everyone else.
+    fn propose(&mut self, value: T) -> Vec<Message<T>>;
+    /// Handles an inbound message, returning what to send to everyone else.
+    fn handle(&mut self, message: Message<T>) -> Result<Vec<Message<T>>, ()>;
+    /// Called when a timer fires, returning what to send to everyone else.
+    fn timeout(&mut self) -> Vec<Message<T>>;
+    /// The bits of state the invariants look at.
+    fn observe(&self) -> Observation<T>;
+    /// Hashes everything that affects how the participant behaves from here on.
+    /// Two participants that hash the same are treated as the same state.
+    fn fingerprint<H: Hasher>(&self, state: &mut H);
+}
+
+/// What the invariants are checked against.
+/// Zero ballots are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation<T: Value> {
+    pub phase:        Phase,
+    pub externalized: Option<T>,
+    pub prepared_a:   Option<Ballot<T>>,
+    pub prepared_b:   Option<Ballot<T>>,
+    pub lowest:       usize,
+    pub highest:      usize,
+}
+
+impl<T: Value> Participant<T> for Standalone<T> {
+    fn node_id(&self) -> NodeId {
+        return self.node_id.clone();
+    }
+
+    fn propose(&mut self, value: T) -> Vec<Message<T>> {
+        let context = Context { node_id: &self.node_id, quorum: &self.quorum };
+        return self.slot.propose(context, value);
+    }
+
+    fn handle(&mut self, message: Message<T>) -> Result<Vec<Message<T>>, ()> {
+        let context = Context { node_id: &self.node_id, quorum: &self.quorum };
+        return self.slot.handle(context, message);
+    }
+
+    fn timeout(&mut self) -> Vec<Message<T>> {
+        let context = Context { node_id: &self.node_id, quorum: &self.quorum };
+        return self.slot.timeout(context);
+    }
+
+    fn observe(&self) -> Observation<T> {
+        let slot = &self.slot;
+        let externalized = match (slot.phase, &slot.lowest) {
+            (Phase::Externalize, Some(lowest)) => Some(lowest.value.clone()),
+            _                                  => None,
+        };
+        let number = |ballot: &Option<Ballot<T>>| ballot.as_ref().map_or(0, |b| b.number);
+
+        return Observation {
+            phase:        slot.phase,
+            externalized,
+            prepared_a:   slot.prepared_a.clone(),
+            prepared_b:   slot.prepared_b.clone(),
+            lowest:       number(&slot.lowest),
+            highest:      number(&slot.highest),
+        };
+    }
+
+    fn fingerprint<H: Hasher>(&self, state: &mut H) {
+        self.slot.fingerprint(state);
+    }
+}
+
+/// How far to search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    /// The most steps (deliveries and timeouts) in a single trace.
+    pub depth:    usize,
+    /// The most timeouts each participant can see in a single trace.
+    pub timeouts: usize,
+}
+
+/// A single step of a trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step<T: Value> {
+    Deliver { to: NodeId, message: Message<T> },
+    Timeout(NodeId),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation<T: Value> {
+    /// Two nodes externalized different values.
+    Disagreement { first: (NodeId, T), second: (NodeId, T) },
+    /// A node's `prepared_b` isn't below its `prepared_a`.
+    PreparedOutOfOrder { node_id: NodeId, prepared_a: Option<Ballot<T>>, prepared_b: Ballot<T> },
+    /// A node's `lowest` is above its `highest`.
+    LowestAboveHighest { node_id: NodeId, lowest: usize, highest: usize },
+}
+
+/// A trace of steps that leads to a [`Violation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample<T: Value> {
+    pub trace:     Vec<Step<T>>,
+    pub violation: Violation<T>,
+}
+
+impl<T: Value> fmt::Display for Counterexample<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:?}", self.violation)?;
+        writeln!(f, "after {} steps:", self.trace.len())?;
+        for (i, step) in self.trace.iter().enumerate() {
+            match step {
+                Step::Deliver { to, message } => writeln!(f, "{:>4}. {:?} <- {:?}", i + 1, to, message)?,
+                Step::Timeout(node_id)        => writeln!(f, "{:>4}. {:?} times out", i + 1, node_id)?,
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// How much of the state space was covered when no violation was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    /// Distinct states visited.
+    pub states:   usize,
+    /// Whether some trace was cut short by [`Bounds::depth`].
+    pub bounded:  bool,
+}
+
+// World
+
+/// Every participant, and every message that's been sent but not delivered.
+struct World<T: Value, P: Participant<T>> {
+    participants: Vec<P>,
+    in_flight:    Vec<(usize, Message<T>)>,
+    timeouts:     Vec<usize>,
+}
+
+impl<T: Value, P: Participant<T>> World<T, P> {
+    fn new(participants: Vec<P>, initial: &[Message<T>]) -> World<T, P> {
+        let timeouts = vec![0; participants.len()];
+        let mut world = World { participants, in_flight: vec![], timeouts };
+        world.broadcast(initial.to_vec());
+        return world;
+    }
+
+    /// Sends each message to every participant but its sender.
+    fn broadcast(&mut self, messages: Vec<Message<T>>) {
+        for message in messages {
+            for (i, participant) in self.participants.iter().enumerate() {
+                if participant.node_id() != message.sender {
+                    self.in_flight.push((i, message.clone()));
+                }
+            }
+        }
+    }
+
+    fn position(&self, node_id: &NodeId) -> Option<usize> {
+        return self.participants.iter().position(|p| &p.node_id() == node_id);
+    }
+
+    // TODO: better error types
+    fn apply(&mut self, step: &Step<T>) -> Result<(), ()> {
+        match step {
+            Step::Deliver { to, message } => {
+                let to = self.position(to).ok_or(())?;
+                let index = self.in_flight.iter()
+                    .position(|(i, m)| *i == to && m == message)
+                    .ok_or(())?;
+                let (_, message) = self.in_flight.remove(index);
+
+                // invalid messages are dropped, like they would be over the wire
+                let outbound = self.participants[to].handle(message).unwrap_or_default();
+                self.broadcast(outbound);
+            },
+            Step::Timeout(node_id) => {
+                let i = self.position(node_id).ok_or(())?;
+                self.timeouts[i] += 1;
+                let outbound = self.participants[i].timeout();
+                self.broadcast(outbound);
+            },
+        }
+
+        return Ok(());
+    }
+
+    /// Every step that can be taken from here.
+    /// Identical messages to the same participant lead to the same state,
+    /// so only one of them is returned.
+    fn steps(&self, bounds: &Bounds) -> Vec<Step<T>> {
+        let mut seen  = HashSet::new();
+        let mut steps = vec![];
+
+        for (i, message) in self.in_flight.iter() {
+            if seen.insert((*i, message)) {
+                let to = self.participants[*i].node_id();
+                steps.push(Step::Deliver { to, message: message.clone() });
+            }
+        }
+
+        for (i, participant) in self.participants.iter().enumerate() {
+            if self.timeouts[i] < bounds.timeouts {
+                steps.push(Step::Timeout(participant.node_id()));
+            }
+        }
+
+        return steps;
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        for participant in self.participants.iter() {
+            participant.fingerprint(&mut state);
+        }
+
+        // what's in flight is a multiset, so order doesn't matter
+        let mut in_flight = self.in_flight.iter().map(|entry| {
+            let mut hasher = DefaultHasher::new();
+            entry.hash(&mut hasher);
+            hasher.finish()
+        }).collect::<Vec<u64>>();
+        in_flight.sort();
+        in_flight.hash(&mut state);
+
+        self.timeouts.hash(&mut state);
+        return state.finish();
+    }
+
+    fn violation(&self) -> Option<Violation<T>> {
+        let mut decided: Option<(NodeId, T)> = None;
+
+        for participant in self.participants.iter() {
+            let node_id     = participant.node_id();
+            let observation = participant.observe();
+
+            if observation.lowest > observation.highest {
+                return Some(Violation::LowestAboveHighest {
+                    node_id,
+                    lowest:  observation.lowest,
+                    highest: observation.highest,
+                });
+            }
+
+            if let Some(prepared_b) = observation.prepared_b {
+                if Some(&prepared_b) >= observation.prepared_a.as_ref() {
+                    return Some(Violation::PreparedOutOfOrder {
+                        node_id,
+                        prepared_a: observation.prepared_a,
+                        prepared_b,
+                    });
+                }
+            }
+
+            if let Some(value) = observation.externalized {
+                match &decided {
+                    Some((first, decided_value)) if *decided_value != value => {
+                        return Some(Violation::Disagreement {
+                            first:  (first.clone(), decided_value.clone()),
+                            second: (node_id, value),
+                        });
+                    },
+                    Some(_) => (),
+                    None    => { decided = Some((node_id, value)); },
+                }
+            }
+        }
+
+        return None;
+    }
+}
+
+// Checker
+
+/// Explores every interleaving of a small network, up to some [`Bounds`].
+/// `build` makes a fresh set of participants,
+/// and `initial` is what's in flight before anyone does anything
+/// (e.g. each node nominating its own value).
+pub struct Checker<T: Value, P: Participant<T>, F: Fn() -> Vec<P>> {
+    build:   F,
+    initial: Vec<Message<T>>,
+    bounds:  Bounds,
+}
+
+impl<T: Value, P: Participant<T>, F: Fn() -> Vec<P>> Checker<T, P, F> {
+    pub fn new(build: F, initial: Vec<Message<T>>, bounds: Bounds) -> Checker<T, P, F> {
+        return Checker { build, initial, bounds };
+    }
+
+    fn replay(&self, trace: &[Step<T>]) -> World<T, P> {
+        let mut world = World::new((self.build)(), &self.initial);
+        for step in trace {
+            // the trace was recorded from this exact world, so the step must be there
+            world.apply(step).expect("participants aren't deterministic");
+        }
+        return world;
+    }
+
+    /// Runs the search,
+    /// returning the shortest trace that breaks an invariant if there is one.
+    pub fn run(&self) -> Result<Report, Counterexample<T>> {
+        let mut visited = HashSet::new();
+        let mut queue   = VecDeque::new();
+        let mut bounded = false;
+
+        let world = self.replay(&[]);
+        if let Some(violation) = world.violation() {
+            return Err(Counterexample { trace: vec![], violation });
+        }
+        visited.insert(world.fingerprint());
+        queue.push_back(vec![]);
+
+        while let Some(trace) = queue.pop_front() {
+            let steps = self.replay(&trace).steps(&self.bounds);
+            if steps.is_empty() { continue; }
+            if trace.len() >= self.bounds.depth { bounded = true; continue; }
+
+            for step in steps {
+                let mut next = trace.clone();
+                next.push(step);
+
+                let world = self.replay(&next);
+                if !visited.insert(world.fingerprint()) { continue; }
+
+                if let Some(violation) = world.violation() {
+                    return Err(Counterexample { trace: next, violation });
+                }
+                queue.push_back(next);
+            }
+        }
+
+        return Ok(Report { states: visited.len(), bounded });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{node_id, node_ids, DummyValue, Toy},
+        quorum::{Member, Quorum},
+        slot::SlotId,
+    };
+
+    fn network(wait_for: usize) -> Vec<Toy> {
+        node_ids(&["a", "b", "c"]).iter().enumerate()
+            .map(|(i, n)| Toy::quiet(n, DummyValue(i + 1), wait_for))
+            .collect()
+    }
+
+    fn proposals() -> Vec<Message<DummyValue>> {
+        network(0).iter_mut().map(|toy| toy.nomination()).collect()
+    }
+
+    /// Real slots, each having proposed its own value.
+    fn slots() -> (Vec<Standalone<DummyValue>>, Vec<Message<DummyValue>>) {
+        let quorum = Quorum::new(2, ["a", "b", "c"].iter().map(|n| Member::Node(node_id(n))).collect());
+        let mut proposed = vec![];
+        let slots = node_ids(&["a", "b", "c"]).into_iter().enumerate().map(|(i, n)| {
+            let mut slot = Standalone::new(n, quorum.clone(), SlotId::new(0));
+            proposed.extend(slot.propose(DummyValue(i)));
+            slot
+        }).collect();
+        (slots, proposed)
+    }
+
+    #[test]
+    fn safe() {
+        let bounds = Bounds { depth: 10, timeouts: 1 };
+        let report = Checker::new(|| network(2), proposals(), bounds).run().unwrap();
+        assert!(report.states > 1);
+        assert!(!report.bounded);
+    }
+
+    #[test]
+    fn counterexample() {
+        // deciding after hearing from one peer isn't enough
+        let bounds = Bounds { depth: 10, timeouts: 0 };
+        let counterexample = Checker::new(|| network(1), proposals(), bounds).run().unwrap_err();
+
+        assert_eq!(counterexample.trace.len(), 2);
+        match counterexample.violation {
+            Violation::Disagreement { .. } => (),
+            other => panic!("unexpected violation {:?}", other),
+        }
+    }
+
+    #[test]
+    fn slots_are_safe() {
+        let bounds = Bounds { depth: 6, timeouts: 0 };
+        let report = Checker::new(|| slots().0, slots().1, bounds).run().unwrap();
+        assert!(report.states > 1);
+    }
+
+    #[test]
+    fn slots_are_safe_with_timeouts() {
+        // timeouts bump the ballot and rebroadcast, which is where things get interesting
+        let bounds = Bounds { depth: 4, timeouts: 1 };
+        let report = Checker::new(|| slots().0, slots().1, bounds).run().unwrap();
+
+        // and they do get tried
+        let without = Bounds { timeouts: 0, ..bounds };
+        assert!(report.states > Checker::new(|| slots().0, slots().1, without).run().unwrap().states);
+    }
+}
@@ -0,0 +1,219 @@
+//! Configuring a node from a [`toml`](crate::toml) file.
+//! A config looks like:
+//!
+//! ```toml
+//! id      = "a"
+//! listen  = "127.0.0.1:7000"
+//! storage = "data/a"
+//! timeout_ms = 1000           # optional, how long the first ballot timeout is
+//!
+//! [quorum]
+//! threshold = 2
+//! nodes     = ["a", "b"]
+//! inner     = [{ threshold = 1, nodes = ["c", "d"] }]
+//!
+//! [peers]                     # who we're directly connected to
+//! b = "127.0.0.1:7001"
+//! c = "127.0.0.1:7002"
+//!
+//! [retention]                 # optional, see `Retention`
+//! externalized = 128
+//! pending      = 16
+//! ahead        = 16
+//! ```
+//!
+//! Quorum sets nest through `inner`, which can also be written as `[[quorum.inner]]` tables.
+//!
+//! A whole network, for analysis, is just a quorum set per node, keyed by node id,
+//! in either TOML or JSON:
+//!
+//! ```toml
+//! [a]
+//! threshold = 2
+//! nodes     = ["a", "b", "c"]
+//!
+//! [b]
+//! threshold    = 2
+//! nodes        = ["a", "b", "c"]
+//! organisation = "acme"       # optional, used when drawing the network
+//! ```
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{
+    node::NodeId,
+    quorum::{Member, Quorum},
+    storage::Retention,
+    toml::{self, Toml},
+    value::Value,
+};
+
+// TODO: better error types
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config<T: Value> {
+    pub node_id:   NodeId,
+    pub quorum:    Quorum<T>,
+    pub listen:    SocketAddr,
+    pub peers:     HashMap<NodeId, SocketAddr>,
+    /// A directory to keep externalized slots in.
+    pub storage:   PathBuf,
+    pub retention: Retention,
+    pub timeout:   Duration,
+}
+
+fn string(toml: &Toml, key: &str) -> Result<String, ()> {
+    return Ok(toml.get(key).and_then(Toml::as_str).ok_or(())?.to_string());
+}
+
+fn count(toml: &Toml) -> Result<usize, ()> {
+    let n = toml.as_integer().ok_or(())?;
+    return if n < 0 { Err(()) } else { Ok(n as usize) };
+}
+
+fn address(toml: &Toml) -> Result<SocketAddr, ()> {
+    return toml.as_str().ok_or(())?.parse().map_err(|_| ());
+}
+
+/// Reads a quorum set out of a table with a `threshold`,
+/// and optionally some `nodes` and `inner` sets.
+/// The quorum set isn't checked to be [`valid`](Quorum::valid).
+pub fn quorum<T: Value>(toml: &Toml) -> Result<Quorum<T>, ()> {
+    let threshold = count(toml.get("threshold").ok_or(())?)?;
+    let mut members = vec![];
+
+    if let Some(nodes) = toml.get("nodes") {
+        for node in nodes.as_array().ok_or(())?.iter() {
+            members.push(Member::Node(NodeId::new(node.as_str().ok_or(())?.to_string())));
+        }
+    }
+
+    if let Some(inner) = toml.get("inner") {
+        for set in inner.as_array().ok_or(())?.iter() {
+            members.push(Member::Quorum(quorum(set)?));
+        }
+    }
+
+    return Ok(Quorum::new(threshold, members));
+}
+
+/// Reads a network description, a quorum set for each node.
+/// The quorum sets aren't checked to be [`valid`](Quorum::valid).
+pub fn network<T: Value>(toml: &Toml) -> Result<HashMap<NodeId, Quorum<T>>, ()> {
+    let mut network = HashMap::new();
+    for (node_id, set) in toml.as_table().ok_or(())?.iter() {
+        network.insert(NodeId::new(node_id.clone()), quorum(set)?);
+    }
+    return Ok(network);
+}
+
+/// Reads who runs each node out of a network description,
+/// for the nodes that say.
+pub fn organisations(toml: &Toml) -> Result<HashMap<NodeId, String>, ()> {
+    let mut organisations = HashMap::new();
+    for (node_id, set) in toml.as_table().ok_or(())?.iter() {
+        if let Some(organisation) = set.get("organisation") {
+            organisations.insert(NodeId::new(node_id.clone()), organisation.as_str().ok_or(())?.to_string());
+        }
+    }
+    return Ok(organisations);
+}
+
+impl<T: Value> Config<T> {
+    pub fn parse(input: &str) -> Result<Config<T>, ()> {
+        return Config::from_toml(&toml::parse(input)?);
+    }
+
+    pub fn from_toml(toml: &Toml) -> Result<Config<T>, ()> {
+        let quorum = quorum(toml.get("quorum").ok_or(())?)?;
+        quorum.valid()?;
+
+        let mut peers = HashMap::new();
+        if let Some(table) = toml.get("peers") {
+            for (node_id, addr) in table.as_table().ok_or(())?.iter() {
+                peers.insert(NodeId::new(node_id.clone()), address(addr)?);
+            }
+        }
+
+        let mut retention = Retention::default();
+        if let Some(table) = toml.get("retention") {
+            if let Some(n) = table.get("externalized") { retention.externalized = count(n)?; }
+            if let Some(n) = table.get("pending")      { retention.pending      = count(n)?; }
+            if let Some(n) = table.get("ahead")        { retention.ahead        = count(n)?; }
+        }
+
+        let timeout = match toml.get("timeout_ms") {
+            Some(ms) => Duration::from_millis(count(ms)? as u64),
+            None     => Duration::from_secs(1),
+        };
+
+        return Ok(Config {
+            node_id: NodeId::new(string(toml, "id")?),
+            quorum,
+            listen:  address(toml.get("listen").ok_or(())?)?,
+            peers,
+            storage: PathBuf::from(string(toml, "storage")?),
+            retention,
+            timeout,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{node_id, DummyValue};
+
+    fn node(name: &str) -> Member<DummyValue> {
+        Member::Node(node_id(name))
+    }
+
+    #[test]
+    fn parse() {
+        let config = Config::<DummyValue>::parse(r#"
+            id      = "a"
+            listen  = "127.0.0.1:7000"
+            storage = "data/a"
+
+            [quorum]
+            threshold = 2
+            nodes     = ["a", "b"]
+
+            [[quorum.inner]]
+            threshold = 1
+            nodes     = ["c", "d"]
+
+            [peers]
+            b = "127.0.0.1:7001"
+
+            [retention]
+            externalized = 128
+        "#).unwrap();
+
+        assert_eq!(config.node_id, node_id("a"));
+        assert_eq!(config.quorum, Quorum::new(2, vec![
+            node("a"),
+            node("b"),
+            Member::Quorum(Quorum::new(1, vec![node("c"), node("d")])),
+        ]));
+        assert_eq!(config.peers.len(), 1);
+        assert_eq!(config.retention.externalized, 128);
+        assert_eq!(config.retention.pending, usize::MAX);
+        assert_eq!(config.retention.ahead, usize::MAX);
+        assert_eq!(config.timeout, Duration::from_secs(1));
+
+        // a threshold that can't be met
+        let unmeetable = r#"
+            id = "a"
+            listen = "127.0.0.1:7000"
+            storage = "data/a"
+            quorum = { threshold = 3, nodes = ["a", "b"] }
+        "#;
+        assert_eq!(Config::<DummyValue>::parse(unmeetable), Err(()));
+    }
+}
@@ -0,0 +1,153 @@
+//! Drawing a network of quorum sets with Graphviz.
+//! [`render`] writes a DOT graph with an ellipse per node, labelled with its threshold,
+//! and an edge from each node to every member of its quorum set.
+//! Inner sets are boxes labelled with their thresholds, with edges to their own members.
+//! Pipe the output through `dot -Tsvg` to get a picture.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use crate::{
+    node::NodeId,
+    quorum::{Member, Quorum},
+    value::Value,
+};
+
+/// How to draw a network.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Nodes to draw in bold red, e.g. a quorum or a blocking set.
+    /// Edges between two of them are drawn in bold red too.
+    pub highlight:     HashSet<NodeId>,
+    /// The organisation that runs each node.
+    /// Nodes run by the same organisation are filled with the same colour.
+    pub organisations: HashMap<NodeId, String>,
+}
+
+/// The number of colours in the `set312` Graphviz colour scheme.
+const COLOURS: usize = 12;
+
+fn escape(id: &str) -> String {
+    return id.replace('\\', "\\\\").replace('"', "\\\"");
+}
+
+fn quote(id: &str) -> String {
+    return format!("\"{}\"", escape(id));
+}
+
+fn label<T: Value>(quorum: &Quorum<T>) -> String {
+    return format!("{} of {}", quorum.threshold(), quorum.members().len());
+}
+
+/// Draws the edges from `from` to each member of `quorum`,
+/// and the boxes for its inner sets, named after `from`.
+fn members<T: Value>(out: &mut String, from: &str, highlighted: bool, quorum: &Quorum<T>, options: &Options) {
+    for (i, member) in quorum.members().iter().enumerate() {
+        match member {
+            Member::Node(n) => {
+                let bold = highlighted && options.highlight.contains(n);
+                let style = if bold { " [color=red, penwidth=2]" } else { "" };
+                let _ = writeln!(out, "    {} -> {}{};", quote(from), quote(n.as_str()), style);
+            },
+            Member::Quorum(q) => {
+                let set = format!("{}/{}", from, i);
+                let _ = writeln!(out, "    {} [shape=box, label={}];", quote(&set), quote(&label(q)));
+                let _ = writeln!(out, "    {} -> {};", quote(from), quote(&set));
+                // sets aren't nodes, so edges through them stay highlighted
+                members(out, &set, highlighted, q, options);
+            },
+        }
+    }
+}
+
+/// Renders every node's quorum set as a DOT graph, see the module docs.
+/// Nodes only mentioned in quorum sets are drawn dashed.
+pub fn render<T: Value>(quorums: &HashMap<NodeId, Quorum<T>>, options: &Options) -> String {
+    // sorted, so the same network always draws the same way
+    let mut node_ids = quorums.keys().collect::<Vec<&NodeId>>();
+    node_ids.sort();
+
+    let mut mentioned = quorums.values()
+        .flat_map(|q| q.nodes())
+        .filter(|n| !quorums.contains_key(*n))
+        .collect::<Vec<&NodeId>>();
+    mentioned.sort();
+    mentioned.dedup();
+
+    let mut organisations = options.organisations.values().collect::<Vec<&String>>();
+    organisations.sort();
+    organisations.dedup();
+
+    let mut out = String::new();
+    out.push_str("digraph quorums {\n");
+    out.push_str("    node [colorscheme=set312];\n");
+
+    for node_id in node_ids.iter().chain(mentioned.iter()) {
+        let mut attributes = vec![];
+        match quorums.get(*node_id) {
+            Some(q) => attributes.push(format!("label=\"{}\\n{}\"", escape(node_id.as_str()), label(q))),
+            None    => attributes.push("style=dashed".to_string()),
+        }
+
+        if let Some(organisation) = options.organisations.get(*node_id) {
+            // TODO: safe to unwrap? every organisation was collected above
+            let colour = organisations.iter().position(|o| *o == organisation).unwrap() % COLOURS + 1;
+            let style = if quorums.contains_key(*node_id) { "filled" } else { "\"filled,dashed\"" };
+            attributes.retain(|a| !a.starts_with("style="));
+            attributes.push(format!("style={}, fillcolor={}", style, colour));
+        }
+
+        if options.highlight.contains(*node_id) {
+            attributes.push("color=red, penwidth=2".to_string());
+        }
+
+        let _ = writeln!(out, "    {} [{}];", quote(node_id.as_str()), attributes.join(", "));
+    }
+
+    for node_id in node_ids {
+        let highlighted = options.highlight.contains(node_id);
+        members(&mut out, node_id.as_str(), highlighted, &quorums[node_id], options);
+    }
+
+    out.push_str("}\n");
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{node_id, DummyValue};
+
+    #[test]
+    fn render_network() {
+        let mut quorums = HashMap::new();
+        quorums.insert(node_id("a"), Quorum::<DummyValue>::new(2, vec![
+            Member::Node(node_id("a")),
+            Member::Quorum(Quorum::new(1, vec![Member::Node(node_id("b")), Member::Node(node_id("c"))])),
+        ]));
+        quorums.insert(node_id("b"), Quorum::new(1, vec![Member::Node(node_id("a"))]));
+
+        let mut options = Options::default();
+        options.highlight.insert(node_id("a"));
+        options.highlight.insert(node_id("b"));
+        options.organisations.insert(node_id("b"), "org".to_string());
+
+        assert_eq!(render(&quorums, &options), [
+            "digraph quorums {",
+            "    node [colorscheme=set312];",
+            "    \"a\" [label=\"a\\n2 of 2\", color=red, penwidth=2];",
+            "    \"b\" [label=\"b\\n1 of 1\", style=filled, fillcolor=1, color=red, penwidth=2];",
+            "    \"c\" [style=dashed];",
+            "    \"a\" -> \"a\" [color=red, penwidth=2];",
+            "    \"a/1\" [shape=box, label=\"1 of 2\"];",
+            "    \"a\" -> \"a/1\";",
+            "    \"a/1\" -> \"b\" [color=red, penwidth=2];",
+            "    \"a/1\" -> \"c\";",
+            "    \"b\" -> \"a\" [color=red, penwidth=2];",
+            "}",
+            "",
+        ].join("\n"));
+    }
+}
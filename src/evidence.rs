@@ -0,0 +1,79 @@
+use crate::{
+    message::Message,
+    node::NodeId,
+    slot::SlotId,
+    topic,
+    value::Value,
+};
+
+/// Evidence that a node made two statements that can't both be honest.
+/// Messages aren't signed, so it's only as good as whoever reported it:
+/// [`verify`](Equivocation::verify) checks that the two messages contradict each other,
+/// not that the [`offender`](Equivocation::offender) really sent them.
+/// What to do about it (e.g. dropping the node from your quorum slices)
+/// is up to the operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equivocation<T: Value> {
+    pub first:  Message<T>,
+    pub second: Message<T>,
+}
+
+impl<T: Value> Equivocation<T> {
+    pub fn new(first: Message<T>, second: Message<T>) -> Equivocation<T> {
+        return Equivocation { first, second };
+    }
+
+    /// The node that equivocated, going by what the messages say.
+    pub fn offender(&self) -> &NodeId {
+        return &self.first.sender;
+    }
+
+    /// Checks that the two messages really do contradict each other.
+    pub fn verify(&self) -> Result<(), ()> {
+        return if self.first.contradicts(&self.second) { Ok(()) } else { Err(()) };
+    }
+}
+
+/// A peer, or a proof it sent us, says a slot externalized something other than what we did.
+/// That means consensus failed: either we or enough of our quorum are faulty,
+/// and there's no telling which from here, so it's up to the operator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disagreement<T: Value> {
+    pub slot_id: SlotId,
+    pub from:    NodeId,
+    pub ours:    topic::Externalize<T>,
+    pub theirs:  topic::Externalize<T>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        quorum::Quorum,
+        slot::SlotId,
+        topic::{self, Topic},
+    };
+
+    fn commit(sender: &str, value: usize) -> Message<DummyValue> {
+        let topic = Topic::Commit(topic::Commit {
+            ballot:   Ballot { number: 1, value: DummyValue(value) },
+            prepared: 1,
+            highest:  1,
+            lowest:   1,
+        });
+        Message::new(node_id(sender), SlotId::new(0), Quorum::new(0, vec![]), topic, &mut 0)
+    }
+
+    #[test]
+    fn verify() {
+        let evidence = Equivocation::new(commit("a", 1), commit("a", 2));
+        assert_eq!(evidence.verify(), Ok(()));
+        assert_eq!(evidence.offender(), &node_id("a"));
+
+        // made up evidence doesn't hold up
+        assert_eq!(Equivocation::new(commit("a", 1), commit("a", 1)).verify(), Err(()));
+        assert_eq!(Equivocation::new(commit("a", 1), commit("b", 2)).verify(), Err(()));
+    }
+}
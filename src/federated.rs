@@ -0,0 +1,187 @@
+//! Federated voting, the building block of SCP.
+//! A node accepts a statement when a blocking set accepts it,
+//! or when a quorum votes for or accepts it,
+//! and confirms it when a quorum accepts it.
+//! Nothing here is specific to ballots:
+//! statements are whatever the predicates say they are,
+//! so you can run a federated vote on anything you can put in a [`Message`].
+
+use std::collections::HashSet;
+
+use crate::{
+    message::Message,
+    node::NodeId,
+    quorum::Quorum,
+    predicate::{Predicate, AcceptOrVote},
+    index::Statements,
+    value::Value,
+};
+
+/// Why a node accepted something, see [`accept`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Via {
+    /// It had already accepted it.
+    Own,
+    /// A v-blocking set accepted it.
+    Blocking,
+    /// A quorum voted for or accepted it.
+    Quorum,
+}
+
+/// Works out whether `node_id` accepts the statement described by `rule`,
+/// given the latest `statements` from its peers and the last message it sent, `own`.
+/// Returns a tuple of:
+///
+/// - the nodes that made it accept: itself, a blocking set, or a quorum.
+///   Empty if it doesn't accept.
+/// - how it came to accept, see [`Via`]. `None` if it doesn't accept.
+/// - the final value of the last predicate tested,
+///   e.g. the values or ballots that were accepted.
+pub fn accept<T: Value, A, V>(
+    node_id:    &NodeId,
+    quorum:     &Quorum<T>,
+    statements: &Statements<T>,
+    own:        Option<&Message<T>>,
+    rule:       AcceptOrVote<T, A, V>,
+) -> (HashSet<NodeId>, Option<Via>, A::Final) where A: Predicate<T>, V: Predicate<T, Final=A::Final> {
+    let AcceptOrVote { accept: mut predicate, vote: quorum_predicate, .. } = rule;
+
+    // if this node already accepts the predicate we're done
+    if let Some(message) = own {
+        if predicate.test(message) {
+            let mut accepting = HashSet::new();
+            accepting.insert(node_id.clone());
+            return (accepting, Some(Via::Own), predicate.build_final());
+        }
+    }
+
+    // if there is a blocking set that accepts we accept
+    let (blocking, predicate) = quorum.find_blocking(statements, predicate);
+    if !blocking.is_empty() { return (blocking, Some(Via::Blocking), predicate.build_final()); }
+
+    // if there's a quorum that votes or accepts we accept,
+    // but only if we vote or accept it ourselves, which confirm checks.
+    let (found, narrowed) = confirm(node_id, quorum, statements, own, quorum_predicate);
+    if !found.is_empty() { return (found, Some(Via::Quorum), narrowed); }
+
+    // nobody accepts :(
+    return (HashSet::new(), None, predicate.build_final());
+}
+
+/// Works out whether `node_id` confirms the statement `predicate` describes,
+/// i.e. whether it's part of a quorum that accepts it.
+/// That includes `node_id` itself, going by the last message it sent, `own`.
+/// Returns that quorum, empty if there isn't one,
+/// along with whatever the predicate narrowed down to.
+pub fn confirm<T: Value, P: Predicate<T>>(
+    node_id:       &NodeId,
+    quorum:        &Quorum<T>,
+    statements:    &Statements<T>,
+    own:           Option<&Message<T>>,
+    mut predicate: P,
+) -> (HashSet<NodeId>, P::Final) {
+    // we can't be part of a quorum that accepts something we don't
+    if !own.is_some_and(|message| predicate.test(message)) {
+        return (HashSet::new(), predicate.build_final());
+    }
+
+    let (found, predicate) = quorum.find_quorum(node_id.clone(), statements, predicate);
+    return (found, predicate.build_final());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        predicate::{FnPredicate, PredicateExt},
+        quorum::Member,
+        slot::SlotId,
+        topic::{self, Topic},
+    };
+
+    fn quorum() -> Quorum<DummyValue> {
+        Quorum::new(3, vec![
+            Member::Node(node_id("a")),
+            Member::Node(node_id("b")),
+            Member::Node(node_id("c")),
+            Member::Node(node_id("d")),
+        ])
+    }
+
+    /// A statement is a ballot number;
+    /// prepare messages vote for it, commit messages accept it.
+    fn message(name: &str, accepts: bool) -> Message<DummyValue> {
+        let ballot = Ballot { number: 1, value: DummyValue(0) };
+        let topic = if accepts {
+            Topic::Commit(topic::Commit { ballot, prepared: 1, highest: 1, lowest: 1 })
+        } else {
+            Topic::Prepare(topic::Prepare {
+                ballot,
+                prepared_a: Ballot { number: 0, value: DummyValue(0) },
+                prepared_b: Ballot { number: 0, value: DummyValue(0) },
+                highest:    0,
+                lowest:     0,
+            })
+        };
+        Message::new(node_id(name), SlotId::new(0), quorum(), topic, &mut 0)
+    }
+
+    fn rule() -> AcceptOrVote<DummyValue, impl Predicate<DummyValue, Final=bool>, impl Predicate<DummyValue, Final=bool>> {
+        let accepts = |m: &Message<DummyValue>| matches!(m.topic, Topic::Commit(_));
+        AcceptOrVote::new(
+            FnPredicate::new(accepts).map(|_| true),
+            FnPredicate::new(|_: &Message<DummyValue>| true).map(|_| true),
+        )
+    }
+
+    #[test]
+    fn accept_blocking() {
+        // two of four accepting is enough to block a threshold of three
+        let statements = vec![message("b", true), message("c", true), message("d", false)]
+            .into_iter().collect();
+        let (accepting, via, _) = accept(&node_id("a"), &quorum(), &statements, None, rule());
+        assert_eq!(accepting, vec![node_id("b"), node_id("c")].into_iter().collect());
+        assert_eq!(via, Some(Via::Blocking));
+    }
+
+    #[test]
+    fn accept_quorum() {
+        let statements = vec![message("b", true), message("c", false), message("d", false)]
+            .into_iter().collect();
+
+        // we haven't voted ourselves, so we can't be part of a quorum
+        let (accepting, via, _) = accept(&node_id("a"), &quorum(), &statements, None, rule());
+        assert!(accepting.is_empty());
+        assert_eq!(via, None);
+
+        let own = message("a", false);
+        let (accepting, via, _) = accept(&node_id("a"), &quorum(), &statements, Some(&own), rule());
+        // everyone votes or accepts, so everyone's in the quorum
+        assert_eq!(accepting.len(), 4);
+        assert_eq!(via, Some(Via::Quorum));
+    }
+
+    #[test]
+    fn confirm_quorum() {
+        let accepts = FnPredicate::new(|m: &Message<DummyValue>| matches!(m.topic, Topic::Commit(_)));
+        let statements = vec![message("b", true), message("c", true), message("d", false)]
+            .into_iter().collect();
+
+        // we have to have accepted it too, not just voted for it
+        let (confirming, _) = confirm(&node_id("a"), &quorum(), &statements, None, accepts.clone());
+        assert!(confirming.is_empty());
+        let (confirming, _) = confirm(&node_id("a"), &quorum(), &statements, Some(&message("a", false)), accepts.clone());
+        assert!(confirming.is_empty());
+
+        let own = message("a", true);
+        let (confirming, _) = confirm(&node_id("a"), &quorum(), &statements, Some(&own), accepts.clone());
+        assert_eq!(confirming, vec![node_id("a"), node_id("b"), node_id("c")].into_iter().collect());
+
+        let statements = vec![message("b", true), message("c", false), message("d", false)]
+            .into_iter().collect();
+        let (confirming, _) = confirm(&node_id("a"), &quorum(), &statements, Some(&own), accepts);
+        assert!(confirming.is_empty());
+    }
+}
@@ -0,0 +1,146 @@
+//! Test fixtures shared by the unit tests:
+//! a tiny value type, node ids, and a toy protocol
+//! for the simulator and the model checker to drive.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    arbitrary::{Arbitrary, Source},
+    check::{Observation, Participant},
+    codec::{Decode, Encode},
+    message::Message,
+    node::NodeId,
+    quorum::Quorum,
+    slot::{Phase, SlotId},
+    topic::{self, Topic},
+    value::Value,
+};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub struct DummyValue(pub usize);
+
+impl Value for DummyValue {
+    fn combine(this: Self, that: Self, _slot_id: SlotId) -> Self {
+        DummyValue(this.0 + that.0)
+    }
+}
+
+impl Arbitrary for DummyValue {
+    fn arbitrary(source: &mut Source) -> DummyValue {
+        DummyValue(source.below(3))
+    }
+}
+
+impl Encode for DummyValue {
+    fn encode(&self, out: &mut Vec<u8>) { self.0.encode(out); }
+}
+
+impl Decode for DummyValue {
+    fn decode(input: &mut &[u8]) -> Result<DummyValue, ()> {
+        Ok(DummyValue(usize::decode(input)?))
+    }
+}
+
+pub fn node_id(name: &str) -> NodeId {
+    NodeId::new(name.to_string())
+}
+
+pub fn node_ids(names: &[&str]) -> Vec<NodeId> {
+    names.iter().map(|n| node_id(n)).collect()
+}
+
+/// A toy protocol: decides on the smallest of its own value and those it's heard,
+/// once it's heard from `wait_for` peers.
+/// Whenever it hears something new, it repeats everything it's heard,
+/// so nodes that restart catch up.
+/// On a timeout, it repeats that and its own value too.
+pub struct Toy {
+    id:       NodeId,
+    value:    DummyValue,
+    wait_for: usize,
+    repeats:  bool,
+    counter:  usize,
+    heard:    HashMap<NodeId, Message<DummyValue>>,
+    decided:  Option<DummyValue>,
+}
+
+impl Toy {
+    pub fn new(id: &NodeId, value: DummyValue, wait_for: usize) -> Toy {
+        Toy { id: id.clone(), value, wait_for, repeats: true, counter: 0, heard: HashMap::new(), decided: None }
+    }
+
+    /// A toy that never repeats itself.
+    /// The model checker never loses messages,
+    /// and the repeats would only blow up the search.
+    pub fn quiet(id: &NodeId, value: DummyValue, wait_for: usize) -> Toy {
+        Toy { repeats: false, ..Toy::new(id, value, wait_for) }
+    }
+
+    pub fn nomination(&mut self) -> Message<DummyValue> {
+        let topic = Topic::Nominate(topic::Nominate {
+            nominated: vec![self.value.clone()].into_iter().collect(),
+            accepted:  HashSet::new(),
+        });
+        Message::new(self.id.clone(), SlotId::new(0), Quorum::new(0, vec![]), topic, &mut self.counter)
+    }
+
+    fn heard(&self) -> Vec<Message<DummyValue>> {
+        let mut heard = self.heard.values().cloned().collect::<Vec<Message<DummyValue>>>();
+        heard.sort_by(|a, b| a.sender.cmp(&b.sender));
+        heard
+    }
+}
+
+impl Participant<DummyValue> for Toy {
+    fn node_id(&self) -> NodeId { self.id.clone() }
+
+    fn handle(&mut self, message: Message<DummyValue>) -> Result<Vec<Message<DummyValue>>, ()> {
+        // only the latest from each sender counts
+        let stale = self.heard.get(&message.sender).is_some_and(|m| m.counter >= message.counter);
+        if message.sender == self.id || stale {
+            return Ok(vec![]);
+        }
+        self.heard.insert(message.sender.clone(), message);
+
+        if self.decided.is_none() && self.heard.len() >= self.wait_for {
+            self.decided = self.heard.values()
+                .flat_map(|m| match &m.topic { Topic::Nominate(n) => n.nominated.iter().cloned().collect(), _ => vec![] })
+                .chain(Some(self.value.clone()))
+                .min();
+        }
+        Ok(if self.repeats { self.heard() } else { vec![] })
+    }
+
+    fn propose(&mut self, value: DummyValue) -> Vec<Message<DummyValue>> {
+        self.value = value;
+        vec![self.nomination()]
+    }
+
+    fn timeout(&mut self) -> Vec<Message<DummyValue>> {
+        if !self.repeats { return vec![]; }
+        let mut heard = self.heard();
+        heard.push(self.nomination());
+        heard
+    }
+
+    fn observe(&self) -> Observation<DummyValue> {
+        Observation {
+            phase:        if self.decided.is_some() { Phase::Externalize } else { Phase::Prepare },
+            externalized: self.decided.clone(),
+            prepared_a:   None,
+            prepared_b:   None,
+            lowest:       0,
+            highest:      0,
+        }
+    }
+
+    fn fingerprint<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+        self.counter.hash(state);
+        self.heard().hash(state);
+        self.decided.hash(state);
+    }
+}
@@ -0,0 +1,361 @@
+//! Flooding messages through the network.
+//! Nodes usually aren't directly connected to everyone in their transitive quorum,
+//! so messages have to be passed along by the nodes in between.
+//! [`Flood`] wraps a [`Transport`] whose peers are just our direct neighbours,
+//! and relays every valid message it hasn't seen before on to them.
+//! It's a [`Transport`] itself, so a node can't tell the difference.
+//!
+//! To keep the flood down:
+//! - messages are deduplicated by hash, in a bounded cache,
+//!   though the same message is let through again after a while,
+//!   since slots repeat themselves on a timeout for peers that missed it;
+//! - only a statement that supersedes the last one we saw
+//!   from the same sender, for the same slot and protocol, or repeats it, is relayed;
+//! - each neighbour gets a limited rate, past which what they deliver is dropped.
+//!   That's the neighbour, not the sender: anyone can claim to be anyone,
+//!   but a neighbour can only spend their own budget.
+
+use std::{
+    collections::{HashMap, VecDeque, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    message::Message,
+    node::NodeId,
+    slot::SlotId,
+    topic::{Protocol, Topic},
+    transport::Transport,
+    value::Value,
+};
+
+/// How much a [`Flood`] remembers and lets through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// How many message hashes to remember for deduplication.
+    pub seen:        usize,
+    /// How long until the same message is let through again.
+    /// Keep it below the slot timeout, so a rebroadcast after a timeout is heard.
+    pub rebroadcast: Duration,
+    /// How many messages a neighbour can get through at once.
+    pub burst:       f64,
+    /// How many more a neighbour can send each second, after that.
+    pub per_second:  f64,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        return Limits {
+            seen:        65536,
+            rebroadcast: Duration::from_millis(500),
+            burst:       64.0,
+            per_second:  32.0,
+        };
+    }
+}
+
+/// A token bucket, for rate limiting a single neighbour.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens:  f64,
+    updated: Instant,
+}
+
+/// A flooding overlay on top of another [`Transport`], see the module docs.
+pub struct Flood<T: Value, R: Transport<T>> {
+    inner:   R,
+    limits:  Limits,
+    /// When we last saw each message, by hash.
+    seen:    HashMap<u64, Instant>,
+    /// The order hashes were first seen in, oldest first, so we know what to forget.
+    order:   VecDeque<u64>,
+    latest:  HashMap<(NodeId, SlotId, Protocol), Topic<T>>,
+    buckets: HashMap<NodeId, Bucket>,
+    /// When we last forgot the buckets that had filled back up.
+    swept:   Instant,
+}
+
+fn digest<T: Value>(message: &Message<T>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    message.hash(&mut hasher);
+    return hasher.finish();
+}
+
+impl<T: Value, R: Transport<T>> Flood<T, R> {
+    pub fn new(inner: R, limits: Limits) -> Flood<T, R> {
+        return Flood {
+            inner,
+            limits,
+            seen:    HashMap::new(),
+            order:   VecDeque::new(),
+            latest:  HashMap::new(),
+            buckets: HashMap::new(),
+            swept:   Instant::now(),
+        };
+    }
+
+    pub fn inner(&self) -> &R {
+        return &self.inner;
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        return &mut self.inner;
+    }
+
+    /// Forgets the latest statements for every slot before `slot_id`.
+    /// Call this as slots are externalized, so this doesn't grow forever.
+    pub fn forget_before(&mut self, slot_id: SlotId) {
+        self.latest.retain(|(_, s, _), _| *s >= slot_id);
+    }
+
+    /// Remembers a message, or when we saw it last if we already knew it.
+    fn see(&mut self, hash: u64) {
+        if self.seen.insert(hash, Instant::now()).is_some() { return; }
+
+        self.order.push_back(hash);
+        while self.order.len() > self.limits.seen {
+            if let Some(oldest) = self.order.pop_front() { self.seen.remove(&oldest); }
+        }
+    }
+
+    /// Whether we've seen a message too recently to let it through again.
+    fn recently_seen(&self, hash: u64) -> bool {
+        return self.seen.get(&hash).is_some_and(|at| at.elapsed() < self.limits.rebroadcast);
+    }
+
+    /// Forgets the buckets that have filled back up, since a fresh one is the same thing.
+    /// Only bothers once it's been long enough for a bucket to fill from empty.
+    fn sweep(&mut self, now: Instant) {
+        let limits = self.limits;
+        // buckets that never refill have to be kept, or forgetting them would refill them
+        if limits.per_second <= 0.0 { return; }
+        if now.duration_since(self.swept).as_secs_f64() * limits.per_second < limits.burst { return; }
+
+        self.swept = now;
+        self.buckets.retain(|_, bucket| {
+            let refill = now.duration_since(bucket.updated).as_secs_f64() * limits.per_second;
+            bucket.tokens + refill < limits.burst
+        });
+    }
+
+    /// Takes a token from the peer's bucket, returning whether there was one.
+    fn allow(&mut self, peer: &NodeId) -> bool {
+        let now = Instant::now();
+        self.sweep(now);
+
+        let limits = self.limits;
+        let bucket = self.buckets
+            .entry(peer.clone())
+            .or_insert(Bucket { tokens: limits.burst, updated: now });
+
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * limits.per_second;
+        bucket.tokens = (bucket.tokens + refill).min(limits.burst);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 { return false; }
+        bucket.tokens -= 1.0;
+        return true;
+    }
+
+    /// Decides what to do with a message `peer` delivered,
+    /// relaying it if need be, and returning it if the node should see it.
+    fn admit(&mut self, peer: &NodeId, message: Message<T>) -> Option<Message<T>> {
+        let hash = digest(&message);
+        if self.recently_seen(hash) { return None; }
+        if message.valid().is_err() { return None; }
+        if !self.allow(peer) { return None; }
+        self.see(hash);
+
+        // only pass along what's news, or the sender repeating itself,
+        // which it does on a timeout in case someone missed it.
+        // if the two can't be ordered, the sender is equivocating:
+        // we don't spread that, but the node still gets to see it as evidence.
+        let key = (message.sender.clone(), message.slot_id, message.topic.protocol());
+        let newer = match self.latest.get(&key) {
+            Some(previous) => message.topic == *previous || message.topic.supersedes(previous) == Ok(true),
+            None           => true,
+        };
+
+        if newer {
+            self.latest.insert(key, message.topic.clone());
+            for other in self.inner.peers() {
+                // the sender and whoever handed it to us obviously have it already
+                if other == message.sender || other == *peer { continue; }
+                let _ = self.inner.send_to(&other, &message);
+            }
+        }
+
+        return Some(message);
+    }
+}
+
+impl<T: Value, R: Transport<T>> Transport<T> for Flood<T, R> {
+    fn node_id(&self) -> &NodeId {
+        return self.inner.node_id();
+    }
+
+    fn peers(&self) -> Vec<NodeId> {
+        return self.inner.peers();
+    }
+
+    fn send_to(&mut self, peer: &NodeId, message: &Message<T>) -> Result<(), ()> {
+        // so it isn't relayed back to us
+        self.see(digest(message));
+        return self.inner.send_to(peer, message);
+    }
+
+    fn recv_from(&mut self, timeout: Duration) -> Result<Option<(NodeId, Message<T>)>, ()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (peer, message) = match self.inner.recv_from(remaining)? {
+                Some(received) => received,
+                None           => { return Ok(None); },
+            };
+
+            if let Some(message) = self.admit(&peer, message) { return Ok(Some((peer, message))); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::{
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        quorum::Quorum,
+        topic,
+        transport::Channel,
+    };
+
+    fn nominate(sender: &str, values: &[usize]) -> Message<DummyValue> {
+        let topic = Topic::Nominate(topic::Nominate {
+            nominated: values.iter().map(|v| DummyValue(*v)).collect(),
+            accepted:  HashSet::new(),
+        });
+        Message::new(node_id(sender), SlotId::new(0), Quorum::new(0, vec![]), topic, &mut 0)
+    }
+
+    fn prepare(sender: &str, value: usize) -> Message<DummyValue> {
+        let zero = Ballot { number: 0, value: DummyValue(0) };
+        let topic = Topic::Prepare(topic::Prepare {
+            ballot:     Ballot { number: 1, value: DummyValue(value) },
+            prepared_a: zero.clone(),
+            prepared_b: zero,
+            highest:    0,
+            lowest:     0,
+        });
+        Message::new(node_id(sender), SlotId::new(0), Quorum::new(0, vec![]), topic, &mut 0)
+    }
+
+    const WAIT: Duration = Duration::from_millis(200);
+
+    /// a - b - c, where a and c can only hear each other through b.
+    fn line(limits: Limits) -> Vec<Flood<DummyValue, Channel<DummyValue>>> {
+        let mut channels = Channel::network(&[node_id("a"), node_id("b"), node_id("c")]);
+        channels[0].disconnect(&node_id("c"));
+        channels[2].disconnect(&node_id("a"));
+        channels.into_iter().map(|c| Flood::new(c, limits)).collect()
+    }
+
+    #[test]
+    fn relays_through_neighbours() {
+        let mut nodes = line(Limits::default());
+        let message = nominate("a", &[1]);
+        nodes[0].broadcast(&message).unwrap();
+
+        assert_eq!(nodes[1].recv(WAIT), Ok(Some(message.clone())));
+        assert_eq!(nodes[2].recv(WAIT), Ok(Some(message.clone())));
+
+        // c relays it back to b, who has seen it, and a never gets its own message back
+        assert_eq!(nodes[1].recv(WAIT), Ok(None));
+        assert_eq!(nodes[0].recv(WAIT), Ok(None));
+    }
+
+    #[test]
+    fn only_relays_news() {
+        let mut nodes = line(Limits::default());
+        let newer = nominate("a", &[1, 2]);
+        let older = nominate("a", &[1]);
+        nodes[0].broadcast(&newer).unwrap();
+        nodes[0].broadcast(&older).unwrap();
+
+        // b sees both, but only passes on the newer one
+        assert_eq!(nodes[1].recv(WAIT), Ok(Some(newer.clone())));
+        assert_eq!(nodes[1].recv(WAIT), Ok(Some(older)));
+        assert_eq!(nodes[2].recv(WAIT), Ok(Some(newer)));
+        assert_eq!(nodes[2].recv(WAIT), Ok(None));
+    }
+
+    #[test]
+    fn relays_rebroadcasts() {
+        let mut nodes = line(Limits { rebroadcast: Duration::from_millis(100), ..Limits::default() });
+        let message = nominate("a", &[1]);
+        nodes[0].broadcast(&message).unwrap();
+        assert_eq!(nodes[1].recv(WAIT), Ok(Some(message.clone())));
+        assert_eq!(nodes[2].recv(WAIT), Ok(Some(message.clone())));
+
+        // straight away, it's just an echo
+        nodes[0].broadcast(&message).unwrap();
+        assert_eq!(nodes[1].recv(WAIT), Ok(None));
+
+        // but after a timeout, it's someone repeating themselves for whoever missed it
+        std::thread::sleep(Duration::from_millis(100));
+        nodes[0].broadcast(&message).unwrap();
+        assert_eq!(nodes[1].recv(WAIT), Ok(Some(message.clone())));
+        assert_eq!(nodes[2].recv(WAIT), Ok(Some(message)));
+    }
+
+    #[test]
+    fn rate_limits_senders() {
+        let mut nodes = line(Limits { seen: 16, burst: 2.0, per_second: 0.0, ..Limits::default() });
+        for n in 1..=3 { nodes[0].broadcast(&nominate("a", &(1..=n).collect::<Vec<usize>>())).unwrap(); }
+
+        assert!(nodes[1].recv(WAIT).unwrap().is_some());
+        assert!(nodes[1].recv(WAIT).unwrap().is_some());
+        assert_eq!(nodes[1].recv(WAIT), Ok(None));
+    }
+
+    #[test]
+    fn relays_each_protocol() {
+        let mut nodes = line(Limits::default());
+        let ballot = prepare("a", 1);
+        let nomination = nominate("a", &[1]);
+        nodes[0].broadcast(&ballot).unwrap();
+        nodes[0].broadcast(&nomination).unwrap();
+
+        // a nomination doesn't supersede a ballot statement, but it isn't old news either
+        assert_eq!(nodes[1].recv(WAIT), Ok(Some(ballot.clone())));
+        assert_eq!(nodes[1].recv(WAIT), Ok(Some(nomination.clone())));
+        assert_eq!(nodes[2].recv(WAIT), Ok(Some(ballot)));
+        assert_eq!(nodes[2].recv(WAIT), Ok(Some(nomination)));
+    }
+
+    #[test]
+    fn rate_limits_peers() {
+        let mut nodes = line(Limits { seen: 16, burst: 2.0, per_second: 0.0, ..Limits::default() });
+        // b can say they're passing on messages from anyone, it's still b's budget
+        for sender in ["x", "y", "z"] { nodes[1].broadcast(&nominate(sender, &[1])).unwrap(); }
+
+        assert_eq!(nodes[2].recv_from(WAIT), Ok(Some((node_id("b"), nominate("x", &[1])))));
+        assert_eq!(nodes[2].recv_from(WAIT), Ok(Some((node_id("b"), nominate("y", &[1])))));
+        assert_eq!(nodes[2].recv(WAIT), Ok(None));
+    }
+
+    #[test]
+    fn forgets_idle_peers() {
+        let mut nodes = line(Limits { seen: 16, burst: 2.0, per_second: 1000.0, ..Limits::default() });
+        nodes[0].broadcast(&nominate("a", &[1])).unwrap();
+        assert!(nodes[1].recv(WAIT).unwrap().is_some());
+        assert_eq!(nodes[1].buckets.keys().collect::<Vec<_>>(), vec![&node_id("a")]);
+
+        // long enough for a's bucket to fill back up
+        std::thread::sleep(Duration::from_millis(10));
+        nodes[2].broadcast(&nominate("c", &[1])).unwrap();
+        assert!(nodes[1].recv(WAIT).unwrap().is_some());
+        assert_eq!(nodes[1].buckets.keys().collect::<Vec<_>>(), vec![&node_id("c")]);
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    iter::FromIterator,
+};
+
+use crate::{
+    message::Message,
+    node::NodeId,
+    quorum::IndexedQuorum,
+    value::Value,
+};
+
+// Node index
+
+/// Maps every node we know about to a small, dense index,
+/// so quorum computations can work with bitsets
+/// rather than hashing [`NodeId`]s at every step.
+/// Indices are handed out in order and never reused.
+#[derive(Debug, Clone, Default)]
+pub struct NodeIndex {
+    ids:     Vec<NodeId>,
+    indices: HashMap<NodeId, usize>,
+}
+
+impl NodeIndex {
+    pub fn new() -> NodeIndex {
+        return NodeIndex { ids: vec![], indices: HashMap::new() };
+    }
+
+    /// Returns the index of a node, giving it one if it doesn't have one yet.
+    pub fn intern(&mut self, node_id: &NodeId) -> usize {
+        if let Some(index) = self.indices.get(node_id) { return *index; }
+
+        let index = self.ids.len();
+        self.ids.push(node_id.clone());
+        self.indices.insert(node_id.clone(), index);
+        return index;
+    }
+
+    pub fn get(&self, node_id: &NodeId) -> Option<usize> {
+        return self.indices.get(node_id).copied();
+    }
+
+    pub fn node_id(&self, index: usize) -> Option<&NodeId> {
+        return self.ids.get(index);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.ids.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.ids.is_empty();
+    }
+}
+
+// Bitset
+
+/// A growable set of node indices.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> BitSet {
+        return BitSet { words: vec![] };
+    }
+
+    fn locate(index: usize) -> (usize, u64) {
+        return (index / 64, 1 << (index % 64));
+    }
+
+    /// Adds an index to the set, returning whether it was newly added.
+    pub fn insert(&mut self, index: usize) -> bool {
+        let (word, bit) = BitSet::locate(index);
+        if word >= self.words.len() { self.words.resize(word + 1, 0); }
+
+        let added = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        return added;
+    }
+
+    /// Removes an index from the set, returning whether it was there.
+    pub fn remove(&mut self, index: usize) -> bool {
+        let (word, bit) = BitSet::locate(index);
+        if word >= self.words.len() { return false; }
+
+        let removed = self.words[word] & bit != 0;
+        self.words[word] &= !bit;
+        return removed;
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        let (word, bit) = BitSet::locate(index);
+        return match self.words.get(word) {
+            Some(w) => w & bit != 0,
+            None    => false,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.words.iter().map(|w| w.count_ones() as usize).sum();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.words.iter().all(|w| *w == 0);
+    }
+
+    /// Iterates over the indices in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        return self.words.iter().enumerate().flat_map(|(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| word * 64 + bit)
+        });
+    }
+}
+
+// Statements
+
+/// The latest message from each node, indexed for quorum computations.
+/// Every node a message mentions, the sender and the members of its quorum set,
+/// is given an index when the message is inserted,
+/// and the sender's quorum set is compiled down to those indices once, up front.
+/// Presence is tracked as a [`BitSet`].
+#[derive(Debug, Clone)]
+pub struct Statements<T: Value> {
+    index:    NodeIndex,
+    present:  BitSet,
+    messages: Vec<Option<(Message<T>, IndexedQuorum)>>,
+}
+
+impl<T: Value> Statements<T> {
+    pub fn new() -> Statements<T> {
+        return Statements {
+            index:    NodeIndex::new(),
+            present:  BitSet::new(),
+            messages: vec![],
+        };
+    }
+
+    pub fn get(&self, node_id: &NodeId) -> Option<&Message<T>> {
+        let index = self.index.get(node_id)?;
+        return self.entry(index).map(|(message, _)| message);
+    }
+
+    pub fn contains(&self, node_id: &NodeId) -> bool {
+        return self.get(node_id).is_some();
+    }
+
+    /// Inserts a message, replacing and returning
+    /// the previous message from the same sender, if any.
+    pub fn insert(&mut self, message: Message<T>) -> Option<Message<T>> {
+        let index  = self.index.intern(&message.sender);
+        let quorum = message.quorum.intern(&mut self.index);
+
+        if index >= self.messages.len() { self.messages.resize(index + 1, None); }
+        self.present.insert(index);
+
+        let previous = self.messages[index].replace((message, quorum));
+        return previous.map(|(message, _)| message);
+    }
+
+    pub fn remove(&mut self, node_id: &NodeId) -> Option<Message<T>> {
+        let index = self.index.get(node_id)?;
+        self.present.remove(index);
+        return self.messages.get_mut(index)?.take().map(|(message, _)| message);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.present.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.present.is_empty();
+    }
+
+    pub fn values(&self) -> impl Iterator<Item=&Message<T>> + '_ {
+        return self.messages.iter().filter_map(|entry| entry.as_ref().map(|(message, _)| message));
+    }
+
+    /// Which nodes we have a message from.
+    pub fn present(&self) -> &BitSet {
+        return &self.present;
+    }
+
+    pub fn index(&self) -> &NodeIndex {
+        return &self.index;
+    }
+
+    /// The message from the node with the given index, and its compiled quorum set.
+    pub(crate) fn entry(&self, index: usize) -> Option<&(Message<T>, IndexedQuorum)> {
+        if !self.present.contains(index) { return None; }
+        return self.messages.get(index)?.as_ref();
+    }
+}
+
+impl<T: Value> Default for Statements<T> {
+    fn default() -> Statements<T> {
+        return Statements::new();
+    }
+}
+
+impl<T: Value> FromIterator<Message<T>> for Statements<T> {
+    fn from_iter<I: IntoIterator<Item=Message<T>>>(iter: I) -> Statements<T> {
+        let mut statements = Statements::new();
+        for message in iter { statements.insert(message); }
+        return statements;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitset() {
+        let mut set = BitSet::new();
+        assert!(set.is_empty());
+        assert!(set.insert(3));
+        assert!(set.insert(130));
+        assert!(!set.insert(3));
+        assert!(set.contains(130));
+        assert!(!set.contains(4));
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.iter().collect::<Vec<usize>>(), vec![3, 130]);
+        assert!(set.remove(3));
+        assert!(!set.remove(3));
+        assert!(!set.remove(1000));
+        assert_eq!(set.iter().collect::<Vec<usize>>(), vec![130]);
+    }
+
+    #[test]
+    fn interning() {
+        let mut index = NodeIndex::new();
+        let a = NodeId::new("a".to_string());
+        let b = NodeId::new("b".to_string());
+        assert_eq!(index.intern(&a), 0);
+        assert_eq!(index.intern(&b), 1);
+        assert_eq!(index.intern(&a), 0);
+        assert_eq!(index.get(&b), Some(1));
+        assert_eq!(index.node_id(1), Some(&b));
+        assert_eq!(index.len(), 2);
+    }
+}
@@ -0,0 +1,301 @@
+//! Just enough JSON to dump state for operators, e.g. on a status endpoint,
+//! and, with the `tools` feature, to read simple config files back in.
+//! Values are written with their `Debug` representation,
+//! because [`Value`] doesn't say anything else about how to show one.
+
+use std::fmt;
+#[cfg(feature = "tools")]
+use std::{iter::Peekable, str::Chars};
+
+use crate::{
+    ballot::Ballot,
+    node::NodeId,
+    topic::Topic,
+    value::Value,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys are written in the order given.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object out of `(key, value)` pairs.
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        return Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect());
+    }
+}
+
+fn escape(string: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"'  => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    return write!(f, "\"");
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null      => write!(f, "null"),
+            Json::Bool(b)   => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => escape(s, f),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    escape(key, f)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+// Parsing
+
+// TODO: better error types
+
+/// Parses a single JSON document.
+/// Numbers have to be non-negative integers, because that's all [`Json`] holds.
+#[cfg(feature = "tools")]
+pub fn parse(input: &str) -> Result<Json, ()> {
+    let mut parser = Parser { chars: input.chars().peekable() };
+    let json = parser.value()?;
+    parser.skip_whitespace();
+    return if parser.chars.next().is_none() { Ok(json) } else { Err(()) };
+}
+
+#[cfg(feature = "tools")]
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+#[cfg(feature = "tools")]
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.chars.peek() { self.chars.next(); }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ()> {
+        self.skip_whitespace();
+        return if self.chars.next() == Some(c) { Ok(()) } else { Err(()) };
+    }
+
+    /// Expects `word`, after its first character has already been peeked.
+    fn word(&mut self, word: &str, json: Json) -> Result<Json, ()> {
+        for c in word.chars() {
+            if self.chars.next() != Some(c) { return Err(()); }
+        }
+        return Ok(json);
+    }
+
+    fn value(&mut self) -> Result<Json, ()> {
+        self.skip_whitespace();
+        return match self.chars.peek().ok_or(())? {
+            'n' => self.word("null",  Json::Null),
+            't' => self.word("true",  Json::Bool(true)),
+            'f' => self.word("false", Json::Bool(false)),
+            '"' => Ok(Json::String(self.string()?)),
+            '[' => self.array(),
+            '{' => self.object(),
+            c if c.is_ascii_digit() => self.number(),
+            _ => Err(()),
+        };
+    }
+
+    fn number(&mut self) -> Result<Json, ()> {
+        let mut digits = String::new();
+        while let Some(c) = self.chars.peek() {
+            if !c.is_ascii_digit() { break; }
+            digits.push(*c);
+            self.chars.next();
+        }
+        // no leading zeros, and no fractions or exponents
+        if digits.len() > 1 && digits.starts_with('0') { return Err(()); }
+        if let Some('.') | Some('e') | Some('E') = self.chars.peek() { return Err(()); }
+        return digits.parse().map(Json::Number).map_err(|_| ());
+    }
+
+    fn string(&mut self) -> Result<String, ()> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next().ok_or(())? {
+                '"'  => { return Ok(string); },
+                '\\' => string.push(match self.chars.next().ok_or(())? {
+                    '"'  => '"',
+                    '\\' => '\\',
+                    '/'  => '/',
+                    'b'  => '\u{8}',
+                    'f'  => '\u{c}',
+                    'n'  => '\n',
+                    'r'  => '\r',
+                    't'  => '\t',
+                    'u'  => {
+                        // TODO: surrogate pairs
+                        let hex = (0..4).map(|_| self.chars.next().ok_or(())).collect::<Result<String, ()>>()?;
+                        let code = u32::from_str_radix(&hex, 16).map_err(|_| ())?;
+                        char::from_u32(code).ok_or(())?
+                    },
+                    _ => { return Err(()); },
+                }),
+                c if (c as u32) < 0x20 => { return Err(()); },
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, ()> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') { self.chars.next(); return Ok(Json::Array(items)); }
+
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => (),
+                Some(']') => { return Ok(Json::Array(items)); },
+                _         => { return Err(()); },
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, ()> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') { self.chars.next(); return Ok(Json::Object(fields)); }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => (),
+                Some('}') => { return Ok(Json::Object(fields)); },
+                _         => { return Err(()); },
+            }
+        }
+    }
+}
+
+// Protocol types
+
+pub fn number(n: usize) -> Json {
+    return Json::Number(n as u64);
+}
+
+pub fn value<T: Value>(value: &T) -> Json {
+    return Json::String(format!("{:?}", value));
+}
+
+pub fn values<'a, T: Value + 'a>(values: impl IntoIterator<Item=&'a T>) -> Json {
+    return Json::Array(values.into_iter().map(value).collect());
+}
+
+pub fn node_id(node_id: &NodeId) -> Json {
+    return Json::String(node_id.as_str().to_string());
+}
+
+pub fn node_ids<'a>(node_ids: impl IntoIterator<Item=&'a NodeId>) -> Json {
+    return Json::Array(node_ids.into_iter().map(node_id).collect());
+}
+
+pub fn ballot<T: Value>(ballot: &Ballot<T>) -> Json {
+    return Json::object(vec![
+        ("number", number(ballot.number)),
+        ("value",  value(&ballot.value)),
+    ]);
+}
+
+pub fn topic<T: Value>(topic: &Topic<T>) -> Json {
+    return match topic {
+        Topic::Nominate(n) => {
+            let mut nominated = n.nominated.iter().collect::<Vec<&T>>();
+            let mut accepted  = n.accepted.iter().collect::<Vec<&T>>();
+            nominated.sort();
+            accepted.sort();
+
+            Json::object(vec![
+                ("type",      Json::String("nominate".to_string())),
+                ("nominated", values(nominated)),
+                ("accepted",  values(accepted)),
+            ])
+        },
+        Topic::Prepare(p) => Json::object(vec![
+            ("type",       Json::String("prepare".to_string())),
+            ("ballot",     ballot(&p.ballot)),
+            ("prepared_a", ballot(&p.prepared_a)),
+            ("prepared_b", ballot(&p.prepared_b)),
+            ("highest",    number(p.highest)),
+            ("lowest",     number(p.lowest)),
+        ]),
+        Topic::Commit(c) => Json::object(vec![
+            ("type",     Json::String("commit".to_string())),
+            ("ballot",   ballot(&c.ballot)),
+            ("prepared", number(c.prepared)),
+            ("highest",  number(c.highest)),
+            ("lowest",   number(c.lowest)),
+        ]),
+        Topic::Externalize(e) => Json::object(vec![
+            ("type",    Json::String("externalize".to_string())),
+            ("ballot",  ballot(&e.ballot)),
+            ("highest", number(e.highest)),
+        ]),
+    };
+}
+
+#[cfg(all(test, feature = "tools"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let json = Json::object(vec![
+            ("name",  Json::String("a \"quoted\"\nline".to_string())),
+            ("peers", Json::Array(vec![Json::Number(1), Json::Null, Json::Bool(true)])),
+            ("empty", Json::Object(vec![])),
+        ]);
+        assert_eq!(
+            json.to_string(),
+            r#"{"name":"a \"quoted\"\nline","peers":[1,null,true],"empty":{}}"#,
+        );
+        assert_eq!(parse(&json.to_string()), Ok(json));
+    }
+
+    #[test]
+    fn malformed() {
+        for input in ["", "[1,]", "{\"a\" 1}", "01", "1.5", "-1", "\"open", "[] []", "nul"].iter() {
+            assert_eq!(parse(input), Err(()), "{:?}", input);
+        }
+    }
+}
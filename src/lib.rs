@@ -6,6 +6,30 @@ pub mod slot;
 pub mod topic;
 pub mod ballot;
 pub mod value;
+pub mod catchup;
+pub mod storage;
+pub mod evidence;
+pub mod index;
+pub mod federated;
+pub mod check;
+pub mod arbitrary;
+pub mod codec;
+pub mod trace;
+pub mod json;
+pub mod metrics;
+pub mod log;
+pub mod asynchronous;
+pub mod transport;
+pub mod gossip;
+#[cfg(feature = "tools")]
+pub mod toml;
+#[cfg(feature = "tools")]
+pub mod config;
+pub mod analysis;
+pub mod dot;
+pub mod sim;
+#[cfg(test)]
+mod fixtures;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,186 @@
+//! Why a node did what it did.
+//! Slots note a [`Transition`] every time their state moves,
+//! along with the nodes that moved it, and a [`Node`](crate::node::Node)
+//! passes them on to its [`Logger`] with the slot they happened in.
+//! Like [metrics](crate::metrics), where they end up is up to you.
+//! [`NoLogger`] drops everything, and is the default.
+//! [`Stderr`] prints each one on a line, which is handy when debugging.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    ballot::Ballot,
+    federated::Via,
+    node::NodeId,
+    slot::{Phase, SlotId},
+    value::Value,
+};
+
+/// Where a ballot's new value came from, see [`Transition::Ballot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    /// The highest ballot we've confirmed prepared.
+    Highest,
+    /// The values we've confirmed nominated, combined.
+    Confirmed,
+    /// The highest ballot we've accepted prepared.
+    Prepared,
+}
+
+/// Node sets are sorted, so the same transition always reads the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transition<T: Value> {
+    AcceptedNominated  { values: Vec<T>, via: Via, nodes: Vec<NodeId> },
+    ConfirmedNominated { values: Vec<T>, quorum: Vec<NodeId> },
+    AcceptedPrepared   { ballot: Ballot<T>, via: Via, nodes: Vec<NodeId> },
+    ConfirmedPrepared  { ballot: Ballot<T>, quorum: Vec<NodeId> },
+    AcceptedCommit     { ballot: Ballot<T>, via: Via, nodes: Vec<NodeId> },
+    ConfirmedCommit    { ballot: Ballot<T>, quorum: Vec<NodeId> },
+    /// The ballot we're working on took a new value.
+    Ballot { ballot: Ballot<T>, source: Source },
+    /// The timer fired, moving nomination to `round`,
+    /// and bumping the ballot counter if we'd started balloting.
+    Timeout { round: usize, ballot: Option<Ballot<T>> },
+    Phase { from: Phase, to: Phase },
+    /// A peer, or a proof from one, says a different value was externalized.
+    /// Their message is dropped, and kept as a [`Disagreement`](crate::evidence::Disagreement).
+    Disagreement { ours: T, theirs: T, from: NodeId },
+}
+
+fn nodes(f: &mut fmt::Formatter<'_>, nodes: &[NodeId]) -> fmt::Result {
+    let names = nodes.iter().map(|n| n.as_str()).collect::<Vec<&str>>();
+    return write!(f, "{{{}}}", names.join(", "));
+}
+
+fn via(f: &mut fmt::Formatter<'_>, via: Via, accepting: &[NodeId]) -> fmt::Result {
+    match via {
+        Via::Own      => { return write!(f, "because we already had"); },
+        Via::Blocking => write!(f, "because blocking set ")?,
+        Via::Quorum   => write!(f, "via quorum ")?,
+    }
+    return nodes(f, accepting);
+}
+
+fn ballot<T: Value>(ballot: &Ballot<T>) -> String {
+    return format!("({}, {:?})", ballot.number, ballot.value);
+}
+
+impl<T: Value> fmt::Display for Transition<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transition::AcceptedNominated { values, via: v, nodes: n } => {
+                write!(f, "accepted nominated values {:?} ", values)?;
+                return via(f, *v, n);
+            },
+            Transition::ConfirmedNominated { values, quorum } => {
+                write!(f, "confirmed nominated values {:?} via quorum ", values)?;
+                return nodes(f, quorum);
+            },
+            Transition::AcceptedPrepared { ballot: b, via: v, nodes: n } => {
+                write!(f, "accepted prepared ballot {} ", ballot(b))?;
+                return via(f, *v, n);
+            },
+            Transition::ConfirmedPrepared { ballot: b, quorum } => {
+                write!(f, "confirmed prepared ballot {} via quorum ", ballot(b))?;
+                return nodes(f, quorum);
+            },
+            Transition::AcceptedCommit { ballot: b, via: v, nodes: n } => {
+                write!(f, "accepted commit ballot {} ", ballot(b))?;
+                return via(f, *v, n);
+            },
+            Transition::ConfirmedCommit { ballot: b, quorum } => {
+                write!(f, "confirmed commit ballot {} via quorum ", ballot(b))?;
+                return nodes(f, quorum);
+            },
+            Transition::Ballot { ballot: b, source } => {
+                return write!(f, "moved to ballot {} from {:?}", ballot(b), source);
+            },
+            Transition::Timeout { round, ballot: Some(b) } => {
+                return write!(f, "timed out, nomination round {}, bumped to ballot {}", round, ballot(b));
+            },
+            Transition::Timeout { round, ballot: None } => {
+                return write!(f, "timed out, nomination round {}", round);
+            },
+            Transition::Phase { from, to } => {
+                return write!(f, "moved from {:?} to {:?}", from, to);
+            },
+            Transition::Disagreement { ours, theirs, from } => {
+                return write!(f, "consensus failure! {} externalized {:?}, we externalized {:?}", from.as_str(), theirs, ours);
+            },
+        }
+    }
+}
+
+/// Somewhere to send [`Transition`]s to, see [`Node::set_logger`](crate::node::Node::set_logger).
+pub trait Logger<T: Value> {
+    fn log(&mut self, slot_id: SlotId, transition: Transition<T>);
+}
+
+/// Ignores everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoLogger;
+
+impl<T: Value> Logger<T> for NoLogger {
+    fn log(&mut self, _slot_id: SlotId, _transition: Transition<T>) {}
+}
+
+/// Prints every transition to stderr, one per line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stderr;
+
+impl<T: Value> Logger<T> for Stderr {
+    fn log(&mut self, slot_id: SlotId, transition: Transition<T>) {
+        eprintln!("slot {}: {}", slot_id.number(), transition);
+    }
+}
+
+/// Keeps everything, in order.
+impl<T: Value> Logger<T> for Vec<(SlotId, Transition<T>)> {
+    fn log(&mut self, slot_id: SlotId, transition: Transition<T>) {
+        self.push((slot_id, transition));
+    }
+}
+
+/// So a log can be read while a node is writing to it,
+/// hand the node a clone of an `Arc<Mutex<_>>`.
+impl<T: Value, L: Logger<T>> Logger<T> for Arc<Mutex<L>> {
+    fn log(&mut self, slot_id: SlotId, transition: Transition<T>) {
+        // a poisoned lock just means we lose some lines
+        if let Ok(mut logger) = self.lock() { logger.log(slot_id, transition); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{node_ids, DummyValue};
+
+    #[test]
+    fn display() {
+        let shared = Arc::new(Mutex::new(Vec::new()));
+        let mut logger = shared.clone();
+
+        logger.log(SlotId::new(1), Transition::AcceptedPrepared {
+            ballot: Ballot { number: 3, value: DummyValue(7) },
+            via:    Via::Blocking,
+            nodes:  node_ids(&["a", "b"]),
+        });
+        logger.log(SlotId::new(1), Transition::ConfirmedNominated {
+            values: vec![DummyValue(7)],
+            quorum: node_ids(&["a", "b", "c"]),
+        });
+        logger.log(SlotId::new(2), Transition::Timeout { round: 2, ballot: None });
+
+        let lines = shared.lock().unwrap().iter()
+            .map(|(slot_id, t)| format!("{}: {}", slot_id.number(), t))
+            .collect::<Vec<String>>();
+        assert_eq!(lines, vec![
+            "1: accepted prepared ballot (3, DummyValue(7)) because blocking set {a, b}",
+            "1: confirmed nominated values [DummyValue(7)] via quorum {a, b, c}",
+            "2: timed out, nomination round 2",
+        ]);
+    }
+}
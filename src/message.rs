@@ -1,4 +1,7 @@
+use std::hash::{Hash, Hasher};
+
 use crate::{
+    ballot::Ballot,
     quorum::Quorum,
     node::NodeId,
     slot::SlotId,
@@ -6,13 +9,31 @@ use crate::{
     value::Value,
 };
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Message<T: Value> {
-    counter:     usize,
-    pub sender:  NodeId,
-    pub slot_id: SlotId,
-    pub quorum:  Quorum<T>,
-    pub topic:   Topic<T>,
+    pub(crate) counter: usize,
+    pub sender:         NodeId,
+    pub slot_id:        SlotId,
+    pub quorum:         Quorum<T>,
+    pub topic:          Topic<T>,
+}
+
+// The counter only tells apart messages that are otherwise the same,
+// so it's left out of the hash. Equal messages still hash the same.
+impl<T: Value> Hash for Message<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sender.hash(state);
+        self.slot_id.hash(state);
+        self.quorum.hash(state);
+        self.topic.hash(state);
+    }
+}
+
+/// Whether `ballot` is at or below `other` and has the same value,
+/// so preparing `other` prepares `ballot` too.
+/// Nothing is below the zero ballot.
+fn below<T: Value>(ballot: &Ballot<T>, other: &Ballot<T>) -> bool {
+    return !other.is_zero() && ballot.value == other.value && ballot.number <= other.number;
 }
 
 impl<T: Value> Message<T> {
@@ -30,34 +51,211 @@ impl<T: Value> Message<T> {
     // TODO: better error types
 
     fn nominate_valid(t: &topic::Nominate<T>) -> Result<(), ()> {
-        // we just need 1 item for an intersection, then we return
-        for item in t.nominated.intersection(&t.accepted) { return Ok(()); }
-        panic!("Non-empty intersection between nominated and accepted"); // TODO: error
+        // accepted values are moved out of nominated,
+        // so a value can't be in both
+        return if t.nominated.is_disjoint(&t.accepted) { Ok(()) } else { Err(()) };
     }
 
     fn prepare_valid(t: &topic::Prepare<T>) -> Result<(), ()> {
-        // TODO: what about zero ballots?
+        // a zero ballot means we haven't started balloting, so we wouldn't send this
+        if t.ballot.is_zero()              { return Err(()); }
         if t.prepared_a >  t.ballot        { return Err(()); }
-        if t.prepared_b >= t.prepared_a    { return Err(()); }
+        // nothing prepared is written as two zero ballots
+        if !t.prepared_b.is_zero()
+        && t.prepared_b >= t.prepared_a    { return Err(()); }
         if t.lowest     >  t.highest       { return Err(()); }
         if t.highest    >  t.ballot.number { return Err(()); }
         return Ok(());
     }
 
     fn commit_valid(t: &topic::Commit<T>) -> Result<(), ()> {
+        if t.ballot.is_zero() { return Err(()); }
         return if t.lowest > t.highest { Err(()) } else { Ok(()) };
     }
 
+    fn externalize_valid(t: &topic::Externalize<T>) -> Result<(), ()> {
+        return if t.ballot.is_zero() { Err(()) } else { Ok(()) };
+    }
+
+    /// Whether this message accepts `value` as nominated.
+    pub fn accepts_nominated(&self, value: &T) -> bool {
+        return match &self.topic {
+            Topic::Nominate(n) => n.accepted.contains(value),
+            _                  => false,
+        };
+    }
+
+    /// Whether this message votes for or accepts `value` as nominated.
+    pub fn votes_or_accepts_nominated(&self, value: &T) -> bool {
+        return match &self.topic {
+            Topic::Nominate(n) => n.nominated.contains(value) || n.accepted.contains(value),
+            _                  => false,
+        };
+    }
+
+    // Commit and externalize statements vote to prepare every ballot with their value,
+    // as if they'd prepared the ballot (∞, x).
+
+    /// Whether this message accepts `ballot` as prepared.
+    pub fn accepts_prepared(&self, ballot: &Ballot<T>) -> bool {
+        return match &self.topic {
+            Topic::Prepare(p)     => below(ballot, &p.prepared_a) || below(ballot, &p.prepared_b),
+            Topic::Commit(c)      => c.ballot.value == ballot.value && ballot.number <= c.prepared,
+            Topic::Externalize(e) => e.ballot.value == ballot.value,
+            Topic::Nominate(_)    => false,
+        };
+    }
+
+    /// Whether this message votes for or accepts `ballot` as prepared.
+    pub fn votes_or_accepts_prepared(&self, ballot: &Ballot<T>) -> bool {
+        return match &self.topic {
+            Topic::Prepare(p)     => below(ballot, &p.ballot) || self.accepts_prepared(ballot),
+            Topic::Commit(c)      => c.ballot.value == ballot.value,
+            Topic::Externalize(e) => e.ballot.value == ballot.value,
+            Topic::Nominate(_)    => false,
+        };
+    }
+
+    /// Whether this message accepts committing every ballot `(n, value)`
+    /// with `lowest <= n <= highest`.
+    pub fn accepts_commit(&self, value: &T, lowest: usize, highest: usize) -> bool {
+        return match &self.topic {
+            Topic::Commit(c)      => c.ballot.value == *value && c.lowest <= lowest && highest <= c.highest,
+            Topic::Externalize(e) => e.ballot.value == *value && e.ballot.number <= lowest,
+            _                     => false,
+        };
+    }
+
+    /// Whether this message votes for or accepts committing every ballot `(n, value)`
+    /// with `lowest <= n <= highest`.
+    pub fn votes_or_accepts_commit(&self, value: &T, lowest: usize, highest: usize) -> bool {
+        return match &self.topic {
+            Topic::Prepare(p)     => p.lowest != 0
+                && p.ballot.value == *value
+                && p.lowest <= lowest && highest <= p.highest,
+            Topic::Commit(c)      => c.ballot.value == *value && c.lowest <= lowest,
+            Topic::Externalize(e) => e.ballot.value == *value && e.ballot.number <= lowest,
+            Topic::Nominate(_)    => false,
+        };
+    }
+
+    /// The value a topic commits to, if any.
+    /// Once a node votes to commit a value, it can't take it back.
+    fn committed(topic: &Topic<T>) -> Option<&T> {
+        return match topic {
+            Topic::Commit(c)      => Some(&c.ballot.value),
+            Topic::Externalize(e) => Some(&e.ballot.value),
+            _                     => None,
+        };
+    }
+
+    /// Whether this message and `other` can't both have been sent by an honest node,
+    /// i.e. committing to two different values for the same slot.
+    /// Messages from different senders or about different slots never contradict.
+    // Counters aren't compared: each slot counts its own messages from zero,
+    // so an honest node sends plenty of different messages with the same counter.
+    pub fn contradicts(&self, other: &Message<T>) -> bool {
+        if self.sender != other.sender || self.slot_id != other.slot_id { return false; }
+
+        return match (Message::committed(&self.topic), Message::committed(&other.topic)) {
+            (Some(a), Some(b)) => a != b,
+            _                  => false,
+        };
+    }
+
     pub fn valid(&self) -> Result<(), ()> {
-        return match self.topic {
-            Topic::Nominate(n) => Message::nominate_valid(&n),
-            Topic::NominatePrepare(n, p) => {
-                Message::nominate_valid(&n)?;
-                Message::prepare_valid(&p)
-            },
-            Topic::Prepare(p)     => Message::prepare_valid(&p),
-            Topic::Commit(c)      => Message::commit_valid(&c),
-            Topic::Externalize(_) => Ok(()),
+        self.quorum.valid()?;
+
+        return match &self.topic {
+            Topic::Nominate(n) => Message::nominate_valid(n),
+            Topic::Prepare(p)     => Message::prepare_valid(p),
+            Topic::Commit(c)      => Message::commit_valid(c),
+            Topic::Externalize(e) => Message::externalize_valid(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arbitrary::{Arbitrary, Source},
+        fixtures::{node_id, DummyValue},
+    };
+
+    #[test]
+    fn valid_upholds_invariants() {
+        for seed in 0..5000 {
+            let message = Message::<DummyValue>::arbitrary(&mut Source::new(seed));
+            if message.valid().is_err() { continue; }
+
+            match &message.topic {
+                Topic::Nominate(n) => assert!(n.nominated.is_disjoint(&n.accepted)),
+                Topic::Prepare(p) => {
+                    assert!(!p.ballot.is_zero());
+                    assert!(p.prepared_a <= p.ballot);
+                    assert!(p.prepared_b.is_zero() || p.prepared_b < p.prepared_a, "{:?}", p);
+                    assert!(p.lowest <= p.highest);
+                },
+                Topic::Commit(c)      => assert!(c.lowest <= c.highest),
+                Topic::Externalize(e) => assert!(!e.ballot.is_zero()),
+            }
         }
     }
+
+    #[test]
+    fn nothing_prepared_is_valid() {
+        let zero = Ballot { number: 0, value: DummyValue(0) };
+        let topic = Topic::Prepare(topic::Prepare {
+            ballot:     Ballot { number: 1, value: DummyValue(0) },
+            prepared_a: zero.clone(),
+            prepared_b: zero,
+            highest:    0,
+            lowest:     0,
+        });
+        let message = Message::new(node_id("a"), SlotId::new(0), Quorum::new(0, vec![]), topic, &mut 0);
+        assert_eq!(message.valid(), Ok(()));
+    }
+
+    fn message(sender: &str, slot_id: usize, topic: Topic<DummyValue>) -> Message<DummyValue> {
+        Message::new(node_id(sender), SlotId::new(slot_id), Quorum::new(0, vec![]), topic, &mut 0)
+    }
+
+    fn commit(value: usize) -> Topic<DummyValue> {
+        Topic::Commit(topic::Commit { ballot: Ballot { number: 1, value: DummyValue(value) }, prepared: 1, highest: 1, lowest: 1 })
+    }
+
+    fn externalize(value: usize) -> Topic<DummyValue> {
+        Topic::Externalize(topic::Externalize { ballot: Ballot { number: 1, value: DummyValue(value) }, highest: 1 })
+    }
+
+    fn nominate(values: &[usize]) -> Topic<DummyValue> {
+        Topic::Nominate(topic::Nominate {
+            nominated: values.iter().map(|v| DummyValue(*v)).collect(),
+            accepted:  Default::default(),
+        })
+    }
+
+    #[test]
+    fn committing_twice_contradicts() {
+        assert!(message("a", 0, commit(1)).contradicts(&message("a", 0, commit(2))));
+        assert!(message("a", 0, commit(1)).contradicts(&message("a", 0, externalize(2))));
+        assert!(message("a", 0, externalize(1)).contradicts(&message("a", 0, externalize(2))));
+
+        // committing and then externalizing the same value is just progress
+        assert!(!message("a", 0, commit(1)).contradicts(&message("a", 0, externalize(1))));
+        // different senders or slots can disagree all they like
+        assert!(!message("a", 0, commit(1)).contradicts(&message("b", 0, commit(2))));
+        assert!(!message("a", 0, commit(1)).contradicts(&message("a", 1, commit(2))));
+    }
+
+    #[test]
+    fn sharing_a_counter_doesnt_contradict() {
+        // both have counter 1, like the first messages a node and its slot send
+        let first = message("a", 0, nominate(&[1]));
+        let second = message("a", 0, nominate(&[1, 2]));
+        assert_eq!(first.counter, second.counter);
+        assert!(!first.contradicts(&second));
+        assert!(!first.contradicts(&message("a", 0, commit(1))));
+    }
 }
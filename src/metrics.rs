@@ -0,0 +1,139 @@
+//! Hooks for monitoring how consensus is doing.
+//! A [`Node`] reports a [`Metric`] to its [`Metrics`] whenever something worth counting happens;
+//! slots collect theirs as they go, and the node passes them along.
+//! Where they end up (Prometheus, StatsD, a log line) is up to you,
+//! this crate doesn't depend on any of them.
+//! [`NoMetrics`] drops everything, and is the default.
+//! [`InMemory`] adds everything up, which is handy for tests and simple status pages.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    slot::Phase,
+    topic::Kind,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// A slot was externalized, this long after it was created.
+    Externalized { latency: Duration },
+    /// A slot moved out of a phase, after spending this long in it.
+    Phase { phase: Phase, duration: Duration },
+    /// A slot's ballot counter was bumped because its timer fired.
+    BallotBumped,
+    /// A slot moved on to the next round of nomination.
+    NominationRound,
+    Received(Kind),
+    Sent(Kind),
+    /// An inbound message was invalid, or couldn't be handled.
+    Rejected,
+    /// A quorum or v-blocking set search took this long.
+    QuorumSearch { duration: Duration },
+}
+
+/// Somewhere to report [`Metric`]s to, see [`Node::set_metrics`].
+pub trait Metrics {
+    fn record(&mut self, metric: Metric);
+}
+
+/// Ignores everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics {
+    fn record(&mut self, _metric: Metric) {}
+}
+
+/// So metrics can be read while a node is writing to them,
+/// hand the node a clone of an `Arc<Mutex<_>>`.
+impl<M: Metrics> Metrics for Arc<Mutex<M>> {
+    fn record(&mut self, metric: Metric) {
+        // a poisoned lock just means we lose some numbers
+        if let Ok(mut metrics) = self.lock() { metrics.record(metric); }
+    }
+}
+
+/// How many times something took some time, and how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Summary {
+    pub count: usize,
+    pub total: Duration,
+    pub min:   Duration,
+    pub max:   Duration,
+}
+
+impl Summary {
+    pub fn add(&mut self, duration: Duration) {
+        if self.count == 0 || duration < self.min { self.min = duration; }
+        if duration > self.max { self.max = duration; }
+        self.count += 1;
+        self.total += duration;
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 { return None; }
+        return Some(self.total / self.count as u32);
+    }
+}
+
+/// Adds up every [`Metric`] it's given.
+#[derive(Debug, Clone, Default)]
+pub struct InMemory {
+    pub externalized:      Summary,
+    pub phases:            HashMap<Phase, Summary>,
+    pub ballot_bumps:      usize,
+    pub nomination_rounds: usize,
+    pub received:          HashMap<Kind, usize>,
+    pub sent:              HashMap<Kind, usize>,
+    pub rejected:          usize,
+    pub quorum_search:     Summary,
+}
+
+impl InMemory {
+    pub fn new() -> InMemory {
+        return InMemory::default();
+    }
+}
+
+impl Metrics for InMemory {
+    fn record(&mut self, metric: Metric) {
+        match metric {
+            Metric::Externalized { latency }  => self.externalized.add(latency),
+            Metric::Phase { phase, duration } => self.phases.entry(phase).or_default().add(duration),
+            Metric::BallotBumped              => self.ballot_bumps += 1,
+            Metric::NominationRound           => self.nomination_rounds += 1,
+            Metric::Received(kind)            => *self.received.entry(kind).or_default() += 1,
+            Metric::Sent(kind)                => *self.sent.entry(kind).or_default() += 1,
+            Metric::Rejected                  => self.rejected += 1,
+            Metric::QuorumSearch { duration } => self.quorum_search.add(duration),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_memory() {
+        let shared = Arc::new(Mutex::new(InMemory::new()));
+        let mut metrics = shared.clone();
+
+        metrics.record(Metric::Received(Kind::Prepare));
+        metrics.record(Metric::Received(Kind::Prepare));
+        metrics.record(Metric::Rejected);
+        metrics.record(Metric::QuorumSearch { duration: Duration::from_millis(3) });
+        metrics.record(Metric::QuorumSearch { duration: Duration::from_millis(1) });
+
+        let totals = shared.lock().unwrap();
+        assert_eq!(totals.received[&Kind::Prepare], 2);
+        assert_eq!(totals.rejected, 1);
+        assert_eq!(totals.quorum_search.min, Duration::from_millis(1));
+        assert_eq!(totals.quorum_search.max, Duration::from_millis(3));
+        assert_eq!(totals.quorum_search.mean(), Some(Duration::from_millis(2)));
+    }
+}
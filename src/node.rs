@@ -1,11 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     value::Value,
     quorum::Quorum,
-    slot::{Slot, SlotId},
+    slot::{Context, Slot, SlotId, SlotInfo},
     topic::{self, Topic},
     message::Message,
+    predicate::FnPredicate,
+    storage::{Storage, Retention, Usage},
+    evidence::{Disagreement, Equivocation},
+    index::Statements,
+    trace::{Recorder, Event},
+    metrics::{Metrics, Metric, NoMetrics},
+    log::{Logger, Transition, NoLogger},
+    catchup,
 };
 
 // TODO: make NodeId something unique, like a public key,
@@ -14,19 +22,54 @@ use crate::{
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct NodeId(String);
 
-// TODO: wrap in Rc or something with SlotId as weakref
-// because they both try to hold reference to each other
-// "But Isaac!" I hear you say, "Rc is *slow*"
-// "Not to worry" I console,
-// "We'll not use Rc and instead, idk, remove the backref from Slot
-// and have nodes be passed in as a function paramater."
-// (or something).
+impl NodeId {
+    pub fn new(id: String) -> NodeId {
+        return NodeId(id);
+    }
+
+    pub fn as_str(&self) -> &str {
+        return &self.0;
+    }
+}
 
+/// Runs any number of slots side by side, each with its own messages and timer.
+/// Slots don't point back at their node: we lend them our id and quorum set
+/// as a [`Context`] whenever they act.
 pub struct Node<T: Value> {
     pub id:       NodeId,
     pub quorum:   Quorum<T>,
     pending:      HashMap<SlotId, Slot<T>>,
     externalized: HashMap<SlotId, topic::Externalize<T>>,
+    counter:      usize,
+
+    /// The latest [`Topic::Externalize`] message we've heard from each peer,
+    /// by slot. Used to prove our own decisions to peers that have fallen behind.
+    heard: HashMap<SlotId, Statements<T>>,
+    /// The highest slot each member of our quorum set says it externalized,
+    /// however far ahead of us that is. Used to notice when we've fallen behind.
+    claimed: Statements<T>,
+
+    /// How much we hold on to in memory, see [`Node::prune`].
+    pub retention: Retention,
+    storage:       Option<Box<dyn Storage<T>>>,
+    latest:        Option<SlotId>,
+    /// The newest slot evicted to storage.
+    /// Anything at or below this that isn't in memory is done with.
+    floor:         Option<SlotId>,
+    /// Externalized slots whose proof is already in storage, see [`Node::persist`].
+    stored:        HashSet<SlotId>,
+
+    /// Peers caught contradicting themselves, see [`Node::take_evidence`].
+    evidence: Vec<Equivocation<T>>,
+    /// Peers that externalized something else, see [`Node::take_disagreements`].
+    disagreements: Vec<Disagreement<T>>,
+
+    /// Where to record what we see and say, see [`Node::set_recorder`].
+    recorder: Option<Box<dyn Recorder<T>>>,
+    /// Where to report how we're doing, see [`Node::set_metrics`].
+    metrics:  Box<dyn Metrics>,
+    /// Where to explain what our slots do, see [`Node::set_logger`].
+    logger:   Box<dyn Logger<T>>,
 
     /// A fraction from 0/255 (never) to 255/255 (always) that represents
     /// the chance of a message being ignored. Used for testing.
@@ -36,7 +79,8 @@ pub struct Node<T: Value> {
 impl<T: Value> Node<T> {
     /// Build a new node.
     /// We explicitly pass in `externalized`
-    /// so we can recover from disk, say.
+    /// so we can recover from disk, say
+    /// (though see [`Node::restore`], which checks the proofs first).
     /// We don't pass in `pending`,
     /// Because an in-progrees slot shouldn't really exist
     /// outside of a running program.
@@ -46,80 +90,749 @@ impl<T: Value> Node<T> {
         quorum:       Quorum<T>,
         externalized: HashMap<SlotId, topic::Externalize<T>>,
     ) -> Node<T> {
+        let latest = externalized.keys().max().copied();
+
         return Node {
             id,
             quorum,
             pending: HashMap::new(),
             externalized,
+            counter: 0,
+            heard: HashMap::new(),
+            claimed: Statements::new(),
+            retention: Retention::default(),
+            storage: None,
+            latest,
+            floor: None,
+            stored: HashSet::new(),
+            evidence: vec![],
+            disagreements: vec![],
+            recorder: None,
+            metrics: Box::new(NoMetrics),
+            logger: Box::new(NoLogger),
             _fake_drop: 0
         };
     }
 
-    pub fn run(&mut self, context: ()) {
-        // delay until
-        todo!();
+    /// Sets where externalized slots are kept, once proven or evicted from memory.
+    /// Without storage, evicted slots are simply forgotten.
+    pub fn set_storage(&mut self, storage: Box<dyn Storage<T>>) {
+        self.storage = Some(storage);
+    }
+
+    /// Records every inbound message, timeout, proposal, and outbound message from now on,
+    /// so the run can be replayed later with [`trace::replay`](crate::trace::replay).
+    pub fn set_recorder(&mut self, recorder: Box<dyn Recorder<T>>) {
+        self.recorder = Some(recorder);
+    }
 
-        // loop {
-        //     // read the next command
-        //     match command {
-        //         Command::Message  => (),
-        //         Command::Delay    => (),
-        //         Command::Defer    => (),
-        //         Command::NewRound => (),
-        //         Command::Rehandle => (),
-        //     }
-        // }
+    /// Reports metrics from now on, instead of dropping them.
+    pub fn set_metrics(&mut self, metrics: Box<dyn Metrics>) {
+        self.metrics = metrics;
+    }
+
+    /// Logs every state transition our slots make from now on, instead of dropping them.
+    pub fn set_logger(&mut self, logger: Box<dyn Logger<T>>) {
+        self.logger = logger;
+    }
+
+    fn record(&mut self, event: impl FnOnce() -> Event<T>) {
+        if let Some(recorder) = &mut self.recorder { recorder.record(event()); }
+    }
+
+    /// Records what we're sending, both in the trace and in the metrics.
+    fn record_outbound(&mut self, outbound: &[Message<T>]) {
+        for message in outbound.iter() {
+            self.record(|| Event::Outbound(message.clone()));
+            self.metrics.record(Metric::Sent(message.topic.kind()));
+        }
     }
 
     // TODO: have the return result be our response.
     // TODO: clean up logic around externalized messages.
 
-    /// Handles a message, optionally returning a response.
-    pub fn handle(&mut self, message: &Message<T>) -> Result<Option<Message<T>>, ()> {
+    /// Handles a message, returning any responses.
+    /// There can be more than one, because nomination and balloting
+    /// each send their own statements.
+    pub fn handle(&mut self, message: &Message<T>) -> Result<Vec<Message<T>>, ()> {
+        self.record(|| Event::Inbound(message.clone()));
+        self.metrics.record(Metric::Received(message.topic.kind()));
+
+        let outbound = match self.handle_message(message) {
+            Ok(outbound) => outbound,
+            Err(())      => { self.metrics.record(Metric::Rejected); return Err(()); },
+        };
+
+        self.record_outbound(&outbound);
+        return Ok(outbound);
+    }
+
+    /// Fires the timer for a pending slot,
+    /// returning anything the slot wants to send because of it.
+    pub fn timeout(&mut self, slot_id: SlotId) -> Vec<Message<T>> {
+        self.record(|| Event::Timeout(slot_id));
+        let context = Context { node_id: &self.id, quorum: &self.quorum };
+        let outbound = match self.pending.get_mut(&slot_id) {
+            Some(slot) => {
+                let outbound = slot.timeout(context);
+                for metric in slot.take_metrics() { self.metrics.record(metric); }
+                for transition in slot.take_transitions() { self.logger.log(slot_id, transition); }
+                outbound
+            },
+            None => vec![],
+        };
+        self.record_outbound(&outbound);
+        return outbound;
+    }
+
+    /// Votes to nominate `value` for a slot, starting the slot if we haven't already.
+    /// Does nothing if the slot's already externalized or too old to hold in memory.
+    pub fn propose(&mut self, slot_id: SlotId, value: T) -> Vec<Message<T>> {
+        self.record(|| Event::Propose(slot_id, value.clone()));
+        if self.externalized.contains_key(&slot_id) || self.stale(slot_id) { return vec![]; }
+
+        let slot = self.pending
+            .entry(slot_id)
+            .or_insert_with(|| Slot::new(slot_id));
+
+        let context = Context { node_id: &self.id, quorum: &self.quorum };
+        let outbound = slot.propose(context, value);
+        for metric in slot.take_metrics() { self.metrics.record(metric); }
+        for transition in slot.take_transitions() { self.logger.log(slot_id, transition); }
+
+        for sent in outbound.iter() {
+            if let Topic::Externalize(e) = &sent.topic {
+                self.externalize(sent.slot_id, e.clone());
+            }
+        }
+
+        self.record_outbound(&outbound);
+        return outbound;
+    }
+
+    fn handle_message(&mut self, message: &Message<T>) -> Result<Vec<Message<T>>, ()> {
+        // nothing we hold on to gets taken on faith
+        message.valid()?;
+
+        // before anything else, so we notice we're behind however far behind we are
+        self.note_claim(message);
+
+        // we've moved on from this slot and no longer hold it in memory
+        if !self.externalized.contains_key(&message.slot_id) && self.stale(message.slot_id) {
+            return Ok(self.handle_stale(message).into_iter().collect());
+        }
+
+        // or we're nowhere near it yet
+        if self.ahead(message.slot_id) { return Ok(vec![]); }
+
+        // remember who externalized what, for catching up later
+        if let Topic::Externalize(_) = &message.topic {
+            let heard = self.heard
+                .entry(message.slot_id)
+                .or_default();
+
+            if let Some(previous) = heard.get(&message.sender) {
+                if previous.contradicts(message) {
+                    self.evidence.push(Equivocation::new(previous.clone(), message.clone()));
+                    return Ok(vec![]);
+                }
+            }
+
+            heard.insert(message.clone());
+        }
+
         // we've already externalized the topic, so we don't need to do any more thinking
         // (unless someone else messaged us they externalized the topic as well)
         if let Some(externalized) = self.externalized.get(&message.slot_id) {
-            if let Topic::Externalize(e) = message.topic {
+            if let Topic::Externalize(e) = &message.topic {
                 // the externalized value disagrees with what we think! oh no!
+                // anyone can send us this, so we note it down and move on
                 if externalized.ballot.value != e.ballot.value {
-                    eprintln!(
-                        "Ahh! consensus failure! Inbound {:?} disagrees with own {:?}",
-                        e.ballot.value,
-                        externalized.ballot.value
-                    );
-                    panic!();
+                    let ours = externalized.clone();
+                    self.disagree(message.slot_id, &message.sender, ours, e.clone());
+                    return Err(());
                 }
+                // their statement might be all our proof was missing
+                self.persist(message.slot_id);
             } else {
-                return Ok(Some(Message::new(
-                    self.id,
+                return Ok(vec![Message::new(
+                    self.id.clone(),
                     message.slot_id,
-                    self.quorum,
-                    Topic::Externalize(*externalized),
-                    todo!(),
-                )));
+                    self.quorum.clone(),
+                    Topic::Externalize(externalized.clone()),
+                    &mut self.counter,
+                )]);
             }
-            return Ok(None);
+            return Ok(vec![]);
         }
 
         // create a new slot if we haven't already
-        let slot = match self.pending.get_mut(&message.slot_id) {
-            Some(s) => s,
-            None => {
-                let slot = Slot::new(message.slot_id, self);
-                self.pending.insert(message.slot_id, slot);
-                slot
-            },
-        };
+        let slot = self.pending
+            .entry(message.slot_id)
+            .or_insert_with(|| Slot::new(message.slot_id));
 
         // run consensus and handle the message
-        let outbound = slot.handle(message)?;
+        let context = Context { node_id: &self.id, quorum: &self.quorum };
+        let outbound = slot.handle(context, message.clone());
+        self.evidence.append(&mut slot.take_evidence());
+        for metric in slot.take_metrics() { self.metrics.record(metric); }
+        for transition in slot.take_transitions() { self.logger.log(message.slot_id, transition); }
+        let outbound = outbound?;
 
         // if the slot was externalized, move it to the externalized set
-        if let Some(Topic::Externalize(e)) = outbound.topic {
-            self.externalized.insert(slot.id, e);
-            self.pending.remove(slot.id);
+        for sent in outbound.iter() {
+            if let Topic::Externalize(e) = &sent.topic {
+                self.externalize(sent.slot_id, e.clone());
+            }
         }
 
-        return outbound;
+        return Ok(outbound);
+    }
+
+    /// Moves a slot from pending to externalized,
+    /// pruning anything we no longer need to hold on to.
+    fn externalize(&mut self, slot_id: SlotId, externalize: topic::Externalize<T>) {
+        self.externalized.insert(slot_id, externalize);
+        if let Some(slot) = self.pending.remove(&slot_id) {
+            self.metrics.record(Metric::Externalized { latency: slot.created().elapsed() });
+        }
+        if Some(slot_id) > self.latest { self.latest = Some(slot_id); }
+        self.persist(slot_id);
+        self.prune();
+    }
+
+    /// Writes a slot's proof to storage as soon as it holds up,
+    /// so what we've decided survives a restart however much we keep in memory.
+    /// Our own statement doesn't count towards it,
+    /// so this is tried again as our peers' statements come in.
+    fn persist(&mut self, slot_id: SlotId) {
+        if self.storage.is_none() || self.stored.contains(&slot_id) { return; }
+
+        let proof = match self.proof(slot_id) {
+            Some(proof) if proof.verify(&self.id, &self.quorum).is_ok() => proof,
+            _ => { return; },
+        };
+        if let Some(storage) = &mut self.storage { storage.store(proof); }
+        self.stored.insert(slot_id);
+    }
+
+    /// Remembers the highest slot a member of our quorum set says it externalized.
+    /// Nobody else can make us think we're behind, so nobody else is kept,
+    /// and only one message each.
+    fn note_claim(&mut self, message: &Message<T>) {
+        if !matches!(message.topic, Topic::Externalize(_)) { return; }
+        if !self.quorum.nodes().contains(&message.sender) { return; }
+
+        let higher = !matches!(self.claimed.get(&message.sender), Some(m) if m.slot_id >= message.slot_id);
+        if higher { self.claimed.insert(message.clone()); }
+    }
+
+    /// Whether a slot is too old to be held in memory,
+    /// either because it was evicted to storage,
+    /// or because it fell too far behind the latest externalized slot.
+    fn stale(&self, slot_id: SlotId) -> bool {
+        if Some(slot_id) <= self.floor { return true; }
+        return match self.latest {
+            Some(latest) => slot_id.lag(latest) > self.retention.pending,
+            None         => false,
+        };
+    }
+
+    /// Whether a slot is further past the latest externalized slot than [`Retention::ahead`].
+    fn ahead(&self, slot_id: SlotId) -> bool {
+        let latest = self.latest.unwrap_or(SlotId::new(0));
+        return latest.lag(slot_id) > self.retention.ahead;
+    }
+
+    /// Answers a message about a stale slot from storage.
+    /// Like [`Node::handle`], we only reply if the peer hasn't externalized yet.
+    fn handle_stale(&mut self, message: &Message<T>) -> Option<Message<T>> {
+        if let Topic::Externalize(_) = &message.topic { return None; }
+
+        let proof = self.storage.as_ref()?.load(message.slot_id)?;
+        return Some(Message::new(
+            self.id.clone(),
+            message.slot_id,
+            self.quorum.clone(),
+            Topic::Externalize(proof.externalize),
+            &mut self.counter,
+        ));
+    }
+
+    /// Evicts externalized slots beyond the most recent [`Retention::externalized`] to storage,
+    /// and purges pending slots more than [`Retention::pending`] slots behind
+    /// the latest externalized one.
+    /// This is called every time a slot is externalized,
+    /// but can be called again after changing [`Node::retention`].
+    pub fn prune(&mut self) {
+        let latest = match self.latest {
+            Some(l) => l,
+            None    => { return; },
+        };
+
+        // evict the oldest externalized slots
+        if self.externalized.len() > self.retention.externalized {
+            let mut slot_ids = self.externalized.keys().copied().collect::<Vec<SlotId>>();
+            slot_ids.sort();
+            let evict = slot_ids.len() - self.retention.externalized;
+
+            for slot_id in slot_ids.into_iter().take(evict) {
+                // if it's not stored yet, this is the last chance,
+                // even if the proof wouldn't convince anyone
+                let proof = if self.stored.remove(&slot_id) { None } else { self.proof(slot_id) };
+                self.externalized.remove(&slot_id);
+                self.heard.remove(&slot_id);
+                if Some(slot_id) > self.floor { self.floor = Some(slot_id); }
+
+                if let (Some(storage), Some(proof)) = (&mut self.storage, proof) {
+                    storage.store(proof);
+                }
+            }
+        }
+
+        // give up on pending slots that have fallen too far behind
+        let window = self.retention.pending;
+        self.pending.retain(|slot_id, _| slot_id.lag(latest) <= window);
+
+        let externalized = &self.externalized;
+        self.heard.retain(|slot_id, _| {
+            externalized.contains_key(slot_id) || slot_id.lag(latest) <= window
+        });
+    }
+
+    /// Logs and keeps a [`Disagreement`], once per peer and slot.
+    fn disagree(
+        &mut self,
+        slot_id: SlotId,
+        from:    &NodeId,
+        ours:    topic::Externalize<T>,
+        theirs:  topic::Externalize<T>,
+    ) {
+        if self.disagreements.iter().any(|d| d.slot_id == slot_id && &d.from == from) { return; }
+
+        self.logger.log(slot_id, Transition::Disagreement {
+            ours:   ours.ballot.value.clone(),
+            theirs: theirs.ballot.value.clone(),
+            from:   from.clone(),
+        });
+        self.disagreements.push(Disagreement { slot_id, from: from.clone(), ours, theirs });
+    }
+
+    /// Peers that say a slot externalized something other than what we did.
+    /// Kept until taken with [`Node::take_disagreements`].
+    pub fn disagreements(&self) -> &[Disagreement<T>] {
+        return &self.disagreements;
+    }
+
+    /// Takes all the disagreements noted so far.
+    pub fn take_disagreements(&mut self) -> Vec<Disagreement<T>> {
+        return std::mem::take(&mut self.disagreements);
+    }
+
+    /// Evidence of peers equivocating, i.e. making statements that contradict each other.
+    /// Kept until taken with [`Node::take_evidence`].
+    pub fn evidence(&self) -> &[Equivocation<T>] {
+        return &self.evidence;
+    }
+
+    /// Takes all the evidence of equivocation gathered so far.
+    pub fn take_evidence(&mut self) -> Vec<Equivocation<T>> {
+        return std::mem::take(&mut self.evidence);
+    }
+
+    /// How much this node is currently holding in memory.
+    pub fn usage(&self) -> Usage {
+        let slot_messages = self.pending.values()
+            .map(|slot| slot.messages_held())
+            .sum::<usize>();
+        let heard_messages = self.heard.values()
+            .map(|heard| heard.len())
+            .sum::<usize>();
+
+        return Usage {
+            pending:      self.pending.len(),
+            externalized: self.externalized.len(),
+            messages:     slot_messages + heard_messages + self.claimed.len(),
+        };
+    }
+
+    /// What we externalized for a slot, if we did and still have it in memory.
+    pub fn externalized(&self, slot_id: SlotId) -> Option<&topic::Externalize<T>> {
+        return self.externalized.get(&slot_id);
+    }
+
+    /// Whether a slot has been started but not externalized.
+    pub fn is_pending(&self, slot_id: SlotId) -> bool {
+        return self.pending.contains_key(&slot_id);
+    }
+
+    /// A snapshot of a pending slot, see [`Slot::info`].
+    pub fn slot_info(&self, slot_id: SlotId) -> Option<SlotInfo<T>> {
+        let context = Context { node_id: &self.id, quorum: &self.quorum };
+        return self.pending.get(&slot_id).map(|slot| slot.info(context));
+    }
+
+    /// Every pending slot, oldest first.
+    /// Cheaper than [`Node::slots_info`] when that's all you need.
+    pub fn pending_slot_ids(&self) -> Vec<SlotId> {
+        let mut slot_ids = self.pending.keys().copied().collect::<Vec<SlotId>>();
+        slot_ids.sort();
+        return slot_ids;
+    }
+
+    /// Snapshots of every pending slot, oldest first.
+    pub fn slots_info(&self) -> Vec<SlotInfo<T>> {
+        let context = Context { node_id: &self.id, quorum: &self.quorum };
+        let mut slots = self.pending.values()
+            .map(|slot| slot.info(context))
+            .collect::<Vec<SlotInfo<T>>>();
+        slots.sort_by_key(|info| info.slot_id);
+        return slots;
+    }
+
+    /// The most recent slot we've externalized, if any.
+    pub fn latest_externalized(&self) -> Option<SlotId> {
+        return self.latest;
+    }
+
+    /// Returns the latest slot that a v-blocking set of our peers
+    /// has externalized (or gone past) and we haven't.
+    /// A v-blocking set can't be made up entirely of faulty nodes
+    /// (if it is, we have bigger problems),
+    /// so if one says a slot is decided, we've fallen behind.
+    pub fn behind(&self) -> Option<SlotId> {
+        let latest = self.latest_externalized();
+
+        let mut slot_ids = self.claimed.values()
+            .map(|m| m.slot_id)
+            .filter(|s| Some(*s) > latest)
+            .collect::<Vec<SlotId>>();
+        slot_ids.sort();
+        slot_ids.dedup();
+
+        // newest first, we only care about the latest one
+        for slot_id in slot_ids.into_iter().rev() {
+            let (blocking, _) = self.quorum.find_blocking(
+                &self.claimed,
+                FnPredicate::new(move |m: &Message<T>| m.slot_id >= slot_id),
+            );
+            if !blocking.is_empty() { return Some(slot_id); }
+        }
+
+        return None;
+    }
+
+    /// Builds a [`catchup::Request`] for every slot we've missed,
+    /// if we've fallen behind.
+    pub fn catch_up(&self) -> Option<catchup::Request> {
+        let to = self.behind()?;
+        return Some(catchup::Request {
+            sender: self.id.clone(),
+            after:  self.latest_externalized(),
+            to,
+        });
+    }
+
+    /// Answers a [`catchup::Request`] from a peer that has fallen behind,
+    /// with a [`catchup::Proof`] for each requested slot we've externalized,
+    /// whether it's still in memory or evicted to storage.
+    pub fn handle_catch_up(&mut self, request: &catchup::Request) -> catchup::Response<T> {
+        let mut proofs = vec![];
+
+        // evicted slots are all older than the ones in memory
+        if let (Some(storage), Some(floor)) = (&self.storage, self.floor) {
+            let from = request.after.map_or(0, |after| after.number().saturating_add(1));
+            let to = request.to.min(floor).number();
+            if from <= to {
+                proofs.extend((from..=to)
+                    .take(catchup::MAX_PROOFS)
+                    .filter_map(|n| storage.load(SlotId::new(n))));
+            }
+        }
+
+        let mut slot_ids = self.externalized.keys()
+            .filter(|s| Some(**s) > request.after && **s <= request.to)
+            .copied()
+            .collect::<Vec<SlotId>>();
+        slot_ids.sort();
+        slot_ids.truncate(catchup::MAX_PROOFS.saturating_sub(proofs.len()));
+
+        for slot_id in slot_ids {
+            if let Some(proof) = self.proof(slot_id) { proofs.push(proof); }
+        }
+
+        return catchup::Response { sender: self.id.clone(), proofs };
+    }
+
+    /// Builds a [`catchup::Proof`] for a slot we've externalized,
+    /// out of our own statement and every statement we've heard that agrees with it.
+    fn proof(&mut self, slot_id: SlotId) -> Option<catchup::Proof<T>> {
+        let externalize = self.externalized.get(&slot_id)?.clone();
+
+        // only pass along statements that agree with us
+        let mut messages = match self.heard.get(&slot_id) {
+            Some(heard) => heard.values()
+                .filter(|m| m.sender != self.id)
+                .filter(|m| match &m.topic {
+                    Topic::Externalize(e) => e.ballot.value == externalize.ballot.value,
+                    _ => false,
+                })
+                .cloned()
+                .collect::<Vec<Message<T>>>(),
+            None => vec![],
+        };
+
+        messages.push(Message::new(
+            self.id.clone(),
+            slot_id,
+            self.quorum.clone(),
+            Topic::Externalize(externalize.clone()),
+            &mut self.counter,
+        ));
+
+        return Some(catchup::Proof { slot_id, externalize, messages });
+    }
+
+    /// Picks up slots we externalized before, e.g. from storage after a restart.
+    /// The proofs are verified and adopted like a [`catchup::Response`] from ourselves,
+    /// so only the most recent [`Retention::externalized`] stay in memory.
+    pub fn restore(&mut self, mut proofs: Vec<catchup::Proof<T>>) -> Result<Vec<SlotId>, ()> {
+        proofs.sort_by_key(|proof| proof.slot_id);
+        let response = catchup::Response { sender: self.id.clone(), proofs };
+        return self.handle_catch_up_response(&response);
+    }
+
+    /// Adopts the values in a [`catchup::Response`].
+    /// Every proof is verified against our own quorum set before anything is adopted,
+    /// so a single bad proof rejects the whole response.
+    /// So does one that disagrees with a slot we externalized, which is noted as a [`Disagreement`].
+    /// Returns the slots that were newly externalized.
+    pub fn handle_catch_up_response(
+        &mut self,
+        response: &catchup::Response<T>,
+    ) -> Result<Vec<SlotId>, ()> {
+        for proof in response.proofs.iter() {
+            proof.verify(&self.id, &self.quorum)?;
+
+            // the proven value disagrees with what we think! oh no!
+            if let Some(externalized) = self.externalized.get(&proof.slot_id) {
+                if externalized.ballot.value != proof.externalize.ballot.value {
+                    let ours = externalized.clone();
+                    self.disagree(proof.slot_id, &response.sender, ours, proof.externalize.clone());
+                    return Err(());
+                }
+            }
+        }
+
+        let mut adopted = vec![];
+        for proof in response.proofs.iter() {
+            // we've already moved past this one, or already have it
+            if self.stale(proof.slot_id) || self.externalized.contains_key(&proof.slot_id) { continue; }
+
+            // so we can prove it to the next peer that asks
+            let heard = self.heard.entry(proof.slot_id).or_default();
+            for message in proof.messages.iter() { heard.insert(message.clone()); }
+
+            self.externalize(proof.slot_id, proof.externalize.clone());
+            adopted.push(proof.slot_id);
+        }
+
+        return Ok(adopted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+    use crate::{
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        quorum::Member,
+    };
+
+    /// Keeps stored slots in memory, which is all a test needs.
+    /// Clones share the same slots, so a test can keep one to look at.
+    #[derive(Clone, Default)]
+    struct Memory(Rc<RefCell<HashMap<SlotId, catchup::Proof<DummyValue>>>>);
+
+    impl Storage<DummyValue> for Memory {
+        fn store(&mut self, proof: catchup::Proof<DummyValue>) { self.0.borrow_mut().insert(proof.slot_id, proof); }
+        fn load(&self, slot_id: SlotId) -> Option<catchup::Proof<DummyValue>> { self.0.borrow().get(&slot_id).cloned() }
+    }
+
+    /// Two of `a`, `b` and `c`.
+    fn quorum() -> Quorum<DummyValue> {
+        Quorum::new(2, ["a", "b", "c"].iter().map(|n| Member::Node(node_id(n))).collect())
+    }
+
+    fn externalize(number: usize) -> topic::Externalize<DummyValue> {
+        topic::Externalize { ballot: Ballot { number, value: DummyValue(1) }, highest: number }
+    }
+
+    fn message(sender: &str, slot_id: usize, topic: Topic<DummyValue>) -> Message<DummyValue> {
+        Message::new(node_id(sender), SlotId::new(slot_id), quorum(), topic, &mut 0)
+    }
+
+    /// A proof that `b` and `c` externalized a slot.
+    fn proof(slot_id: usize) -> catchup::Proof<DummyValue> {
+        let messages = ["b", "c"].iter()
+            .map(|s| message(s, slot_id, Topic::Externalize(externalize(1))))
+            .collect();
+        catchup::Proof { slot_id: SlotId::new(slot_id), externalize: externalize(1), messages }
+    }
+
+    /// Has `node` externalize a slot on the word of `b` and `c`.
+    fn adopt(node: &mut Node<DummyValue>, slot_id: usize) {
+        let response = catchup::Response { sender: node_id("b"), proofs: vec![proof(slot_id)] };
+        node.handle_catch_up_response(&response).unwrap();
+    }
+
+    /// `a`, having externalized slots `1..=n`.
+    fn decided(n: usize, retention: Retention) -> Node<DummyValue> {
+        let mut node = Node::new(node_id("a"), quorum(), HashMap::new());
+        node.retention = retention;
+        node.set_storage(Box::new(Memory::default()));
+        for slot_id in 1..=n { adopt(&mut node, slot_id); }
+        return node;
+    }
+
+    #[test]
+    fn evicts_to_storage() {
+        let mut node = decided(5, Retention { externalized: 2, ..Retention::default() });
+        assert_eq!(node.usage().externalized, 2);
+        assert!(node.externalized(SlotId::new(3)).is_none());
+        assert!(node.externalized(SlotId::new(4)).is_some());
+
+        // still answered, from storage
+        let nominate = Topic::Nominate(topic::Nominate { nominated: Default::default(), accepted: Default::default() });
+        let answer = node.handle(&message("b", 2, nominate)).unwrap();
+        assert_eq!(answer.len(), 1);
+        assert_eq!(answer[0].topic, Topic::Externalize(externalize(1)));
+
+        let request = catchup::Request { sender: node_id("b"), after: Some(SlotId::new(1)), to: SlotId::new(5) };
+        let proven = node.handle_catch_up(&request).proofs.into_iter()
+            .map(|proof| proof.verify(&node_id("b"), &quorum()).map(|()| proof.slot_id.number()))
+            .collect::<Result<Vec<usize>, ()>>();
+        assert_eq!(proven, Ok(vec![2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn purges_pending() {
+        let mut node = decided(1, Retention { pending: 2, ..Retention::default() });
+        node.propose(SlotId::new(2), DummyValue(2));
+        node.propose(SlotId::new(4), DummyValue(4));
+        assert_eq!(node.pending_slot_ids(), vec![SlotId::new(2), SlotId::new(4)]);
+
+        adopt(&mut node, 5);
+        assert_eq!(node.pending_slot_ids(), vec![SlotId::new(4)]);
+        // too old to start again, either
+        assert!(node.propose(SlotId::new(2), DummyValue(2)).is_empty());
+        assert!(!node.is_pending(SlotId::new(2)));
+    }
+
+    #[test]
+    fn ignores_slots_far_ahead() {
+        let mut node = decided(1, Retention { ahead: 10, ..Retention::default() });
+        let usage = node.usage();
+
+        for sender in ["b", "c"] {
+            assert_eq!(node.handle(&message(sender, 100, Topic::Externalize(externalize(1)))), Ok(vec![]));
+        }
+        assert!(!node.is_pending(SlotId::new(100)));
+
+        // but we do notice we've fallen behind, which only takes one message per peer
+        assert_eq!(node.behind(), Some(SlotId::new(100)));
+        assert_eq!(node.catch_up().map(|request| request.to), Some(SlotId::new(100)));
+        let usage = Usage { messages: usage.messages + 2, ..usage };
+        assert_eq!(node.usage(), usage);
+
+        // from members of our quorum set, that is
+        node.handle(&message("d", 200, Topic::Externalize(externalize(1)))).unwrap();
+        assert_eq!(node.usage(), usage);
+
+        // nor is anything invalid noted down
+        let zero = topic::Externalize { ballot: Ballot { number: 0, value: DummyValue(1) }, highest: 0 };
+        for sender in ["b", "c"] {
+            assert_eq!(node.handle(&message(sender, 5, Topic::Externalize(zero.clone()))), Err(()));
+        }
+        assert_eq!(node.usage(), usage);
+
+        // right on the edge is fine
+        for sender in ["b", "c"] {
+            node.handle(&message(sender, 11, Topic::Externalize(externalize(1)))).unwrap();
+        }
+        assert!(node.externalized(SlotId::new(11)).is_some());
+    }
+
+    #[test]
+    fn stores_once_proven() {
+        let memory = Memory::default();
+        let externalized = vec![(SlotId::new(1), externalize(1))].into_iter().collect();
+        let mut node = Node::new(node_id("a"), quorum(), externalized);
+        node.set_storage(Box::new(memory.clone()));
+
+        // nothing's evicted, but it's stored as soon as our word isn't all there is
+        node.handle(&message("b", 1, Topic::Externalize(externalize(1)))).unwrap();
+        assert!(memory.load(SlotId::new(1)).is_none());
+        node.handle(&message("c", 1, Topic::Externalize(externalize(1)))).unwrap();
+        let stored = memory.load(SlotId::new(1)).unwrap();
+        assert_eq!(stored.verify(&node_id("a"), &quorum()), Ok(()));
+
+        // adopted slots come with their proof
+        adopt(&mut node, 2);
+        assert!(memory.load(SlotId::new(2)).is_some());
+    }
+
+    #[test]
+    fn restores() {
+        let mut node = Node::new(node_id("a"), quorum(), HashMap::new());
+        node.retention = Retention { externalized: 2, ..Retention::default() };
+        node.set_storage(Box::new(Memory::default()));
+
+        // in any order, but only if every one of them checks out
+        let mut forged = proof(6);
+        forged.messages.truncate(1);
+        assert_eq!(node.restore(vec![proof(2), forged, proof(1)]), Err(()));
+        assert_eq!(node.latest_externalized(), None);
+
+        let restored = node.restore(vec![proof(3), proof(1), proof(2)]);
+        assert_eq!(restored, Ok(vec![SlotId::new(1), SlotId::new(2), SlotId::new(3)]));
+        assert_eq!(node.usage().externalized, 2);
+        assert_eq!(node.latest_externalized(), Some(SlotId::new(3)));
+
+        let request = catchup::Request { sender: node_id("b"), after: None, to: SlotId::new(3) };
+        assert_eq!(node.handle_catch_up(&request).proofs.len(), 3);
+    }
+
+    #[test]
+    fn notes_disagreements() {
+        // d isn't even in our quorum set, and mustn't be able to take us down
+        let mut node = decided(1, Retention::default());
+        let other = topic::Externalize { ballot: Ballot { number: 1, value: DummyValue(2) }, highest: 1 };
+
+        for _ in 0..2 {
+            assert_eq!(node.handle(&message("d", 1, Topic::Externalize(other.clone()))), Err(()));
+        }
+        assert_eq!(node.disagreements().len(), 1);
+        assert_eq!(node.disagreements()[0].from, node_id("d"));
+        assert_eq!(node.disagreements()[0].theirs, other);
+        assert_eq!(node.externalized(SlotId::new(1)), Some(&externalize(1)));
+    }
+
+    #[test]
+    fn rejects_disagreeing_proofs() {
+        let mut node = decided(1, Retention::default());
+
+        let mut other = proof(1);
+        other.externalize.ballot.value = DummyValue(2);
+        for message in other.messages.iter_mut() { message.topic = Topic::Externalize(other.externalize.clone()); }
+
+        let response = catchup::Response { sender: node_id("b"), proofs: vec![proof(2), other] };
+        assert_eq!(node.handle_catch_up_response(&response), Err(()));
+        assert_eq!(node.disagreements().len(), 1);
+        assert_eq!(node.externalized(SlotId::new(1)), Some(&externalize(1)));
+        // nothing in the response is taken
+        assert!(node.externalized(SlotId::new(2)).is_none());
     }
 }
@@ -1,6 +1,8 @@
 use std::{
     fmt,
+    hash::Hash,
     collections::HashSet,
+    marker::PhantomData,
 };
 use crate::{
     value::Value,
@@ -8,40 +10,52 @@ use crate::{
 };
 
 /// A predicate is a condition we use to build up and narrow down stuff.
-pub trait Predicate<T: Value>: fmt::Debug {
+/// Predicates are narrowed in place as messages are tested against them.
+/// Because quorum search backtracks,
+/// a predicate has to be able to undo narrowing back to an earlier checkpoint;
+/// implementations keep an undo log of their changes
+/// so that this doesn't need a copy of the whole predicate.
+/// Predicates are passed around by value and dispatched statically,
+/// so the functions inside them can be closures that capture state.
+pub trait Predicate<T: Value>: Clone {
     type Final;
 
-    fn test<'s>(self: Box<Self>, message: &Message<T>)
-        -> Option<Box<dyn Predicate<T, Final=Self::Final> + 's>> where Self: 's;
+    /// Narrows the predicate down to what `message` agrees with,
+    /// returning whether it passed.
+    /// If the message doesn't pass, the predicate is left untouched.
+    fn test(&mut self, message: &Message<T>) -> bool;
+    /// Marks the current state of the predicate.
+    fn checkpoint(&self) -> usize;
+    /// Undoes all narrowing done since `checkpoint` was taken.
+    fn rollback(&mut self, checkpoint: usize);
     /// Extract the final values from a predicate if applicable
     fn build_final(self) -> Self::Final;
-    /// Like clone but for traits
-    fn dupe<'s>(&self) -> Box<dyn Predicate<T, Final=Self::Final> + 's> where Self: 's;
 }
 
 // Function predicate
 
 #[derive(Clone)]
-pub struct FnPredicate<T: Value>(fn(&Message<T>) -> bool);
+pub struct FnPredicate<T: Value, F>(F, PhantomData<T>)
+    where F: Fn(&Message<T>) -> bool + Clone;
 
-impl<T: Value> Predicate<T> for FnPredicate<T> {
+impl<T: Value, F> FnPredicate<T, F> where F: Fn(&Message<T>) -> bool + Clone {
+    pub fn new(function: F) -> FnPredicate<T, F> {
+        return FnPredicate(function, PhantomData);
+    }
+}
+
+impl<T: Value, F> Predicate<T> for FnPredicate<T, F> where F: Fn(&Message<T>) -> bool + Clone {
     type Final = ();
 
-    fn test<'s>(self: Box<Self>, message: &Message<T>)
-        -> Option<Box<dyn Predicate<T, Final=Self::Final> + 's>> where Self: 's
-    {
-        return if (self.0)(message) {
-            Some(self)
-        } else {
-            None
-        }
-    }
+    fn test(&mut self, message: &Message<T>) -> bool { (self.0)(message) }
 
-    fn build_final(self) -> Self::Final { () }
-    fn dupe<'s>(&self) -> Box<dyn Predicate<T, Final=Self::Final> + 's> where Self: 's { Box::new(self.clone()) }
+    // nothing to narrow, so nothing to undo
+    fn checkpoint(&self) -> usize { 0 }
+    fn rollback(&mut self, _checkpoint: usize) {}
+    fn build_final(self) -> Self::Final {}
 }
 
-impl<T: Value> fmt::Debug for FnPredicate<T> {
+impl<T: Value, F> fmt::Debug for FnPredicate<T, F> where F: Fn(&Message<T>) -> bool + Clone {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("FnPredicate")
             .field(&format_args!("_"))
@@ -58,45 +72,80 @@ impl<T: Value> fmt::Debug for FnPredicate<T> {
 /// There are two common sets used with the [`hashSetPredicate`]:
 /// 1. A [`Value`] set, in which case `T = S`.
 /// 2. A [`Ballot`] set, in which case `T != S`; `S = Ballot`.
+///
 /// Note that, for instance, the [`HashSetPredicate`] for
 /// a [`Ballot`] set is written `HashSetPredicate<Value, Ballot<Value>>`
 /// and not `HashSetPredicate<Value, HashSet<Ballot<Value>>>`.
 /// The type paramater `S` should not be a `HashSet`.
+/// `function` decides whether a message agrees with a single value;
+/// values the message doesn't agree with are narrowed out.
 #[derive(Clone)]
-pub struct HashSetPredicate<T: Value, S: fmt::Debug + Clone> {
-    values:       HashSet<S>,
-    final_values: HashSet<S>,
+pub struct HashSetPredicate<T: Value, S, F>
+    where S: fmt::Debug + Clone + Hash + Eq, F: Fn(&Message<T>, &S) -> bool + Clone
+{
+    values:   HashSet<S>,
+    /// Values narrowed out so far, most recent last.
+    removed:  Vec<S>,
     // TODO: fnmut?
-    function:     fn(&Message<T>, &HashSet<S>) -> HashSet<S>,
+    function: F,
+    _phantom_value: PhantomData<T>,
 }
 
-impl<T: Value, S: fmt::Debug + Clone> Predicate<T> for HashSetPredicate<T, S> {
+impl<T: Value, S, F> HashSetPredicate<T, S, F>
+    where S: fmt::Debug + Clone + Hash + Eq, F: Fn(&Message<T>, &S) -> bool + Clone
+{
+    pub fn new(values: HashSet<S>, function: F) -> HashSetPredicate<T, S, F> {
+        return HashSetPredicate { values, removed: vec![], function, _phantom_value: PhantomData };
+    }
+}
+
+impl<T: Value, S, F> Predicate<T> for HashSetPredicate<T, S, F>
+    where S: fmt::Debug + Clone + Hash + Eq, F: Fn(&Message<T>, &S) -> bool + Clone
+{
     type Final = HashSet<S>;
 
-    fn test<'s>(mut self: Box<Self>, message: &Message<T>)
-        -> Option<Box<dyn Predicate<T, Final=Self::Final> + 's>> where Self: 's
-    {
-        if self.values.is_empty() { return None; }
-        let next_values = (self.function)(message, &self.values);
+    fn test(&mut self, message: &Message<T>) -> bool {
+        if self.values.is_empty() { return false; }
 
-        self.values = match next_values.is_empty() {
-            true  => { return None; },
-            false => { self.final_values = next_values.clone(); next_values }
-        };
+        let checkpoint = self.checkpoint();
+        let function   = &self.function;
+        let removed    = &mut self.removed;
+
+        self.values.retain(|value| {
+            let keep = function(message, value);
+            if !keep { removed.push(value.clone()); }
+            keep
+        });
+
+        // nothing left, so the message doesn't pass
+        if self.values.is_empty() {
+            self.rollback(checkpoint);
+            return false;
+        }
 
-        return Some(self);
+        return true;
     }
 
-    fn build_final(self) -> Self::Final { return self.final_values; }
-    fn dupe<'s>(&self) -> Box<dyn Predicate<T, Final=Self::Final> + 's> where Self: 's { Box::new(self.clone()) }
+    fn checkpoint(&self) -> usize { self.removed.len() }
+
+    fn rollback(&mut self, checkpoint: usize) {
+        while self.removed.len() > checkpoint {
+            // TODO: safe to unwrap?
+            self.values.insert(self.removed.pop().unwrap());
+        }
+    }
+
+    fn build_final(self) -> Self::Final { return self.values; }
 }
 
-impl<T: Value, S: fmt::Debug + Clone> fmt::Debug for HashSetPredicate<T, S> {
+impl<T: Value, S, F> fmt::Debug for HashSetPredicate<T, S, F>
+    where S: fmt::Debug + Clone + Hash + Eq, F: Fn(&Message<T>, &S) -> bool + Clone
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("HashSetPredicate")
-            .field("values",       &self.values)
-            .field("final_values", &self.final_values)
-            .field("function",     &format_args!("_"))
+            .field("values",   &self.values)
+            .field("removed",  &self.removed)
+            .field("function", &format_args!("_"))
             .finish()
     }
 }
@@ -115,55 +164,307 @@ impl<T: Value, S: fmt::Debug + Clone> fmt::Debug for HashSetPredicate<T, S> {
 // that might work, actually.
 // imma commit really quick in case everything goes wrong.
 
+// Predicates narrow in place now, so the final values are just whatever's left.
+
 // Min max predicate
 
 #[derive(Clone)]
-pub struct MinMaxPredicate<T: Value> {
-    min:       usize,
-    max:       usize,
-    final_min: usize,
-    final_max: usize,
-    function:  fn(&Message<T>, usize, usize) -> (bool, usize, usize),
+pub struct MinMaxPredicate<T: Value, F>
+    where F: Fn(&Message<T>, usize, usize) -> (bool, usize, usize) + Clone
+{
+    min:      usize,
+    max:      usize,
+    /// Previous `(min, max)` pairs, most recent last.
+    history:  Vec<(usize, usize)>,
+    function: F,
+    _phantom_value: PhantomData<T>,
 }
 
-impl<T: Value> MinMaxPredicate<T> {
-    pub fn new(
-        function: fn(&Message<T>, usize, usize) -> (bool, usize, usize)
-    ) -> MinMaxPredicate<T> {
-        
+impl<T: Value, F> MinMaxPredicate<T, F>
+    where F: Fn(&Message<T>, usize, usize) -> (bool, usize, usize) + Clone
+{
+    pub fn new(min: usize, max: usize, function: F) -> MinMaxPredicate<T, F> {
+        return MinMaxPredicate { min, max, history: vec![], function, _phantom_value: PhantomData };
     }
 }
 
-impl<T: Value> Predicate<T> for MinMaxPredicate<T> {
+impl<T: Value, F> Predicate<T> for MinMaxPredicate<T, F>
+    where F: Fn(&Message<T>, usize, usize) -> (bool, usize, usize) + Clone
+{
     type Final = (usize, usize);
 
-    fn test<'s>(mut self: Box<Self>, message: &Message<T>)
-        -> Option<Box<dyn Predicate<T, Final=Self::Final> + 's>> where Self: 's
-    {
-        if self.min > self.max { return None; }
+    fn test(&mut self, message: &Message<T>) -> bool {
+        if self.min > self.max { return false; }
 
         let (res, min, max) = (self.function)(message, self.min, self.max);
-        if !res { return None; }
+        if !res { return false; }
 
-        self.min       = min;
-        self.max       = max;
-        self.final_min = min;
-        self.final_max = max;
-        return Some(self);
+        self.history.push((self.min, self.max));
+        self.min = min;
+        self.max = max;
+        return true;
     }
 
-    fn build_final(self) -> Self::Final { (self.final_min, self.final_max) }
-    fn dupe<'s>(&self) -> Box<dyn Predicate<T, Final=Self::Final> + 's> where Self: 's { Box::new(self.clone()) }
+    fn checkpoint(&self) -> usize { self.history.len() }
+
+    fn rollback(&mut self, checkpoint: usize) {
+        if checkpoint < self.history.len() {
+            let (min, max) = self.history[checkpoint];
+            self.min = min;
+            self.max = max;
+            self.history.truncate(checkpoint);
+        }
+    }
+
+    fn build_final(self) -> Self::Final { (self.min, self.max) }
 }
 
-impl<T: Value> fmt::Debug for MinMaxPredicate<T> {
+impl<T: Value, F> fmt::Debug for MinMaxPredicate<T, F>
+    where F: Fn(&Message<T>, usize, usize) -> (bool, usize, usize) + Clone
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("MinMaxPredicate")
-            .field("min",       &self.min)
-            .field("max",       &self.max)
-            .field("final_min", &self.final_min)
-            .field("final_max", &self.final_max)
+            .field("min",      &self.min)
+            .field("max",      &self.max)
+            .field("history",  &self.history)
+            .field("function", &format_args!("_"))
+            .finish()
+    }
+}
+
+// Combinators
+
+/// Predicates can be put together with these to build bigger ones.
+/// See [`AndPredicate`], [`OrPredicate`], and [`MapPredicate`].
+pub trait PredicateExt<T: Value>: Predicate<T> {
+    /// Passes messages that pass both predicates.
+    /// Usually `self` is a filter, and `other` does the narrowing.
+    fn and<Q: Predicate<T>>(self, other: Q) -> AndPredicate<T, Self, Q> {
+        return AndPredicate::new(self, other);
+    }
+
+    /// Passes messages that pass either predicate.
+    fn or<Q: Predicate<T>>(self, other: Q) -> OrPredicate<T, Self, Q> {
+        return OrPredicate::new(self, other);
+    }
+
+    /// Changes what the predicate builds in the end.
+    fn map<U, F>(self, function: F) -> MapPredicate<T, Self, F>
+        where F: Fn(Self::Final) -> U + Clone
+    {
+        return MapPredicate::new(self, function);
+    }
+}
+
+impl<T: Value, P: Predicate<T>> PredicateExt<T> for P {}
+
+/// Both predicates have to pass.
+/// If the second one doesn't, the first one is rolled back,
+/// so a failing message leaves the whole thing untouched.
+/// Builds the final values of both.
+#[derive(Debug, Clone)]
+pub struct AndPredicate<T: Value, P: Predicate<T>, Q: Predicate<T>> {
+    first:   P,
+    second:  Q,
+    /// Checkpoints of `first` and `second` before each message that passed.
+    history: Vec<(usize, usize)>,
+    _phantom_value: PhantomData<T>,
+}
+
+impl<T: Value, P: Predicate<T>, Q: Predicate<T>> AndPredicate<T, P, Q> {
+    pub fn new(first: P, second: Q) -> AndPredicate<T, P, Q> {
+        return AndPredicate { first, second, history: vec![], _phantom_value: PhantomData };
+    }
+}
+
+impl<T: Value, P: Predicate<T>, Q: Predicate<T>> Predicate<T> for AndPredicate<T, P, Q> {
+    type Final = (P::Final, Q::Final);
+
+    fn test(&mut self, message: &Message<T>) -> bool {
+        let checkpoints = (self.first.checkpoint(), self.second.checkpoint());
+
+        if !self.first.test(message) { return false; }
+        if !self.second.test(message) {
+            self.first.rollback(checkpoints.0);
+            return false;
+        }
+
+        self.history.push(checkpoints);
+        return true;
+    }
+
+    fn checkpoint(&self) -> usize { self.history.len() }
+
+    fn rollback(&mut self, checkpoint: usize) {
+        if checkpoint < self.history.len() {
+            let (first, second) = self.history[checkpoint];
+            self.first.rollback(first);
+            self.second.rollback(second);
+            self.history.truncate(checkpoint);
+        }
+    }
+
+    fn build_final(self) -> Self::Final {
+        return (self.first.build_final(), self.second.build_final());
+    }
+}
+
+/// At least one of the predicates has to pass.
+/// Each predicate is only narrowed by the messages it passes.
+/// Builds the final values of both.
+#[derive(Debug, Clone)]
+pub struct OrPredicate<T: Value, P: Predicate<T>, Q: Predicate<T>> {
+    first:   P,
+    second:  Q,
+    /// Checkpoints of `first` and `second` before each message that passed.
+    history: Vec<(usize, usize)>,
+    _phantom_value: PhantomData<T>,
+}
+
+impl<T: Value, P: Predicate<T>, Q: Predicate<T>> OrPredicate<T, P, Q> {
+    pub fn new(first: P, second: Q) -> OrPredicate<T, P, Q> {
+        return OrPredicate { first, second, history: vec![], _phantom_value: PhantomData };
+    }
+}
+
+impl<T: Value, P: Predicate<T>, Q: Predicate<T>> Predicate<T> for OrPredicate<T, P, Q> {
+    type Final = (P::Final, Q::Final);
+
+    fn test(&mut self, message: &Message<T>) -> bool {
+        let checkpoints = (self.first.checkpoint(), self.second.checkpoint());
+
+        // no short-circuiting, both get a chance to narrow
+        let first  = self.first.test(message);
+        let second = self.second.test(message);
+        if !first && !second { return false; }
+
+        self.history.push(checkpoints);
+        return true;
+    }
+
+    fn checkpoint(&self) -> usize { self.history.len() }
+
+    fn rollback(&mut self, checkpoint: usize) {
+        if checkpoint < self.history.len() {
+            let (first, second) = self.history[checkpoint];
+            self.first.rollback(first);
+            self.second.rollback(second);
+            self.history.truncate(checkpoint);
+        }
+    }
+
+    fn build_final(self) -> Self::Final {
+        return (self.first.build_final(), self.second.build_final());
+    }
+}
+
+/// Tests messages exactly like the predicate it wraps,
+/// but runs `function` over the final values when they're built.
+#[derive(Clone)]
+pub struct MapPredicate<T: Value, P: Predicate<T>, F> {
+    predicate: P,
+    function:  F,
+    _phantom_value: PhantomData<T>,
+}
+
+impl<T: Value, P: Predicate<T>, F> MapPredicate<T, P, F> {
+    pub fn new(predicate: P, function: F) -> MapPredicate<T, P, F> {
+        return MapPredicate { predicate, function, _phantom_value: PhantomData };
+    }
+}
+
+impl<T: Value, P: Predicate<T>, U, F> Predicate<T> for MapPredicate<T, P, F>
+    where F: Fn(P::Final) -> U + Clone
+{
+    type Final = U;
+
+    fn test(&mut self, message: &Message<T>) -> bool { self.predicate.test(message) }
+    fn checkpoint(&self) -> usize { self.predicate.checkpoint() }
+    fn rollback(&mut self, checkpoint: usize) { self.predicate.rollback(checkpoint) }
+    fn build_final(self) -> Self::Final { (self.function)(self.predicate.build_final()) }
+}
+
+impl<T: Value, P: Predicate<T> + fmt::Debug, F> fmt::Debug for MapPredicate<T, P, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapPredicate")
+            .field("predicate", &self.predicate)
             .field("function",  &format_args!("_"))
             .finish()
     }
 }
+
+// Federated voting
+
+/// The two halves of a federated voting rule.
+/// We accept a statement if a blocking set accepts it,
+/// or if a quorum votes for or accepts it.
+/// `accept` is what a peer has to say to count towards the blocking set,
+/// and `vote` is what it has to say to count towards the quorum.
+/// Both build the same kind of final value.
+#[derive(Debug, Clone)]
+pub struct AcceptOrVote<T: Value, A, V>
+    where A: Predicate<T>, V: Predicate<T, Final=A::Final>
+{
+    pub accept: A,
+    pub vote:   V,
+    _phantom_value: PhantomData<T>,
+}
+
+impl<T: Value, A, V> AcceptOrVote<T, A, V>
+    where A: Predicate<T>, V: Predicate<T, Final=A::Final>
+{
+    pub fn new(accept: A, vote: V) -> AcceptOrVote<T, A, V> {
+        return AcceptOrVote { accept, vote, _phantom_value: PhantomData };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        quorum::Quorum,
+        slot::SlotId,
+        topic::{self, Topic},
+    };
+
+    fn externalize(value: usize) -> Message<DummyValue> {
+        let topic = Topic::Externalize(topic::Externalize {
+            ballot:  Ballot { number: 1, value: DummyValue(value) },
+            highest: 1,
+        });
+        Message::new(node_id("a"), SlotId::new(0), Quorum::new(0, vec![]), topic, &mut 0)
+    }
+
+    fn value(message: &Message<DummyValue>) -> usize {
+        match &message.topic {
+            Topic::Externalize(e) => e.ballot.value.0,
+            _                     => 0,
+        }
+    }
+
+    #[test]
+    fn and_rolls_back_first() {
+        let even = HashSetPredicate::new(
+            (0..10).collect(),
+            |message: &Message<DummyValue>, n: &usize| n % 2 == value(message) % 2,
+        );
+        let small = MinMaxPredicate::new(0, 10, |message: &Message<DummyValue>, min, max| {
+            let v = value(message);
+            (v < max, min, v)
+        });
+        let mut both = even.and(small).map(|(set, (_, max))| set.len() + max);
+
+        assert!(both.test(&externalize(8)));
+        let checkpoint = both.checkpoint();
+
+        // passes the set but not the range, so nothing changes
+        assert!(!both.test(&externalize(10)));
+        // passes both, and narrows both
+        assert!(both.test(&externalize(4)));
+
+        both.rollback(checkpoint);
+        assert_eq!(both.build_final(), 5 + 8);
+    }
+}
@@ -6,22 +6,22 @@ use std::{
 use crate::{
     predicate::Predicate,
     node::NodeId,
-    message::Message,
+    index::{BitSet, NodeIndex, Statements},
     value::Value,
 };
 
 // TODO: quorum value type <T> where T: Value?
-// TODO: we're basically implementing a backtracking algorithm
-// but the problem is it makes a copy on each recursive call.
-// better solution would be to use a mutable datatype
-// with an 'undo' operation
-// that only stores the mutations
-// and can be called out into a full datatype upon backtracking completing.
+
+/// How many levels deep quorum sets can nest inside each other.
+/// Anything that walks a quorum set recurses once per level,
+/// so a peer could blow our stack with a deep enough one.
+/// Real quorum sets are rarely more than a couple of levels deep.
+pub const MAX_DEPTH: usize = 4;
 
 /// A [`Quorum`] set is a set of nodes/subsets (a [`Member`]), named `members`.
 /// A quorum slice is is a subset of a [`Quorum`] set,
 /// With at least `threshold` number of `members`.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Quorum<T: Value> {
     threshold:      usize,
     members:        Vec<Member<T>>,
@@ -30,161 +30,460 @@ pub struct Quorum<T: Value> {
 
 /// A Member is either a [`Node`] (referenced by a [`NodeId`]),
 /// or a nested [`Quorum`] set.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Member<T: Value> {
     Node(NodeId),
     Quorum(Quorum<T>),
 }
 
+/// A [`Quorum`] set compiled down to the dense node indices of a [`NodeIndex`],
+/// so it can be searched with bitsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IndexedQuorum {
+    threshold: usize,
+    members:   Vec<IndexedMember>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum IndexedMember {
+    Node(usize),
+    Quorum(IndexedQuorum),
+}
+
+/// The working set of a blocking set search.
+/// Rather than copying the set on each recursive call,
+/// we add nodes to it as we go and keep a log of what we added,
+/// so we can undo back to an earlier checkpoint when we backtrack.
+struct Search {
+    nodes: BitSet,
+    log:   Vec<usize>,
+}
+
+impl Search {
+    fn new() -> Search {
+        return Search { nodes: BitSet::new(), log: vec![] };
+    }
+
+    fn contains(&self, node: usize) -> bool {
+        return self.nodes.contains(node);
+    }
+
+    fn insert(&mut self, node: usize) {
+        if self.nodes.insert(node) { self.log.push(node); }
+    }
+
+    fn checkpoint(&self) -> usize {
+        return self.log.len();
+    }
+
+    fn rollback(&mut self, checkpoint: usize) {
+        while self.log.len() > checkpoint {
+            // TODO: safe to unwrap?
+            self.nodes.remove(self.log.pop().unwrap());
+        }
+    }
+
+    /// Copies the nodes found out of the search.
+    /// Nodes that aren't in `index` (i.e. temporary ones) are left out.
+    fn build(&self, index: &NodeIndex) -> HashSet<NodeId> {
+        return self.nodes.iter()
+            .filter_map(|n| index.node_id(n).cloned())
+            .collect();
+    }
+}
+
 // TODO: find blocking and find quorum are very similar; refactor?
 
 impl<T: Value> Quorum<T> {
-    fn needed(&self) -> usize {
-        return 1 + (self.members.len() - self.threshold);
+    pub fn new(threshold: usize, members: Vec<Member<T>>) -> Quorum<T> {
+        return Quorum { threshold, members, _phantom_value: PhantomData };
+    }
+
+    pub fn threshold(&self) -> usize {
+        return self.threshold;
     }
 
-    pub fn find_blocking<'a, F>(
+    pub fn members(&self) -> &[Member<T>] {
+        return &self.members;
+    }
+
+    /// Every node mentioned in this quorum set, nested sets included.
+    pub fn nodes(&self) -> HashSet<&NodeId> {
+        let mut nodes = HashSet::new();
+        for member in self.members.iter() {
+            match member {
+                Member::Node(n)   => { nodes.insert(n); },
+                Member::Quorum(q) => nodes.extend(q.nodes()),
+            }
+        }
+        return nodes;
+    }
+
+    /// Whether the threshold of this quorum set, and every set inside it,
+    /// can actually be met by its members,
+    /// and whether sets nest no more than [`MAX_DEPTH`] deep.
+    /// Quorum search assumes this holds.
+    pub fn valid(&self) -> Result<(), ()> {
+        return self.valid_at(0);
+    }
+
+    fn valid_at(&self, depth: usize) -> Result<(), ()> {
+        if depth > MAX_DEPTH { return Err(()); }
+        if self.threshold > self.members.len() { return Err(()); }
+        for member in self.members.iter() {
+            if let Member::Quorum(q) = member { q.valid_at(depth + 1)?; }
+        }
+        return Ok(());
+    }
+
+    /// Whether `nodes` meet this quorum set's threshold on their own,
+    /// counting a nested set as one member if they meet its threshold too.
+    /// Unlike [`Quorum::find_quorum`], nobody else's quorum set comes into it.
+    pub fn satisfied_by(&self, nodes: &HashSet<&NodeId>) -> bool {
+        let met = self.members.iter()
+            .filter(|member| match member {
+                Member::Node(n)   => nodes.contains(n),
+                Member::Quorum(q) => q.satisfied_by(nodes),
+            })
+            .count();
+        return met >= self.threshold;
+    }
+
+    /// Compiles this quorum set down to node indices,
+    /// giving an index to any member that doesn't have one yet.
+    pub(crate) fn intern(&self, index: &mut NodeIndex) -> IndexedQuorum {
+        let members = self.members.iter().map(|member| match member {
+            Member::Node(n)   => IndexedMember::Node(index.intern(n)),
+            Member::Quorum(q) => IndexedMember::Quorum(q.intern(index)),
+        }).collect();
+
+        return IndexedQuorum { threshold: self.threshold, members };
+    }
+
+    /// Compiles this quorum set down to node indices without changing `index`.
+    /// Members `index` doesn't know about can't have sent us anything,
+    /// so they're given temporary indices past the end of it, kept in `extra`.
+    fn lookup<'q>(
+        &'q self,
+        index: &NodeIndex,
+        extra: &mut HashMap<&'q NodeId, usize>,
+    ) -> IndexedQuorum {
+        let members = self.members.iter().map(|member| match member {
+            Member::Node(n) => IndexedMember::Node(match index.get(n) {
+                Some(i) => i,
+                None    => {
+                    let next = index.len() + extra.len();
+                    *extra.entry(n).or_insert(next)
+                },
+            }),
+            Member::Quorum(q) => IndexedMember::Quorum(q.lookup(index, extra)),
+        }).collect();
+
+        return IndexedQuorum { threshold: self.threshold, members };
+    }
+
+    pub fn find_blocking<P: Predicate<T>>(
         &self,
-        messages:  &HashMap<NodeId, Message<T>>,
-        predicate: Box<dyn Predicate<T, Final=F> + 'a>,
-    ) -> (HashSet<NodeId>, Box<dyn Predicate<T, Final=F> + 'a>) where T: 'a {
-        return Quorum::find_blocking_inner(
-            self.needed(),
-            &self.members,
-            messages,
-            predicate,
-            HashSet::new(),
+        statements:    &Statements<T>,
+        mut predicate: P,
+    ) -> (HashSet<NodeId>, P) {
+        let quorum = self.lookup(statements.index(), &mut HashMap::new());
+        let mut search = Search::new();
+
+        let found = quorum.find_blocking_inner(
+            quorum.needed(),
+            statements,
+            &mut predicate,
+            &mut search,
         );
+
+        let blocking = if found { search.build(statements.index()) } else { HashSet::new() };
+        return (blocking, predicate);
     }
 
-    // TODO: remove unnessary clones, dupes, and boxing
-    // would also have to change the Predicate trait
+    /// Finds a quorum containing `node_id` where every other member passes the predicate,
+    /// narrowing the predicate down to what they all agree with.
+    /// Empty if there isn't one.
+    /// Our own statement isn't tested, see [`federated::confirm`](crate::federated::confirm).
+    pub fn find_quorum<P: Predicate<T>>(
+        &self,
+        node_id:       NodeId,
+        statements:    &Statements<T>,
+        mut predicate: P,
+    ) -> (HashSet<NodeId>, P) {
+        let index = statements.index();
 
-    fn find_blocking_inner<'a, F>(
-        mut needed:    usize,
-        members:       &[Member<T>],
-        messages:      &HashMap<NodeId, Message<T>>,
-        mut predicate: Box<dyn Predicate<T, Final=F> + 'a>,
-        mut so_far:    HashSet<NodeId>,
-    ) -> (HashSet<NodeId>, Box<dyn Predicate<T, Final=F> + 'a>) where T: 'a {
-        // base cases
-        if needed == 0 { return (so_far, predicate) }
-        if needed > members.len() { return (HashSet::new(), predicate) }
+        // we might not have heard of ourselves
+        let own = match index.get(&node_id) {
+            Some(i) => i,
+            None    => index.len(),
+        };
 
-        // TODO: safe to unwrap?
-        let (member, remaining) = members.split_first().unwrap();
+        let checkpoint = predicate.checkpoint();
+        let found = IndexedQuorum::find_quorum_inner(own, statements, &mut predicate);
 
-        match member {
-            Member::Node(n) => {
-                if let Some(message) = messages.get(n) {
-                    if let Some(new_predicate) = predicate.dupe().test(message) {
-                        needed -= 1;
-                        predicate = new_predicate;
-                        so_far.insert(n.clone());
-                    }
-                }
-            },
-            Member::Quorum(q) => {
-                let (new_so_far, new_predicate) = Quorum::find_blocking_inner(
-                    q.needed(),
-                    &q.members,
-                    messages,
-                    predicate.dupe(),
-                    so_far.clone(),
-                );
-
-                // backtrack here, which is why we make a copy of predicate
-                if !new_so_far.is_empty() {
-                    needed -= 1;
-                    predicate = new_predicate;
-                    so_far = new_so_far;
-                }
-            },
+        // whoever else is left, the quorum has to satisfy our own slice too
+        let mut found_quorum = found.iter()
+            .filter_map(|n| index.node_id(n).cloned())
+            .collect::<HashSet<NodeId>>();
+        found_quorum.insert(node_id);
+
+        if !self.satisfied_by(&found_quorum.iter().collect()) {
+            predicate.rollback(checkpoint);
+            return (HashSet::new(), predicate);
         }
+        return (found_quorum, predicate);
+    }
+}
 
-        return Quorum::find_blocking_inner(
-            needed,
-            remaining,
-            messages,
-            predicate,
-            so_far,
-        );
+impl IndexedQuorum {
+    fn needed(&self) -> usize {
+        return 1 + (self.members.len() - self.threshold);
     }
 
-    pub fn find_quorum<'a, F>(
+    /// Looks for `needed` members that satisfy the predicate,
+    /// adding them to `search` and narrowing `predicate` as it goes.
+    /// If there aren't enough, `search` and `predicate`
+    /// are rolled back to how they were, and this returns false.
+    fn find_blocking_inner<T: Value, P: Predicate<T>>(
         &self,
-        node_id:   NodeId,
-        messages:  &HashMap<NodeId, Message<T>>,
-        predicate: Box<dyn Predicate<T, Final=F> + 'a>,
-    ) -> (HashSet<NodeId>, Box<dyn Predicate<T, Final=F> + 'a>) where T: 'a {
-        todo!()
-    }
-
-    pub fn find_quorum_inner<'a, F>(
-        mut threshold: usize,
-        members:       &[Member<T>],
-        messages:      &HashMap<NodeId, Message<T>>,
-        mut predicate: Box<dyn Predicate<T, Final=F> + 'a>,
-        mut so_far:    HashSet<NodeId>,
-    ) -> (HashSet<NodeId>, Box<dyn Predicate<T, Final=F> + 'a>) where T: 'a {
-        // base cases
-        if threshold == 0 { return (so_far, predicate); }
-        if threshold > members.len() { return (HashSet::new(), predicate); }
-
-        // TODO: safe to unwrap?
-        let (member, remaining) = members.split_first().unwrap();
-
-        match member {
-            // TODO: refactor this out.
-            // note that we basically call find_blocking_inner in both branches
-            Member::Node(n) => {
-                if so_far.contains(n) {
-                    threshold -= 1;
-                } else if let Some(message) = messages.get(n) {
-                    if let Some(new_predicate) = predicate.dupe().test(message) {
-                        let mut new_so_far = so_far.clone();
-                        new_so_far.insert(message.sender.clone());
-
-                        let (new_so_far, new_predicate) = Quorum::find_blocking_inner(
-                            message.quorum.needed(),
-                            &message.quorum.members,
-                            messages,
-                            predicate.dupe(),
-                            new_so_far,
-                        );
-
-                        if !new_so_far.is_empty() {
-                            threshold -= 1;
-                            predicate = new_predicate;
-                            so_far = new_so_far;
+        mut needed: usize,
+        statements: &Statements<T>,
+        predicate:  &mut P,
+        search:     &mut Search,
+    ) -> bool {
+        let search_checkpoint    = search.checkpoint();
+        let predicate_checkpoint = predicate.checkpoint();
+
+        for (index, member) in self.members.iter().enumerate() {
+            // base cases
+            if needed == 0 { break; }
+            if needed > self.members.len() - index { break; }
+
+            match member {
+                IndexedMember::Node(n) => {
+                    // already part of the quorum we're building, e.g. ourselves
+                    if search.contains(*n) {
+                        needed -= 1;
+                    } else if let Some((message, _)) = statements.entry(*n) {
+                        if predicate.test(message) {
+                            needed -= 1;
+                            search.insert(*n);
                         }
                     }
-                }
-            },
-            Member::Quorum(q) => {
-                let (new_so_far, new_predicate) = Quorum::find_quorum_inner(
-                    q.threshold,
-                    &q.members,
-                    messages,
-                    predicate.dupe(),
-                    so_far.clone(),
-                );
-
-                // backtrack here, which is why we make a copy of predicate
-                if !new_so_far.is_empty() {
-                    threshold -= 1;
-                    predicate = new_predicate;
-                    so_far = new_so_far;
-                }
+                },
+                IndexedMember::Quorum(q) => {
+                    // backtracks on its own if it fails
+                    if q.find_blocking_inner(q.needed(), statements, predicate, search) {
+                        needed -= 1;
+                    }
+                },
             }
         }
 
-        return Quorum::find_quorum_inner(
-            threshold,
-            remaining,
-            messages,
-            predicate,
-            so_far,
-        );
+        if needed == 0 { return true; }
+
+        search.rollback(search_checkpoint);
+        predicate.rollback(predicate_checkpoint);
+        return false;
+    }
+
+    /// Whether `nodes` meet this quorum set's threshold,
+    /// like [`Quorum::satisfied_by`].
+    /// Stops as soon as the answer is known, as this runs for every node in a quorum search.
+    fn satisfied_by(&self, nodes: &BitSet) -> bool {
+        let mut met = 0;
+        for (index, member) in self.members.iter().enumerate() {
+            if met >= self.threshold { return true; }
+            if met + (self.members.len() - index) < self.threshold { return false; }
+
+            let in_set = match member {
+                IndexedMember::Node(n)   => nodes.contains(*n),
+                IndexedMember::Quorum(q) => q.satisfied_by(nodes),
+            };
+            if in_set { met += 1; }
+        }
+        return met >= self.threshold;
+    }
+
+    /// Finds the largest set of nodes, `own` included,
+    /// where everyone else passes the predicate
+    /// and has a slice inside the set.
+    /// Starts from everyone we've heard from,
+    /// and drops whoever fails the predicate or has no slice left,
+    /// until nothing changes.
+    /// Dropping a node can leave others without a slice, hence the loop.
+    /// The predicate ends up narrowed by exactly the nodes that are left.
+    /// Whether the set satisfies our own slice is up to the caller.
+    fn find_quorum_inner<T: Value, P: Predicate<T>>(
+        own:        usize,
+        statements: &Statements<T>,
+        predicate:  &mut P,
+    ) -> BitSet {
+        let checkpoint = predicate.checkpoint();
+        let mut found = statements.present().clone();
+        found.insert(own);
+
+        loop {
+            // the narrowing depends on who's in the set, so start over each time
+            predicate.rollback(checkpoint);
+            let before = found.len();
+
+            for n in statements.present().iter() {
+                if n == own || !found.contains(n) { continue; }
+                // TODO: safe to unwrap?
+                let (message, _) = statements.entry(n).unwrap();
+                if !predicate.test(message) { found.remove(n); }
+            }
+
+            for n in statements.present().iter() {
+                if n == own || !found.contains(n) { continue; }
+                let (_, quorum) = statements.entry(n).unwrap();
+                if !quorum.satisfied_by(&found) { found.remove(n); }
+            }
+
+            if found.len() == before { return found; }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        message::Message,
+        predicate::FnPredicate,
+        slot::SlotId,
+        topic::{self, Topic},
+    };
+
+    /// Three nodes that all trust two of the three,
+    /// where `a` and `b` externalize 1 and `c` externalizes 2.
+    fn network() -> (Quorum<DummyValue>, Statements<DummyValue>) {
+        let quorum = Quorum::new(2, vec![
+            Member::Node(node_id("a")),
+            Member::Node(node_id("b")),
+            Member::Node(node_id("c")),
+        ]);
+
+        let mut counter = 0;
+        let statements = [("a", 1), ("b", 1), ("c", 2)].iter().map(|(name, value)| {
+            let topic = Topic::Externalize(topic::Externalize {
+                ballot:  Ballot { number: 1, value: DummyValue(*value) },
+                highest: 1,
+            });
+            Message::new(node_id(name), SlotId::new(0), quorum.clone(), topic, &mut counter)
+        }).collect();
+
+        (quorum, statements)
+    }
+
+    fn set(threshold: usize, names: &[&str]) -> Quorum<DummyValue> {
+        Quorum::new(threshold, names.iter().map(|n| Member::Node(node_id(n))).collect())
+    }
+
+    /// Everyone externalizes 1, each with their own quorum set.
+    fn statements(quorums: &[(&str, Quorum<DummyValue>)]) -> Statements<DummyValue> {
+        let mut counter = 0;
+        quorums.iter().map(|(name, quorum)| {
+            let topic = Topic::Externalize(topic::Externalize {
+                ballot:  Ballot { number: 1, value: DummyValue(1) },
+                highest: 1,
+            });
+            Message::new(node_id(name), SlotId::new(0), quorum.clone(), topic, &mut counter)
+        }).collect()
+    }
+
+    fn externalizes(wanted: usize) -> impl Fn(&Message<DummyValue>) -> bool + Clone {
+        move |message| match &message.topic {
+            Topic::Externalize(e) => e.ballot.value == DummyValue(wanted),
+            _                     => false,
+        }
+    }
+
+    #[test]
+    fn blocking() {
+        let (quorum, statements) = network();
+
+        let (blocking, _) = quorum.find_blocking(&statements, FnPredicate::new(externalizes(1)));
+        assert_eq!(blocking, vec![node_id("a"), node_id("b")].into_iter().collect());
+
+        let (blocking, _) = quorum.find_blocking(&statements, FnPredicate::new(externalizes(2)));
+        assert!(blocking.is_empty());
+    }
+
+    #[test]
+    fn quorum() {
+        let (quorum, statements) = network();
+
+        let (found, _) = quorum.find_quorum(node_id("a"), &statements, FnPredicate::new(externalizes(1)));
+        assert_eq!(found, vec![node_id("a"), node_id("b")].into_iter().collect());
+
+        let (found, _) = quorum.find_quorum(node_id("c"), &statements, FnPredicate::new(externalizes(2)));
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn quorum_with_ourselves() {
+        // we don't keep our own statements with everyone else's,
+        // but we still count towards the slices of the peers we find
+        let (quorum, statements) = network();
+        let statements = statements.values()
+            .filter(|m| m.sender != node_id("a"))
+            .cloned()
+            .collect::<Statements<DummyValue>>();
+
+        let (found, _) = quorum.find_quorum(node_id("a"), &statements, FnPredicate::new(externalizes(1)));
+        assert_eq!(found, vec![node_id("a"), node_id("b")].into_iter().collect());
+    }
+
+    #[test]
+    fn quorum_needs_every_threshold() {
+        // b needs three of four, so a and b alone aren't a quorum
+        let a = set(2, &["a", "b"]);
+        let statements = statements(&[("b", set(3, &["a", "b", "c", "d"]))]);
+        let (found, _) = a.find_quorum(node_id("a"), &statements, FnPredicate::new(externalizes(1)));
+        assert!(found.is_empty());
+
+        // once c says the same, they are
+        let statements = self::statements(&[
+            ("b", set(3, &["a", "b", "c", "d"])),
+            ("c", set(1, &["c"])),
+        ]);
+        let (found, _) = a.find_quorum(node_id("a"), &statements, FnPredicate::new(externalizes(1)));
+        assert_eq!(found, vec![node_id("a"), node_id("b"), node_id("c")].into_iter().collect());
+    }
+
+    #[test]
+    fn quorum_follows_slices_transitively() {
+        // b needs c, and c needs d, who hasn't said anything
+        let a = set(2, &["a", "b"]);
+        let mut quorums = vec![("b", set(2, &["b", "c"])), ("c", set(2, &["c", "d"]))];
+        let (found, _) = a.find_quorum(node_id("a"), &statements(&quorums), FnPredicate::new(externalizes(1)));
+        assert!(found.is_empty());
+
+        quorums.push(("d", set(1, &["d"])));
+        let (found, _) = a.find_quorum(node_id("a"), &statements(&quorums), FnPredicate::new(externalizes(1)));
+        assert_eq!(found.len(), 4);
+    }
+
+    #[test]
+    fn quorum_nested() {
+        // a needs b, and one of c or d; c needs e too
+        let a = Quorum::new(2, vec![Member::Node(node_id("b")), Member::Quorum(set(1, &["c", "d"]))]);
+        let b = set(1, &["a"]);
+        let c = Quorum::new(2, vec![Member::Node(node_id("c")), Member::Quorum(set(1, &["e"]))]);
+
+        let (found, _) = a.find_quorum(node_id("a"), &statements(&[("b", b.clone()), ("c", c.clone())]), FnPredicate::new(externalizes(1)));
+        assert!(found.is_empty());
+
+        let (found, _) = a.find_quorum(node_id("a"), &statements(&[("b", b.clone()), ("c", c), ("e", set(1, &["e"]))]), FnPredicate::new(externalizes(1)));
+        assert_eq!(found, vec![node_id("a"), node_id("b"), node_id("c"), node_id("e")].into_iter().collect());
+
+        // d is enough on its own
+        let (found, _) = a.find_quorum(node_id("a"), &statements(&[("b", b), ("d", set(1, &["d"]))]), FnPredicate::new(externalizes(1)));
+        assert_eq!(found, vec![node_id("a"), node_id("b"), node_id("d")].into_iter().collect());
     }
 }
@@ -0,0 +1,704 @@
+//! A deterministic network simulator, for exploring liveness.
+//! Where the [`check`](crate::check) model checker tries every interleaving of a tiny network,
+//! this runs one: a [`Scenario`] says who trusts whom, who proposes what,
+//! and what goes wrong when (partitions, crashes, Byzantine nodes),
+//! and a seed picks how long each message takes to arrive.
+//! The same scenario and seed always play out the same way.
+//!
+//! Time is measured in ticks. Each node's timer first fires after [`Scenario::timeout`] ticks,
+//! and each time after that waits one `timeout` longer than the last, like SCP says.
+//! Messages between nodes that can't reach each other, when they're sent or when they'd arrive,
+//! are dropped.
+//!
+//! Safety is the [model checker](crate::check)'s job; here we check liveness.
+//! [`Outcome::stalled`] finds the nodes that didn't externalize soon enough
+//! once the last fault was over.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    arbitrary::Source,
+    check::Participant,
+    message::Message,
+    node::NodeId,
+    quorum::Quorum,
+    slot::{Phase, SlotId},
+    topic::{self, Topic},
+    value::Value,
+};
+#[cfg(feature = "tools")]
+use crate::{config, toml::Toml};
+
+// TODO: better error types
+
+/// Something that goes wrong, or stops going wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Splits the network into groups that can only talk amongst themselves.
+    /// Nodes that aren't in any group can't talk to anyone.
+    /// Replaces any partition already in place.
+    Partition(Vec<Vec<NodeId>>),
+    /// Ends the partition, if there is one.
+    Heal,
+    /// The node stops handling messages and timeouts.
+    Crash(NodeId),
+    /// The node comes back with a fresh state, and proposes its value again.
+    Restart(NodeId),
+}
+
+/// How a node behaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Role<T: Value> {
+    Honest,
+    /// Handles messages, but never sends any.
+    Silent,
+    /// Nominates the first value to half its peers and the second to the other half,
+    /// then goes quiet.
+    Equivocate(T, T),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario<T: Value> {
+    pub quorums:   HashMap<NodeId, Quorum<T>>,
+    pub proposals: HashMap<NodeId, T>,
+    /// Nodes without a role are honest.
+    pub roles:     HashMap<NodeId, Role<T>>,
+    /// What happens when, in ticks.
+    pub faults:    Vec<(u64, Fault)>,
+    pub seed:      u64,
+    /// How long the first timeout is, in ticks.
+    pub timeout:   u64,
+    /// The least and most ticks a message takes to arrive.
+    pub latency:   (u64, u64),
+    /// When to give up.
+    pub until:     u64,
+}
+
+#[cfg(feature = "tools")]
+fn node_id(toml: &Toml) -> Result<NodeId, ()> {
+    return Ok(NodeId::new(toml.as_str().ok_or(())?.to_string()));
+}
+
+#[cfg(feature = "tools")]
+fn ticks(toml: Option<&Toml>, default: u64) -> Result<u64, ()> {
+    return match toml {
+        Some(t) => { let n = t.as_integer().ok_or(())?; if n < 0 { Err(()) } else { Ok(n as u64) } },
+        None    => Ok(default),
+    };
+}
+
+#[cfg(feature = "tools")]
+impl<T: Value> Scenario<T> {
+    /// Reads a scenario, with `value` turning the strings in the file into values.
+    /// A scenario looks like:
+    ///
+    /// ```toml
+    /// seed    = 7
+    /// timeout = 10            # optional, ticks
+    /// latency = [1, 5]        # optional, ticks
+    /// until   = 1000          # optional, ticks
+    ///
+    /// [nodes.a]               # a quorum set, like in a network description
+    /// threshold = 2
+    /// nodes     = ["a", "b", "c"]
+    /// propose   = "x"         # optional
+    /// role      = "silent"    # optional, or "equivocate" with `values = ["x", "y"]`
+    ///
+    /// [[faults]]
+    /// at        = 20
+    /// partition = [["a", "b"], ["c"]]   # or `heal = true`, `crash = "c"`, `restart = "c"`
+    /// until     = 80                    # optional, heals the partition
+    /// ```
+    pub fn from_toml<F: Fn(&str) -> T>(toml: &Toml, value: F) -> Result<Scenario<T>, ()> {
+        let mut quorums   = HashMap::new();
+        let mut proposals = HashMap::new();
+        let mut roles     = HashMap::new();
+
+        for (name, node) in toml.get("nodes").and_then(Toml::as_table).ok_or(())?.iter() {
+            let id = NodeId::new(name.clone());
+            quorums.insert(id.clone(), config::quorum(node)?);
+
+            if let Some(proposal) = node.get("propose") {
+                proposals.insert(id.clone(), value(proposal.as_str().ok_or(())?));
+            }
+
+            let role = match node.get("role").map(|r| r.as_str().ok_or(())).transpose()? {
+                None | Some("honest") => Role::Honest,
+                Some("silent")        => Role::Silent,
+                Some("equivocate")    => {
+                    let values = node.get("values").and_then(Toml::as_array).ok_or(())?;
+                    if values.len() != 2 { return Err(()); }
+                    let first  = value(values[0].as_str().ok_or(())?);
+                    let second = value(values[1].as_str().ok_or(())?);
+                    Role::Equivocate(first, second)
+                },
+                Some(_) => { return Err(()); },
+            };
+            roles.insert(id, role);
+        }
+
+        let mut faults = vec![];
+        for fault in toml.get("faults").map(|f| f.as_array().ok_or(())).transpose()?.unwrap_or(&[]) {
+            let at = ticks(Some(fault.get("at").ok_or(())?), 0)?;
+            let fault = if let Some(groups) = fault.get("partition") {
+                let groups = groups.as_array().ok_or(())?.iter()
+                    .map(|g| g.as_array().ok_or(())?.iter().map(node_id).collect())
+                    .collect::<Result<Vec<Vec<NodeId>>, ()>>()?;
+                if let Some(until) = fault.get("until") {
+                    faults.push((ticks(Some(until), 0)?, Fault::Heal));
+                }
+                Fault::Partition(groups)
+            } else if fault.get("heal").and_then(Toml::as_bool) == Some(true) {
+                Fault::Heal
+            } else if let Some(n) = fault.get("crash") {
+                Fault::Crash(node_id(n)?)
+            } else if let Some(n) = fault.get("restart") {
+                Fault::Restart(node_id(n)?)
+            } else {
+                return Err(());
+            };
+            faults.push((at, fault));
+        }
+
+        let latency = match toml.get("latency") {
+            Some(l) => match l.as_array().ok_or(())? {
+                [min, max] => (ticks(Some(min), 0)?, ticks(Some(max), 0)?),
+                _          => { return Err(()); },
+            },
+            None => (1, 1),
+        };
+        if latency.0 > latency.1 { return Err(()); }
+
+        return Ok(Scenario {
+            quorums,
+            proposals,
+            roles,
+            faults,
+            seed:    ticks(toml.get("seed"), 0)?,
+            timeout: ticks(toml.get("timeout"), 10)?,
+            latency,
+            until:   ticks(toml.get("until"), 1000)?,
+        });
+    }
+}
+
+/// Something that happened during a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<T: Value> {
+    Fault(Fault),
+    Timeout { node_id: NodeId, round: usize },
+    Phase { node_id: NodeId, phase: Phase },
+    Externalized { node_id: NodeId, value: T },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry<T: Value> {
+    pub at:    u64,
+    pub event: Event<T>,
+}
+
+/// When and how a node externalized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision<T: Value> {
+    pub value:    T,
+    pub at:       u64,
+    /// Which round the node was in: one, plus the timeouts it had seen.
+    pub rounds:   usize,
+    /// How many messages had been delivered, across the whole network.
+    pub messages: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome<T: Value> {
+    pub timeline:  Vec<Entry<T>>,
+    /// Every node, sorted, with its latest decision.
+    /// A node that restarts forgets what it decided.
+    pub decisions: Vec<(NodeId, Option<Decision<T>>)>,
+    pub delivered: usize,
+    pub dropped:   usize,
+    /// When the run ended: either nothing was left to happen,
+    /// or it hit [`Scenario::until`].
+    pub ended:     u64,
+}
+
+impl<T: Value> Outcome<T> {
+    pub fn decision(&self, node_id: &NodeId) -> Option<&Decision<T>> {
+        return self.decisions.iter().find(|(n, _)| n == node_id).and_then(|(_, d)| d.as_ref());
+    }
+
+    /// When the last fault happened, or zero if nothing went wrong.
+    pub fn settled(&self) -> u64 {
+        return self.timeline.iter()
+            .filter(|e| matches!(e.event, Event::Fault(_)))
+            .map(|e| e.at)
+            .last()
+            .unwrap_or(0);
+    }
+
+    /// The intact nodes that didn't externalize within `rounds` rounds
+    /// of the network [settling](Outcome::settled).
+    /// Intact nodes are honest, and weren't crashed at the end.
+    /// A node that externalized before the network settled is fine.
+    pub fn stalled(&self, scenario: &Scenario<T>, rounds: usize) -> Vec<NodeId> {
+        let settled = self.settled();
+        // timeouts at the same tick as the last fault, but after it, count as after
+        let last = self.timeline.iter().rposition(|e| matches!(e.event, Event::Fault(_))).map_or(0, |i| i + 1);
+
+        // which round each node was in when the network settled
+        let mut round   = HashMap::new();
+        let mut crashed = HashSet::new();
+        for entry in self.timeline[..last].iter() {
+            match &entry.event {
+                Event::Timeout { node_id, round: r } => { round.insert(node_id.clone(), *r); },
+                Event::Fault(Fault::Crash(n))        => { crashed.insert(n.clone()); },
+                Event::Fault(Fault::Restart(n))      => { crashed.remove(n); round.remove(n); },
+                _ => (),
+            }
+        }
+
+        let mut stalled = vec![];
+        for (node_id, decision) in self.decisions.iter() {
+            let honest = scenario.roles.get(node_id).is_none_or(|r| *r == Role::Honest);
+            if !honest || crashed.contains(node_id) { continue; }
+
+            let started = round.get(node_id).copied().unwrap_or(1);
+            match decision {
+                Some(d) if d.at <= settled || d.rounds - started <= rounds => (),
+                _ => stalled.push(node_id.clone()),
+            }
+        }
+        return stalled;
+    }
+}
+
+enum Pending<T: Value> {
+    Deliver { to: usize, message: Message<T> },
+    /// Timers from before a crash or restart are stale, and ignored.
+    Timeout { node: usize, epoch: usize },
+    Fault(Fault),
+}
+
+struct Simulation<'s, T: Value, P: Participant<T>, F: Fn(&NodeId) -> P> {
+    scenario:     &'s Scenario<T>,
+    build:        F,
+    nodes:        Vec<NodeId>,
+    participants: Vec<P>,
+    counters:     Vec<usize>,
+    crashed:      Vec<bool>,
+    epochs:       Vec<usize>,
+    rounds:       Vec<usize>,
+    phases:       Vec<Phase>,
+    decisions:    Vec<Option<Decision<T>>>,
+    /// Which group each node is in, if there's a partition.
+    groups:       Option<Vec<Option<usize>>>,
+
+    /// Ordered by time, then by when it was scheduled.
+    queue:    BTreeMap<(u64, u64), Pending<T>>,
+    next:     u64,
+    now:      u64,
+    source:   Source,
+    timeline: Vec<Entry<T>>,
+    delivered: usize,
+    dropped:   usize,
+}
+
+impl<'s, T: Value, P: Participant<T>, F: Fn(&NodeId) -> P> Simulation<'s, T, P, F> {
+    fn schedule(&mut self, at: u64, pending: Pending<T>) {
+        self.queue.insert((at, self.next), pending);
+        self.next += 1;
+    }
+
+    fn role(&self, node: usize) -> Role<T> {
+        return self.scenario.roles.get(&self.nodes[node]).cloned().unwrap_or(Role::Honest);
+    }
+
+    fn linked(&self, from: usize, to: usize) -> bool {
+        return match &self.groups {
+            Some(groups) => groups[from].is_some() && groups[from] == groups[to],
+            None         => true,
+        };
+    }
+
+    fn send(&mut self, from: usize, to: usize, message: Message<T>) {
+        if !self.linked(from, to) { self.dropped += 1; return; }
+
+        let (min, max) = self.scenario.latency;
+        let latency = min + self.source.below((max - min + 1) as usize) as u64;
+        self.schedule(self.now + latency, Pending::Deliver { to, message });
+    }
+
+    fn broadcast(&mut self, from: usize, messages: Vec<Message<T>>) {
+        if self.role(from) != Role::Honest { return; }
+        for message in messages {
+            for to in 0..self.nodes.len() {
+                if to != from { self.send(from, to, message.clone()); }
+            }
+        }
+    }
+
+    /// A nomination made up on behalf of a faulty node.
+    fn nominate(&mut self, node: usize, value: T) -> Message<T> {
+        let topic = Topic::Nominate(topic::Nominate {
+            nominated: vec![value].into_iter().collect::<HashSet<T>>(),
+            accepted:  HashSet::new(),
+        });
+        let node_id = self.nodes[node].clone();
+        let quorum  = self.scenario.quorums[&node_id].clone();
+        return Message::new(node_id, SlotId::new(0), quorum, topic, &mut self.counters[node]);
+    }
+
+    /// What a node says before anything else happens.
+    fn propose(&mut self, node: usize) {
+        match self.role(node) {
+            Role::Honest => {
+                if let Some(value) = self.scenario.proposals.get(&self.nodes[node]).cloned() {
+                    let outbound = self.participants[node].propose(value);
+                    self.broadcast(node, outbound);
+                }
+            },
+            Role::Silent => (),
+            Role::Equivocate(first, second) => {
+                let first  = self.nominate(node, first);
+                let second = self.nominate(node, second);
+                let peers  = (0..self.nodes.len()).filter(|p| *p != node).collect::<Vec<usize>>();
+                for (i, peer) in peers.iter().enumerate() {
+                    let message = if i < peers.len() / 2 { first.clone() } else { second.clone() };
+                    self.send(node, *peer, message);
+                }
+            },
+        }
+    }
+
+    fn start_timer(&mut self, node: usize) {
+        let at = self.now + self.scenario.timeout * self.rounds[node] as u64;
+        self.schedule(at, Pending::Timeout { node, epoch: self.epochs[node] });
+    }
+
+    /// Notes any phase change or decision since we last looked.
+    fn observe(&mut self, node: usize) {
+        let observation = self.participants[node].observe();
+        let node_id = self.nodes[node].clone();
+
+        if observation.phase != self.phases[node] {
+            self.phases[node] = observation.phase;
+            self.timeline.push(Entry { at: self.now, event: Event::Phase { node_id: node_id.clone(), phase: observation.phase } });
+        }
+
+        if let (Some(value), None) = (observation.externalized, &self.decisions[node]) {
+            self.decisions[node] = Some(Decision {
+                value:    value.clone(),
+                at:       self.now,
+                rounds:   self.rounds[node],
+                messages: self.delivered,
+            });
+            self.timeline.push(Entry { at: self.now, event: Event::Externalized { node_id, value } });
+        }
+    }
+
+    fn fault(&mut self, fault: Fault) {
+        self.timeline.push(Entry { at: self.now, event: Event::Fault(fault.clone()) });
+        let position = |nodes: &[NodeId], n: &NodeId| nodes.iter().position(|m| m == n);
+
+        match fault {
+            Fault::Partition(groups) => {
+                let mut membership = vec![None; self.nodes.len()];
+                for (g, group) in groups.iter().enumerate() {
+                    for n in group { if let Some(i) = position(&self.nodes, n) { membership[i] = Some(g); } }
+                }
+                self.groups = Some(membership);
+            },
+            Fault::Heal => { self.groups = None; },
+            Fault::Crash(n) => if let Some(i) = position(&self.nodes, &n) {
+                self.crashed[i] = true;
+                self.epochs[i] += 1;
+            },
+            Fault::Restart(n) => if let Some(i) = position(&self.nodes, &n) {
+                self.participants[i] = (self.build)(&n);
+                self.crashed[i]   = false;
+                self.epochs[i]   += 1;
+                self.rounds[i]    = 1;
+                self.phases[i]    = self.participants[i].observe().phase;
+                self.decisions[i] = None;
+                self.propose(i);
+                self.start_timer(i);
+            },
+        }
+    }
+
+    fn step(&mut self, pending: Pending<T>) {
+        match pending {
+            Pending::Deliver { to, message } => {
+                let from = self.nodes.iter().position(|n| *n == message.sender);
+                let reachable = from.is_none_or(|from| self.linked(from, to));
+                if self.crashed[to] || !reachable { self.dropped += 1; return; }
+
+                self.delivered += 1;
+                // invalid messages are dropped, like they would be over the wire
+                let outbound = self.participants[to].handle(message).unwrap_or_default();
+                self.observe(to);
+                self.broadcast(to, outbound);
+            },
+            Pending::Timeout { node, epoch } => {
+                if self.crashed[node] || epoch != self.epochs[node] { return; }
+                if self.decisions[node].is_some() { return; }
+
+                self.rounds[node] += 1;
+                self.timeline.push(Entry {
+                    at:    self.now,
+                    event: Event::Timeout { node_id: self.nodes[node].clone(), round: self.rounds[node] },
+                });
+                let outbound = self.participants[node].timeout();
+                self.observe(node);
+                self.broadcast(node, outbound);
+                self.start_timer(node);
+            },
+            Pending::Fault(fault) => self.fault(fault),
+        }
+    }
+}
+
+/// Plays out a scenario, with `build` making a fresh participant for a node,
+/// at the start and whenever it restarts.
+pub fn simulate<T, P, F>(scenario: &Scenario<T>, build: F) -> Outcome<T>
+where
+    T: Value,
+    P: Participant<T>,
+    F: Fn(&NodeId) -> P,
+{
+    // sorted, so the run doesn't depend on hash order
+    let mut nodes = scenario.quorums.keys().cloned().collect::<Vec<NodeId>>();
+    nodes.sort();
+
+    let participants = nodes.iter().map(&build).collect::<Vec<P>>();
+    let phases = participants.iter().map(|p| p.observe().phase).collect();
+    let count = nodes.len();
+
+    let mut simulation = Simulation {
+        scenario,
+        build,
+        nodes,
+        participants,
+        counters:  vec![0; count],
+        crashed:   vec![false; count],
+        epochs:    vec![0; count],
+        rounds:    vec![1; count],
+        phases,
+        decisions: vec![None; count],
+        groups:    None,
+        queue:     BTreeMap::new(),
+        next:      0,
+        now:       0,
+        source:    Source::new(scenario.seed),
+        timeline:  vec![],
+        delivered: 0,
+        dropped:   0,
+    };
+
+    for (at, fault) in scenario.faults.iter() {
+        simulation.schedule(*at, Pending::Fault(fault.clone()));
+    }
+    for node in 0..count {
+        simulation.propose(node);
+        simulation.start_timer(node);
+    }
+
+    while let Some(((at, _), pending)) = simulation.queue.pop_first() {
+        if at > scenario.until { break; }
+        simulation.now = at;
+        simulation.step(pending);
+    }
+
+    return Outcome {
+        timeline:  simulation.timeline,
+        decisions: simulation.nodes.into_iter().zip(simulation.decisions).collect(),
+        delivered: simulation.delivered,
+        dropped:   simulation.dropped,
+        ended:     simulation.now,
+    };
+}
+
+// the scenarios are written in TOML
+#[cfg(all(test, feature = "tools"))]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{node_id, DummyValue, Toy},
+        slot::Standalone,
+        toml,
+    };
+
+    fn scenario(input: &str) -> Scenario<DummyValue> {
+        let toml = toml::parse(input).unwrap();
+        Scenario::from_toml(&toml, |v| DummyValue(v.parse().unwrap())).unwrap()
+    }
+
+    fn run(scenario: &Scenario<DummyValue>, wait_for: usize) -> Outcome<DummyValue> {
+        simulate(scenario, |n| Toy::new(n, scenario.proposals[n].clone(), wait_for))
+    }
+
+    /// Runs real slots instead of the toy.
+    fn run_slots(scenario: &Scenario<DummyValue>) -> Outcome<DummyValue> {
+        simulate(scenario, |n| Standalone::new(n.clone(), scenario.quorums[n].clone(), SlotId::new(0)))
+    }
+
+    /// Everyone decided, and on the same thing.
+    fn agreed(outcome: &Outcome<DummyValue>) -> DummyValue {
+        let decided = outcome.decisions.iter()
+            .map(|(node_id, d)| d.as_ref().unwrap_or_else(|| panic!("{:?} didn't decide", node_id)).value.clone())
+            .collect::<HashSet<DummyValue>>();
+        assert_eq!(decided.len(), 1, "{:?}", decided);
+        decided.into_iter().next().unwrap()
+    }
+
+    const NETWORK: &str = r#"
+        seed    = 3
+        latency = [1, 4]
+        until   = 500
+
+        [nodes.a]
+        threshold = 2
+        nodes     = ["a", "b", "c"]
+        propose   = "3"
+        [nodes.b]
+        threshold = 2
+        nodes     = ["a", "b", "c"]
+        propose   = "1"
+        [nodes.c]
+        threshold = 2
+        nodes     = ["a", "b", "c"]
+        propose   = "2"
+    "#;
+
+    #[test]
+    fn deterministic() {
+        let scenario = scenario(NETWORK);
+        let first = run(&scenario, 2);
+        assert_eq!(first, run(&scenario, 2));
+
+        for (node_id, decision) in first.decisions.iter() {
+            let decision = decision.as_ref().unwrap_or_else(|| panic!("{:?} didn't decide", node_id));
+            assert_eq!(decision.value, DummyValue(1));
+            assert_eq!(decision.rounds, 1);
+        }
+        assert_eq!(first.dropped, 0);
+    }
+
+    #[test]
+    fn crash_and_restart() {
+        let input = format!("{}{}", NETWORK, r#"
+            [[faults]]
+            at    = 0
+            crash = "c"
+            [[faults]]
+            at      = 100
+            restart = "c"
+        "#);
+        let scenario = scenario(&input);
+        // c can only decide once it's back and hears everyone again
+        let outcome = run(&scenario, 2);
+        let c = outcome.decision(&node_id("c")).unwrap();
+        assert!(c.at >= 100);
+        assert_eq!(c.value, DummyValue(1));
+        assert!(outcome.dropped > 0);
+
+        assert_eq!(outcome.timeline[0], Entry { at: 0, event: Event::Fault(Fault::Crash(node_id("c"))) });
+    }
+
+    #[test]
+    fn malformed() {
+        for extra in ["[[faults]]\nat = 1", "[nodes.d]\nthreshold = 1\nrole = \"sneaky\"", "latency = [5, 1]"].iter() {
+            let toml = toml::parse(&format!("{}\n{}", extra, NETWORK.replace("latency = [1, 4]", ""))).unwrap();
+            assert_eq!(Scenario::from_toml(&toml, |v| DummyValue(v.parse().unwrap())), Err(()), "{}", extra);
+        }
+    }
+
+    const PARTITIONED: &str = r#"
+        seed    = 11
+        latency = [1, 3]
+        timeout = 10
+        until   = 2000
+
+        [nodes.a]
+        threshold = 3
+        nodes     = ["a", "b", "c", "d"]
+        propose   = "4"
+        [nodes.b]
+        threshold = 3
+        nodes     = ["a", "b", "c", "d"]
+        propose   = "2"
+        [nodes.c]
+        threshold = 3
+        nodes     = ["a", "b", "c", "d"]
+        propose   = "3"
+        [nodes.d]
+        threshold = 3
+        nodes     = ["a", "b", "c", "d"]
+        propose   = "1"
+
+        [[faults]]
+        at        = 0
+        partition = [["a", "b"], ["c", "d"]]
+        until     = 100
+    "#;
+
+    #[test]
+    fn heals_after_partition() {
+        let scenario = scenario(PARTITIONED);
+        assert_eq!(scenario.faults.len(), 2);
+
+        // neither half can decide alone, but everyone does soon after healing
+        let outcome = run(&scenario, 3);
+        assert_eq!(outcome.settled(), 100);
+        for (node_id, decision) in outcome.decisions.iter() {
+            let decision = decision.as_ref().unwrap_or_else(|| panic!("{:?} didn't decide", node_id));
+            assert!(decision.at > 100);
+            assert!(decision.rounds > 1);
+            assert_eq!(decision.value, DummyValue(1));
+        }
+        assert_eq!(outcome.stalled(&scenario, 2), vec![]);
+        // some nodes needed more than one timeout after healing
+        assert!(!outcome.stalled(&scenario, 0).is_empty());
+    }
+
+    #[test]
+    fn stalls_while_partitioned() {
+        let input = PARTITIONED.replace("until     = 100", "");
+        let forever = scenario(&input);
+        let outcome = run(&forever, 3);
+        assert!(outcome.decisions.iter().all(|(_, d)| d.is_none()));
+        assert_eq!(outcome.stalled(&forever, usize::MAX).len(), 4);
+
+        // a silent node isn't expected to be live,
+        // and the rest only wait to hear from two others
+        let input = PARTITIONED.replace("propose   = \"1\"", "propose   = \"1\"\n        role      = \"silent\"");
+        let silent = scenario(&input);
+        let outcome = run(&silent, 2);
+        assert_eq!(outcome.stalled(&silent, 2), vec![]);
+        assert_eq!(outcome.decision(&node_id("a")).unwrap().value, DummyValue(2));
+    }
+
+    #[test]
+    fn slots_decide() {
+        let scenario = scenario(NETWORK);
+        let outcome = run_slots(&scenario);
+        assert_eq!(outcome, run_slots(&scenario));
+        agreed(&outcome);
+        assert_eq!(outcome.stalled(&scenario, 1), vec![]);
+    }
+
+    #[test]
+    fn slots_heal_after_partition() {
+        let healed = scenario(PARTITIONED);
+        let outcome = run_slots(&healed);
+        agreed(&outcome);
+        for (_, decision) in outcome.decisions.iter() {
+            assert!(decision.as_ref().unwrap().at > 100);
+        }
+        assert_eq!(outcome.stalled(&healed, 2), vec![]);
+
+        // and nobody gets anywhere if it never heals
+        let forever = scenario(&PARTITIONED.replace("until     = 100", ""));
+        let outcome = run_slots(&forever);
+        assert!(outcome.decisions.iter().all(|(_, d)| d.is_none()));
+    }
+}
@@ -1,326 +1,1257 @@
 use std::{
     time,
-    collections::{HashMap, HashSet},
+    collections::HashSet,
+    hash::{Hash, Hasher},
 };
 
 use crate::{
-    node::{Node, NodeId},
+    node::NodeId,
+    quorum::Quorum,
     value::{self, Value},
     message::Message,
-    // topic::Prepare,
     ballot::Ballot,
-    predicate::{self, Predicate},
-    topic::{self, Topic}
+    predicate::{Predicate, HashSetPredicate, AcceptOrVote},
+    topic::{self, Topic, Protocol},
+    evidence::Equivocation,
+    federated::{self, Via},
+    predicate::FnPredicate,
+    json::{self, Json},
+    metrics::Metric,
+    log::{Transition, Source},
+    index::Statements,
+    codec::{self, Encode},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct SlotId(usize);
 
+impl SlotId {
+    pub fn new(number: usize) -> SlotId {
+        return SlotId(number);
+    }
+
+    pub fn number(self) -> usize {
+        return self.0;
+    }
+
+    /// The slot that comes after this one.
+    pub fn next(self) -> SlotId {
+        return SlotId(self.0 + 1);
+    }
+
+    /// How many slots this one lags behind `other`.
+    /// Zero if this slot is at or ahead of `other`.
+    pub fn lag(self, other: SlotId) -> usize {
+        return other.0.saturating_sub(self.0);
+    }
+}
+
+/// Who's running a slot, lent to it whenever it acts.
+/// Slots don't hold on to their node,
+/// so one node can run many slots side by side.
+#[derive(Debug)]
+pub struct Context<'a, T: Value> {
+    pub node_id: &'a NodeId,
+    pub quorum:  &'a Quorum<T>,
+}
+
+// derived Clone and Copy would need T: Copy
+impl<'a, T: Value> Clone for Context<'a, T> { fn clone(&self) -> Self { *self } }
+impl<'a, T: Value> Copy for Context<'a, T> {}
+
 // TODO: some sort of message storage thing?
 // TODO: simplify and break out
 
+/// A single consensus instance.
+/// Nomination and balloting run side by side:
+/// each keeps the latest statement of its kind from every peer,
+/// and emits its own statements.
+/// Slots are driven by a [`Node`](crate::node::Node), which lends them a [`Context`].
 pub struct Slot<T: Value> {
-    id:         SlotId,
-    node:       Node<T>,
-    phase:      Phase,
-    messages:   HashMap<NodeId, Message<T>>,
-    sent:       Option<Message<T>>,
-
-    created:   time::Instant,
-    nominated: HashSet<T>,
-    accepted:  HashSet<T>,
-    confirmed: HashSet<T>,
-
-    ballot:     Ballot<T>,
-    prepared_a: Ballot<T>,
-    prepared_b: Ballot<T>,
-    highest:    Ballot<T>,
-    lowest:     Ballot<T>,
-
-    // what is the point of these priority peers?
-    // are they like the quorum slice of this node?
-    // they are not, hmmm...
+    id:               SlotId,
+    pub(crate) phase: Phase,
+    evidence:         Vec<Equivocation<T>>,
+
+    nominations:     Statements<T>,
+    ballots:         Statements<T>,
+    sent_nomination: Option<Message<T>>,
+    sent_ballot:     Option<Message<T>>,
+    /// Numbers the messages this slot sends.
+    counter:         usize,
 
+    created:    time::Instant,
+    /// When we moved into the current phase.
+    phase_started: time::Instant,
+    /// Metrics gathered since they were last taken, see [`Slot::take_metrics`].
+    metrics:       Vec<Metric>,
+    /// Likewise, see [`Slot::take_transitions`].
+    transitions:   Vec<Transition<T>>,
+
+    nominating: bool,
+    nominated:  HashSet<T>,
+    accepted:   HashSet<T>,
+    confirmed:  HashSet<T>,
+
+    // `None` is what SCP calls the zero ballot,
+    // we don't have a value to put in one until we've started balloting.
+    // The model checker looks at the ones its invariants are about.
+    ballot:                Option<Ballot<T>>,
+    pub(crate) prepared_a: Option<Ballot<T>>,
+    pub(crate) prepared_b: Option<Ballot<T>>,
+    pub(crate) highest:    Option<Ballot<T>>,
+    pub(crate) lowest:     Option<Ballot<T>>,
+
+    /// The leaders of every nomination round so far, see [`Slot::leader`].
+    /// We vote for whatever they vote for.
     priority_peers: HashSet<NodeId>,
     priority_round: usize,
-    priority_timer: time::Instant,
-
-    // Update thing
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        check::Participant,
+        fixtures::DummyValue,
+    };
 
-    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
-    pub struct DummyValue(usize);
+    fn network(n: usize) -> Vec<Standalone<DummyValue>> {
+        let node_ids = (0..n).map(|i| NodeId::new(i.to_string())).collect::<Vec<NodeId>>();
+        let quorum = Quorum::new(n * 2 / 3 + 1, node_ids.iter().cloned().map(crate::quorum::Member::Node).collect());
+        node_ids.into_iter().map(|node_id| Standalone::new(node_id, quorum.clone(), SlotId::new(0))).collect()
+    }
+
+    /// Delivers everything in flight to everyone else, until nobody has anything left to say.
+    fn flood(nodes: &mut [Standalone<DummyValue>], mut inflight: Vec<Message<DummyValue>>) {
+        while !inflight.is_empty() {
+            let message = inflight.remove(0);
+            for node in nodes.iter_mut() {
+                if node.node_id == message.sender { continue; }
+                inflight.extend(Participant::handle(node, message.clone()).unwrap());
+            }
+        }
+    }
 
-    impl Value for DummyValue {
-        fn combine(this: Self, that: Self, slot_id: SlotId) -> Self {
-            DummyValue(this.0 + that.0)
+    #[test]
+    fn externalizes() {
+        let mut nodes = network(3);
+        let mut inflight = vec![];
+        for (i, node) in nodes.iter_mut().enumerate() {
+            inflight.extend(node.propose(DummyValue(i)));
+        }
+        flood(&mut nodes, inflight);
+        let decided = nodes[0].observe().externalized;
+        assert!(decided.is_some());
+        for node in nodes.iter() {
+            assert_eq!(node.observe().externalized, decided);
         }
     }
 
+    #[test]
+    fn nominates_until_committing() {
+        let mut nodes = network(3);
+        let mut inflight = vec![];
+        for (i, node) in nodes.iter_mut().enumerate() {
+            inflight.extend(node.propose(DummyValue(i)));
+        }
+        for node in nodes.iter() {
+            assert!(node.slot.nominating);
+            assert_eq!(node.slot.phase, Phase::Prepare);
+        }
+        flood(&mut nodes, inflight);
+
+        for node in nodes.iter_mut() {
+            assert!(!node.slot.nominating);
+            assert_eq!(node.slot.phase, Phase::Externalize);
+
+            let phases = node.slot.take_transitions().into_iter()
+                .filter_map(|t| match t { Transition::Phase { from, to } => Some((from, to)), _ => None })
+                .collect::<Vec<_>>();
+            assert_eq!(phases, vec![(Phase::Prepare, Phase::Commit), (Phase::Commit, Phase::Externalize)]);
+
+            let timed = node.slot.take_metrics().into_iter()
+                .filter_map(|m| match m { Metric::Phase { phase, .. } => Some(phase), _ => None })
+                .collect::<Vec<_>>();
+            assert_eq!(timed, vec![Phase::Prepare, Phase::Commit]);
+
+            // too late for new values
+            let said = node.propose(DummyValue(7));
+            assert!(said.iter().all(|m| m.topic.protocol() != Protocol::Nomination));
+        }
+    }
+
+    #[test]
+    fn leaders_are_stable() {
+        // known FNV-1a test vectors
+        assert_eq!(fnv(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv(b"a"), 0xaf63dc4c8601ec8c);
+
+        let node = &network(4)[0];
+        let context = Context { node_id: &node.node_id, quorum: &node.quorum };
+        let leaders = (1..=6)
+            .map(|round| node.slot.leader(context, round).as_str().to_string())
+            .collect::<Vec<String>>();
+        // if these change, nodes on different builds stop agreeing on leaders
+        assert_eq!(leaders, vec!["0", "3", "2", "1", "0", "3"]);
+    }
+
     #[test]
     fn slot_size() {
         println!("size of slot: {}", std::mem::size_of::<Slot<DummyValue>>())
     }
 }
 
-/// Represents the current phase of a slot.
+/// Represents the current phase of a slot's ballot protocol.
+/// Nomination isn't a phase, it runs alongside [`Phase::Prepare`]
+/// until we start committing.
 /// Compare this with [`topic::Ballot`].
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Phase {
-    Nominate = 0,
-    NominatePrepare,
-    Prepare,
+    Prepare = 0,
     Commit,
     Externalize,
 }
 
 impl<T: Value> Slot<T> {
-    pub fn new(slot_id: SlotId, node: Node<T>) -> Slot<T> {
-        Slot {
+    pub fn new(slot_id: SlotId) -> Slot<T> {
+        return Slot {
             id:          slot_id,
-            node:        node,
-            phase:       Phase::Nominate,
-            messages:    HashMap::new(),
-            sent:        None,
-
-            created:   time::Instant::now(),
-            nominated: HashSet::new(),
-            accepted:  HashSet::new(),
-            confirmed: HashSet::new(),
-
-            ballot: todo!(),
-            prepared_a: todo!(),
-            prepared_b: todo!(),
-            highest: todo!(),
-            lowest: todo!(),
+            phase:       Phase::Prepare,
+            evidence:    vec![],
+
+            nominations:     Statements::new(),
+            ballots:         Statements::new(),
+            sent_nomination: None,
+            sent_ballot:     None,
+            counter:         0,
+
+            created:    time::Instant::now(),
+            phase_started: time::Instant::now(),
+            metrics:       vec![],
+            transitions:   vec![],
+
+            nominating: true,
+            nominated:  HashSet::new(),
+            accepted:   HashSet::new(),
+            confirmed:  HashSet::new(),
+
+            ballot:     None,
+            prepared_a: None,
+            prepared_b: None,
+            highest:    None,
+            lowest:     None,
 
             priority_peers: HashSet::new(),
             priority_round: 1,
-            priority_timer: todo!(),
+        };
+    }
+
+    /// Takes any [`Equivocation`]s found since this was last called.
+    pub fn take_evidence(&mut self) -> Vec<Equivocation<T>> {
+        return std::mem::take(&mut self.evidence);
+    }
+
+    /// Takes any [`Metric`]s gathered since this was last called.
+    pub fn take_metrics(&mut self) -> Vec<Metric> {
+        return std::mem::take(&mut self.metrics);
+    }
+
+    /// Takes every [`Transition`] made since this was last called, oldest first.
+    pub fn take_transitions(&mut self) -> Vec<Transition<T>> {
+        return std::mem::take(&mut self.transitions);
+    }
+
+    /// When this slot was created.
+    pub fn created(&self) -> time::Instant {
+        return self.created;
+    }
+
+    /// Moves the ballot protocol on to the next phase,
+    /// noting how long we spent in the last one.
+    fn set_phase(&mut self, phase: Phase) {
+        let duration = self.phase_started.elapsed();
+        self.metrics.push(Metric::Phase { phase: self.phase, duration });
+        self.transitions.push(Transition::Phase { from: self.phase, to: phase });
+        self.phase = phase;
+        self.phase_started = time::Instant::now();
+    }
+
+    /// How many messages this slot is holding on to.
+    pub fn messages_held(&self) -> usize {
+        let sent = self.sent_nomination.iter().count() + self.sent_ballot.iter().count();
+        return self.nominations.len() + self.ballots.len() + sent;
+    }
+
+    /// A snapshot of this slot, for operators to look at when it stalls.
+    pub fn info(&self, context: Context<T>) -> SlotInfo<T> {
+        let protocol_info = |protocol: Protocol| {
+            let statements = self.statements(protocol);
+
+            let mut latest = statements.values()
+                .map(|m| (m.sender.clone(), m.topic.clone()))
+                .collect::<Vec<(NodeId, Topic<T>)>>();
+            latest.sort_by(|a, b| a.0.cmp(&b.0));
+
+            // who's said anything at all, not whether they agree
+            let (quorum, _)   = context.quorum.find_quorum(context.node_id.clone(), statements, FnPredicate::new(|_| true));
+            let (blocking, _) = context.quorum.find_blocking(statements, FnPredicate::new(|_| true));
+
+            ProtocolInfo {
+                statements: latest,
+                quorum:     sorted(&quorum).into_iter().cloned().collect(),
+                blocking:   sorted(&blocking).into_iter().cloned().collect(),
+            }
         };
 
-        todo!()
+        let heard_from = |node_id: &NodeId| {
+            self.nominations.contains(node_id) || self.ballots.contains(node_id)
+        };
+        let mut missing = context.quorum.nodes().into_iter()
+            .filter(|n| *n != context.node_id && !heard_from(n))
+            .cloned()
+            .collect::<Vec<NodeId>>();
+        missing.sort();
+
+        return SlotInfo {
+            slot_id:     self.id,
+            phase:       self.phase,
+            nominating:  self.nominating,
+            nominated:   sorted(&self.nominated).into_iter().cloned().collect(),
+            accepted:    sorted(&self.accepted).into_iter().cloned().collect(),
+            confirmed:   sorted(&self.confirmed).into_iter().cloned().collect(),
+            ballot:      self.ballot.clone(),
+            prepared_a:  self.prepared_a.clone(),
+            prepared_b:  self.prepared_b.clone(),
+            highest:     self.highest.clone(),
+            lowest:      self.lowest.clone(),
+            nomination:  protocol_info(Protocol::Nomination),
+            balloting:   protocol_info(Protocol::Ballot),
+            missing,
+        };
+    }
+
+    /// The latest statements from each peer for one of the sub-protocols.
+    fn statements(&self, protocol: Protocol) -> &Statements<T> {
+        return match protocol {
+            Protocol::Nomination => &self.nominations,
+            Protocol::Ballot     => &self.ballots,
+        };
+    }
+
+    fn statements_mut(&mut self, protocol: Protocol) -> &mut Statements<T> {
+        return match protocol {
+            Protocol::Nomination => &mut self.nominations,
+            Protocol::Ballot     => &mut self.ballots,
+        };
+    }
+
+    /// The last statement we sent for one of the sub-protocols.
+    fn sent(&self, protocol: Protocol) -> &Option<Message<T>> {
+        return match protocol {
+            Protocol::Nomination => &self.sent_nomination,
+            Protocol::Ballot     => &self.sent_ballot,
+        };
+    }
+
+    fn sent_mut(&mut self, protocol: Protocol) -> &mut Option<Message<T>> {
+        return match protocol {
+            Protocol::Nomination => &mut self.sent_nomination,
+            Protocol::Ballot     => &mut self.sent_ballot,
+        };
     }
 
     // TODO: simplify building out Topics
 
-    pub fn build_message(&self) -> Option<Message<T>> {
+    pub fn build_nomination(&self) -> Option<Topic<T>> {
+        if !self.nominating { return None; }
+        if self.nominated.is_empty() && self.accepted.is_empty() { return None; }
+
+        return Some(Topic::Nominate(topic::Nominate {
+            nominated: self.nominated.clone(),
+            accepted:  self.accepted.clone(),
+        }));
+    }
+
+    pub fn build_ballot(&self) -> Option<Topic<T>> {
+        // we haven't started balloting yet
+        let ballot = self.ballot.as_ref()?;
+        let zero = || Ballot { number: 0, value: ballot.value.clone() };
+
         let topic = match self.phase {
-            Phase::Nominate => {
-                if self.nominated.is_empty() && self.accepted.is_empty() { return None; }
-                Topic::Nominate(topic::Nominate {
-                    nominated: self.nominated,
-                    accepted:  self.accepted,
-                })
-            },
-            Phase::NominatePrepare => Topic::NominatePrepare(
-                topic::Nominate { nominated: self.nominated, accepted: self.accepted },
-                topic::Prepare {
-                    ballot:     self.ballot,
-                    prepared_a: self.prepared_a,
-                    prepared_b: self.prepared_b,
-                    highest:    self.highest.number,
-                    lowest:     self.lowest.number,
-                },
-            ),
             Phase::Prepare => Topic::Prepare(topic::Prepare {
-                ballot:     self.ballot,
-                prepared_a: self.prepared_a,
-                prepared_b: self.prepared_b,
-                highest:    self.highest.number,
-                lowest:     self.lowest.number,
+                ballot:     ballot.clone(),
+                prepared_a: self.prepared_a.clone().unwrap_or_else(zero),
+                prepared_b: self.prepared_b.clone().unwrap_or_else(zero),
+                highest:    number(&self.highest),
+                lowest:     number(&self.lowest),
             }),
             Phase::Commit => Topic::Commit(topic::Commit {
-                ballot:   self.ballot,
-                prepared: self.prepared_a.number,
-                highest:  self.highest.number,
-                lowest:   self.lowest.number,
+                ballot:   ballot.clone(),
+                prepared: number(&self.prepared_a),
+                highest:  number(&self.highest),
+                lowest:   number(&self.lowest),
             }),
             Phase::Externalize => Topic::Externalize(topic::Externalize {
-                ballot:  self.lowest,
-                highest: self.highest.number,
+                ballot:  self.lowest.clone()?,
+                highest: number(&self.highest),
             }),
         };
 
-        let mut message = Message::new(self.node.id, self.id, self.node.quorum, topic, todo!());
+        return Some(topic);
+    }
+
+    /// Wraps a topic in a message, unless it's the same as the last one we sent.
+    fn send(&mut self, context: Context<T>, topic: Option<Topic<T>>) -> Option<Message<T>> {
+        let topic = topic?;
+        let protocol = topic.protocol();
+
+        if let Some(sent) = self.sent(protocol) {
+            if sent.topic == topic { return None; }
+        }
+
+        let message = Message::new(context.node_id.clone(), self.id, context.quorum.clone(), topic, &mut self.counter);
+        *self.sent_mut(protocol) = Some(message.clone());
+        return Some(message);
     }
 
-    pub fn handle(&mut self, message: Message<T>) -> Result<Option<Message<T>>, ()> {
-        // TODO: handle self messages
+    /// Runs both sub-protocols as far as they'll go, and sends what we now think.
+    /// What we send counts as our own vote, so we go round again
+    /// until there's nothing new to say.
+    /// Only the latest statement for each sub-protocol is returned.
+    fn step(&mut self, context: Context<T>) -> Vec<Message<T>> {
+        let mut nomination = None;
+        let mut ballot     = None;
+
+        loop {
+            self.advance(context);
+
+            let topic = self.build_nomination();
+            let sent_nomination = self.send(context, topic);
+            let topic = self.build_ballot();
+            let sent_ballot = self.send(context, topic);
+
+            if sent_nomination.is_none() && sent_ballot.is_none() { break; }
+            nomination = sent_nomination.or(nomination);
+            ballot     = sent_ballot.or(ballot);
+        }
+
+        return nomination.into_iter().chain(ballot).collect();
+    }
+
+    /// Gives nomination and balloting a chance to make progress,
+    /// until neither can.
+    fn advance(&mut self, context: Context<T>) {
+        // nomination and balloting run side by side,
+        // so we give both a chance to make progress.
+        loop {
+            let nominated = self.nominating && self.nominate(context);
+
+            let balloted = match self.phase {
+                Phase::Prepare     => self.prepare(context),
+                Phase::Commit      => self.commit(context),
+                Phase::Externalize => false,
+            };
 
+            if !nominated && !balloted { return; }
+        }
+    }
+
+    pub fn handle(&mut self, context: Context<T>, message: Message<T>) -> Result<Vec<Message<T>>, ()> {
         // check message validity
         message.valid()?;
 
-        // make sure this is the most up-to-date message
-        // if it isn't, retrieve the most up-to-date one we have
-        let message = match self.messages.get(&message.sender) {
-            Some(m) if m.topic >= message.topic => m,
-            _ => { self.messages.insert(message.sender, message); &message },
-        };
+        // we already know what we think
+        if message.sender == *context.node_id { return Ok(vec![]); }
 
-        // I'm not sure what the whole deal is with this
-        // Nominate prepare phase.
-        // I think the phases lend themselves nicely to pattern matching,
-        // but this phase variant is honestly a huge wrench.
+        let protocol = message.topic.protocol();
+        let sender = message.sender.clone();
 
-        if self.phase == Phase::Nominate
-            || self.phase == Phase::NominatePrepare
-                { self.nominate(message); }
+        // a peer that contradicts itself doesn't get a say,
+        // but we keep both messages around as evidence.
+        if let Some(previous) = self.statements(protocol).get(&sender) {
+            if previous.contradicts(&message) {
+                self.evidence.push(Equivocation::new(previous.clone(), message));
+                return Ok(vec![]);
+            }
+        }
 
-        if self.phase == Phase::NominatePrepare
-            || self.phase == Phase::Prepare
-                { self.prepare(); }
+        // keep only the most up-to-date statement of each kind
+        let statements = self.statements_mut(protocol);
+        let newer = match statements.get(&sender) {
+            Some(m) => message.topic.supersedes(&m.topic)?,
+            None    => true,
+        };
+        if newer { statements.insert(message); }
 
-        if self.phase == Phase::Commit
-            { self.commit();}
+        self.update_leaders(context);
 
         // haiku:
         // I trust the quorum,
         // and count the votes I have seen.
         // We reach consensus.
 
-        let new_message = match self.build_message() {
-            m @ Some(_) if m == self.sent => None,
-            unique => { self.sent = unique; unique },
+        return Ok(self.step(context));
+    }
+
+    /// Votes to nominate `value`.
+    /// Once we've confirmed something nominated we stop taking new values,
+    /// so this might not do anything.
+    pub fn propose(&mut self, context: Context<T>, value: T) -> Vec<Message<T>> {
+        if self.nominating && self.confirmed.is_empty() && !self.accepted.contains(&value) {
+            self.nominated.insert(value);
+        }
+
+        self.update_leaders(context);
+        return self.step(context);
+    }
+
+    /// Called when the slot's timer fires without it making progress.
+    /// Moves nomination on to the next round,
+    /// and bumps the ballot counter if we've started balloting.
+    /// Also repeats our latest statements, in case a peer missed them.
+    pub fn timeout(&mut self, context: Context<T>) -> Vec<Message<T>> {
+        self.priority_round += 1;
+        self.metrics.push(Metric::NominationRound);
+        self.update_leaders(context);
+
+        let bumped = match &self.ballot {
+            Some(ballot) => self.update_ballot(ballot.number + 1),
+            None         => false,
         };
+        if bumped { self.metrics.push(Metric::BallotBumped); }
+        self.transitions.push(Transition::Timeout {
+            round:  self.priority_round,
+            ballot: if bumped { self.ballot.clone() } else { None },
+        });
+
+        let mut outbound = self.step(context);
+        for protocol in [Protocol::Nomination, Protocol::Ballot] {
+            if outbound.iter().any(|m| m.topic.protocol() == protocol) { continue; }
+            if let Some(sent) = self.sent(protocol) { outbound.push(sent.clone()); }
+        }
+        return outbound;
+    }
+
+    // Nomination
+
+    /// The node whose nominations we echo in a nomination round.
+    /// Every node in our quorum set, us included, can be picked,
+    /// and everyone with the same quorum set picks the same one.
+    fn leader(&self, context: Context<T>, round: usize) -> NodeId {
+        let mut nodes = context.quorum.nodes();
+        nodes.insert(context.node_id);
+        let nodes = sorted(&nodes);
+
+        // hashed from the encoding, so every build on every platform picks the same one
+        let mut bytes = codec::to_bytes(&self.id);
+        round.encode(&mut bytes);
+        return NodeId::clone(nodes[(fnv(&bytes) % nodes.len() as u64) as usize]);
+    }
 
-        return Ok(new_message);
+    /// Makes the leader of every round so far a priority peer.
+    fn update_leaders(&mut self, context: Context<T>) {
+        for round in 1..=self.priority_round {
+            let leader = self.leader(context, round);
+            self.priority_peers.insert(leader);
+        }
     }
 
-    pub fn nominate(&mut self, message: &Message<T>) {
-        if self.nominated.len() == 0
-        && self.priority_peers.contains(&message.sender) {
-            // add nominated values to own set
-            todo!()
+    /// Runs nomination as far as it'll go right now.
+    /// Returns whether anything moved.
+    pub fn nominate(&mut self, context: Context<T>) -> bool {
+        // until we've confirmed something, we vote for what our leaders vote for
+        if self.confirmed.is_empty() {
+            let mut echoed = vec![];
+            for peer in self.priority_peers.iter() {
+                if let Some(Topic::Nominate(n)) = self.nominations.get(peer).map(|m| &m.topic) {
+                    echoed.extend(n.nominated.iter().chain(n.accepted.iter()).cloned());
+                }
+            }
+            for value in echoed {
+                if !self.accepted.contains(&value) { self.nominated.insert(value); }
+            }
         }
 
-        // promote accepted values to nominated,
-        // and confirmed values to nominated.
-        self.update_values();
+        // promote nominated values to accepted,
+        // and accepted values to confirmed.
+        let moved = self.update_values(context);
 
         // if a value has been confirmed to be nominated,
-        // we move on to the prepare phase.
-        if self.phase == Phase::Nominate {
-            if self.confirmed.is_empty() {
-                self.update_prepared();
-                todo!()
-                // if self.prepare.is_zero() { return; }
-            }
+        // we start balloting on it.
+        if self.ballot.is_none() && !self.confirmed.is_empty() {
+            return self.update_ballot(1) || moved;
+        }
+
+        return moved;
+    }
 
-            self.phase = Phase::NominatePrepare;
-            self.update_ballot();
+    /// Every value we or our peers vote for or accept that we haven't accepted yet.
+    fn candidates(&self) -> HashSet<T> {
+        let mut candidates = self.nominated.clone();
+        for message in self.nominations.values() {
+            if let Topic::Nominate(n) = &message.topic {
+                candidates.extend(n.nominated.iter().chain(n.accepted.iter()).cloned());
+            }
         }
+        candidates.retain(|value| !self.accepted.contains(value));
+        return candidates;
     }
 
-    // TODO: simplify
-    pub fn update_ballot(&mut self) {
-        if self.phase >= Phase::Commit { panic!(); }
+    /// Moves our ballot to counter `number`, picking its value the way SCP says to:
+    /// the highest ballot we've confirmed prepared, or if there isn't one,
+    /// the values we've confirmed nominated, combined,
+    /// or if there aren't any, the highest ballot we've accepted prepared.
+    /// Returns false if there's nothing to ballot on yet.
+    pub fn update_ballot(&mut self, number: usize) -> bool {
+        if self.phase == Phase::Externalize { return false; }
+
+        let (value, source) = if let Some(highest) = &self.highest {
+            (highest.value.clone(), Source::Highest)
+        } else if let Some(value) = value::combine(self.confirmed.clone(), &self.id) {
+            (value, Source::Confirmed)
+        } else if let Some(prepared) = &self.prepared_a {
+            (prepared.value.clone(), Source::Prepared)
+        } else {
+            return false;
+        };
 
-        if !self.highest.is_zero() {
-            self.ballot.value = self.highest.value;
-        } else if !self.confirmed.is_empty() {
-            // TODO: is unwrap ok here?
-            self.ballot.value = value::combine(self.accepted, &self.id).unwrap();
-        } else if !self.prepared_a.is_zero() {
-            self.ballot.value = self.prepared_a.value;
+        let ballot = Ballot { number, value };
+        self.transitions.push(Transition::Ballot { ballot: ballot.clone(), source });
+        self.ballot = Some(ballot);
+        self.raise_ballot();
+        return true;
+    }
+
+    /// Our ballot has to be at least the highest one we've accepted prepared.
+    fn raise_ballot(&mut self) {
+        let prepared = match &self.prepared_a {
+            Some(p) => p,
+            None    => { return; },
+        };
+
+        match &mut self.ballot {
+            None => {
+                self.transitions.push(Transition::Ballot { ballot: prepared.clone(), source: Source::Prepared });
+                self.ballot = Some(prepared.clone());
+            },
+            // keep our value, at a counter that puts us above it
+            Some(ballot) if *ballot < *prepared => {
+                ballot.number = if ballot.value >= prepared.value { prepared.number } else { prepared.number + 1 };
+            },
+            Some(_) => (),
         }
+    }
 
-        todo!()
+    // Balloting
+
+    /// One step of the prepare phase. Returns whether anything moved.
+    fn prepare(&mut self, context: Context<T>) -> bool {
+        return self.bump(context)
+            || self.accept_prepared(context)
+            || self.confirm_prepared(context)
+            || self.accept_commit(context);
     }
 
-    fn find_blocking<'a, F>(
-        &self, predicate: Box<dyn Predicate<T, Final=F> + 'a>
-    ) -> (HashSet<NodeId>, F) {
-        let (blocking, new_predicate) = self.node.quorum.find_blocking(&self.messages, predicate);
-        return (blocking, new_predicate.build_final());
+    /// One step of the commit phase. Returns whether anything moved.
+    fn commit(&mut self, context: Context<T>) -> bool {
+        return self.bump(context)
+            || self.accept_prepared(context)
+            || self.accept_commit(context)
+            || self.confirm_commit(context);
     }
 
-    fn find_quorum<'a, F>(
-        &self, predicate: Box<dyn Predicate<T, Final=F> + 'a>
-    ) -> (HashSet<NodeId>, F) {
-        let (quorum, new_predicate) = self.node.quorum.find_quorum(self.node.id, &self.messages, predicate);
-        return (quorum, new_predicate.build_final());
+    /// If a v-blocking set is on a higher ballot counter than us,
+    /// we've fallen behind and won't get anywhere where we are,
+    /// so we jump to the lowest counter that isn't left behind too.
+    fn bump(&mut self, context: Context<T>) -> bool {
+        let ours = number(&self.ballot);
+        let ahead = |n: usize| {
+            let (blocking, _) = context.quorum.find_blocking(
+                &self.ballots,
+                FnPredicate::new(move |m: &Message<T>| counter(m) > n),
+            );
+            !blocking.is_empty()
+        };
+        if !ahead(ours) { return false; }
+
+        let mut counters = self.ballots.values()
+            .map(counter)
+            .filter(|n| *n > ours)
+            .collect::<Vec<usize>>();
+        counters.sort();
+        counters.dedup();
+
+        // an externalized peer is at every counter,
+        // accepting commit will catch us up with them instead
+        let target = counters.into_iter()
+            .take_while(|n| *n != usize::MAX)
+            .find(|n| !ahead(*n));
+        return match target {
+            Some(n) => self.update_ballot(n),
+            None    => false,
+        };
     }
 
-    // TODO: just pass in two predicates?
-    fn accept<'a, F>(
-        &self,
-        predicate:        Box<dyn Predicate<T, Final=F> + 'a>,
-        quorum_predicate: Box<dyn Predicate<T, Final=F> + 'a>,
-    ) -> (HashSet<NodeId>, F) {
-        // if this slot's node already accepts the predicate we're done
-        if let Some(message) = self.sent {
-            if let Some(_) = predicate.test(&message) {
-                let mut accepting = HashSet::new();
-                accepting.insert(self.node.id);
-                return (accepting, predicate.build_final());
+    /// Every ballot our peers say anything about preparing, highest first.
+    /// Commit and externalize statements prepare every ballot with their value,
+    /// so we stand in the highest counter anyone's on for those.
+    fn prepare_candidates(&self) -> Vec<Ballot<T>> {
+        let top = self.ballots.values()
+            .map(|m| match &m.topic {
+                Topic::Externalize(e) => e.highest,
+                _                     => counter(m),
+            })
+            .chain(self.ballot.iter().map(|b| b.number))
+            .max()
+            .unwrap_or(0);
+
+        let mut candidates = vec![];
+        for message in self.ballots.values() {
+            match &message.topic {
+                Topic::Prepare(p) => {
+                    candidates.extend([&p.ballot, &p.prepared_a, &p.prepared_b].iter().map(|b| (*b).clone()));
+                },
+                Topic::Commit(c) => {
+                    candidates.push(Ballot { number: c.prepared, value: c.ballot.value.clone() });
+                    candidates.push(Ballot { number: top, value: c.ballot.value.clone() });
+                },
+                Topic::Externalize(e) => {
+                    candidates.push(Ballot { number: top, value: e.ballot.value.clone() });
+                },
+                Topic::Nominate(_) => (),
             }
         }
 
-        // if there is a blocking set that accepts we accept
-        let (blocking, final_value) = self.find_blocking(predicate);
-        if !blocking.is_empty() { return (blocking, final_value); }
+        candidates.retain(|b| !b.is_zero());
+        candidates.sort_by(|a, b| b.cmp(a));
+        candidates.dedup();
+        return candidates;
+    }
+
+    /// Whether we already accept `ballot` as prepared.
+    fn accepts_prepared(&self, ballot: &Ballot<T>) -> bool {
+        let below = |p: &Option<Ballot<T>>| p.as_ref()
+            .is_some_and(|p| p.value == ballot.value && ballot.number <= p.number);
+        return below(&self.prepared_a) || below(&self.prepared_b);
+    }
+
+    /// Accepts the highest ballot we can as prepared.
+    fn accept_prepared(&mut self, context: Context<T>) -> bool {
+        for ballot in self.prepare_candidates() {
+            // once we're committing, only our own value matters
+            if self.phase == Phase::Commit
+            && self.lowest.as_ref().map(|c| &c.value) != Some(&ballot.value) { continue; }
+
+            if self.accepts_prepared(&ballot) { continue; }
+            if let Some(prepared_b) = &self.prepared_b {
+                if ballot <= *prepared_b { continue; }
+            }
+
+            let (accept, vote) = (ballot.clone(), ballot.clone());
+            let (nodes, via, ()) = self.accept(context, Protocol::Ballot, AcceptOrVote::new(
+                FnPredicate::new(move |m: &Message<T>| m.accepts_prepared(&accept)),
+                FnPredicate::new(move |m: &Message<T>| m.votes_or_accepts_prepared(&vote)),
+            ));
 
-        // if there quorum that votes or accepts we accept
-        if let Some(message) = self.sent {
-            if let Some(_) = quorum_predicate.test(&message) {
-                return self.find_quorum(quorum_predicate);
+            if let Some(via) = via {
+                self.set_prepared(ballot.clone());
+                let nodes = sorted(&nodes).into_iter().cloned().collect();
+                self.transitions.push(Transition::AcceptedPrepared { ballot, via, nodes });
+                return true;
             }
         }
 
-        // nobody accepts :(
-        return (HashSet::new(), predicate.build_final());
+        return false;
     }
 
-    pub fn update_values(&mut self) {
-        // move values from nominated to accepted
+    /// Notes that we accept `ballot` as prepared,
+    /// keeping the two highest incompatible ballots we've accepted.
+    fn set_prepared(&mut self, ballot: Ballot<T>) {
+        match self.prepared_a.take() {
+            Some(prepared) if prepared > ballot => {
+                // we'd have skipped it if it were compatible
+                self.prepared_b = Some(ballot);
+                self.prepared_a = Some(prepared);
+            },
+            Some(prepared) => {
+                if prepared.value != ballot.value { self.prepared_b = Some(prepared); }
+                self.prepared_a = Some(ballot);
+            },
+            None => { self.prepared_a = Some(ballot); },
+        }
+
+        // we can't vote to commit anything that might have been aborted
+        if self.phase == Phase::Prepare && self.aborts_highest() { self.lowest = None; }
+        self.raise_ballot();
+    }
+
+    /// Whether we've accepted something incompatible with, and above, our highest ballot.
+    fn aborts_highest(&self) -> bool {
+        let highest = match &self.highest {
+            Some(h) => h,
+            None    => { return false; },
+        };
+        let aborts = |p: &Option<Ballot<T>>| p.as_ref()
+            .is_some_and(|p| p >= highest && p.value != highest.value);
+        return aborts(&self.prepared_a) || aborts(&self.prepared_b);
+    }
+
+    /// Confirms the highest ballot we can as prepared,
+    /// and if nothing stands in the way, votes to commit it.
+    fn confirm_prepared(&mut self, context: Context<T>) -> bool {
+        for ballot in self.prepare_candidates() {
+            if let Some(highest) = &self.highest {
+                if ballot <= *highest { break; }
+            }
+            if !self.accepts_prepared(&ballot) { continue; }
+
+            let accept = ballot.clone();
+            let (quorum, ()) = self.confirm(
+                context,
+                Protocol::Ballot,
+                FnPredicate::new(move |m: &Message<T>| m.accepts_prepared(&accept)),
+            );
+            if quorum.is_empty() { continue; }
+
+            let quorum = sorted(&quorum).into_iter().cloned().collect();
+            self.transitions.push(Transition::ConfirmedPrepared { ballot: ballot.clone(), quorum });
+            if self.ballot.as_ref().is_none_or(|b| *b < ballot) {
+                self.transitions.push(Transition::Ballot { ballot: ballot.clone(), source: Source::Highest });
+                self.ballot = Some(ballot.clone());
+            }
+            self.highest = Some(ballot);
+            if self.aborts_highest() { self.lowest = None; }
+            return true;
+        }
+
+        // vote to commit the highest ballot, if we're on it
+        if self.lowest.is_none() && self.highest.is_some()
+        && self.ballot == self.highest && !self.aborts_highest() {
+            self.lowest = self.highest.clone();
+            return true;
+        }
+
+        return false;
+    }
 
-        let (node_ids, to_promote) = self.accept(
-            Box::new(predicate::HashSetPredicate {
-                values:       self.nominated,
-                final_values: HashSet::new(),
-                function: |message, values| {
-                    values.intersection(message.accept_nominated_set())
-                        .map(|v| v.to_owned())
-                        .collect::<HashSet<T>>()
+    /// The counters our peers mention voting to commit `value` at, lowest first.
+    fn commit_boundaries(&self, value: &T) -> Vec<usize> {
+        let mut boundaries = vec![];
+        for message in self.ballots.values() {
+            match &message.topic {
+                Topic::Prepare(p) if p.lowest != 0 && p.ballot.value == *value => {
+                    boundaries.extend([p.lowest, p.highest].iter());
                 },
-            }),
-            Box::new(predicate::HashSetPredicate {
-                values:       self.nominated,
-                final_values: HashSet::new(),
-                function: |message, values| {
-                    values.intersection(message.vote_or_accept_nominated_set())
-                        .map(|v| v.to_owned())
-                        .collect::<HashSet<T>>()
+                Topic::Commit(c) if c.ballot.value == *value => {
+                    boundaries.extend([c.lowest, c.highest].iter());
                 },
-            }),
-        );
+                Topic::Externalize(e) if e.ballot.value == *value => {
+                    boundaries.extend([e.ballot.number, e.highest].iter());
+                },
+                _ => (),
+            }
+        }
 
-        // TODO: is this check redundant?
-        if !node_ids.is_empty() {
-            for value in to_promote.drain() {
-                self.accepted.insert(value);
+        boundaries.sort();
+        boundaries.dedup();
+        return boundaries;
+    }
+
+    /// Accepts committing the widest range of ballots we can,
+    /// which moves us on to the commit phase.
+    fn accept_commit(&mut self, context: Context<T>) -> bool {
+        let mut values = match (self.phase, &self.lowest) {
+            (Phase::Commit, Some(lowest)) => vec![lowest.value.clone()],
+            _ => self.ballots.values()
+                .filter_map(|m| match &m.topic {
+                    Topic::Prepare(p) if p.lowest != 0 => Some(p.ballot.value.clone()),
+                    Topic::Commit(c)      => Some(c.ballot.value.clone()),
+                    Topic::Externalize(e) => Some(e.ballot.value.clone()),
+                    _                     => None,
+                })
+                .collect::<Vec<T>>(),
+        };
+        values.sort();
+        values.dedup();
+
+        for value in values {
+            let boundaries = self.commit_boundaries(&value);
+            let mut accepted = (HashSet::new(), Via::Own);
+            let range = widest(&boundaries, |lowest, highest| {
+                let (accept, vote) = (value.clone(), value.clone());
+                let (nodes, via, ()) = self.accept(context, Protocol::Ballot, AcceptOrVote::new(
+                    FnPredicate::new(move |m: &Message<T>| m.accepts_commit(&accept, lowest, highest)),
+                    FnPredicate::new(move |m: &Message<T>| m.votes_or_accepts_commit(&vote, lowest, highest)),
+                ));
+                match via {
+                    Some(via) => { accepted = (nodes, via); true },
+                    None      => false,
+                }
+            });
+            let (lowest, highest) = match range {
+                Some(range) => range,
+                None        => { continue; },
+            };
+
+            // nothing we hadn't already accepted
+            if self.phase == Phase::Commit
+            && number(&self.lowest) <= lowest && highest <= number(&self.highest) { continue; }
+
+            let highest = Ballot { number: highest, value: value.clone() };
+            let lowest  = Ballot { number: lowest,  value: value.clone() };
+
+            // accepting commit means we're sticking with this value
+            let ballot = Ballot { number: number(&self.ballot).max(highest.number), value };
+            if self.ballot.as_ref() != Some(&ballot) {
+                self.transitions.push(Transition::Ballot { ballot: ballot.clone(), source: Source::Highest });
+                self.ballot = Some(ballot);
             }
+            if self.prepared_a.as_ref().is_none_or(|p| p.value != highest.value || *p < highest) {
+                self.prepared_a = Some(highest.clone());
+            }
+            self.prepared_b = None;
+
+            let (nodes, via) = accepted;
+            let nodes = sorted(&nodes).into_iter().cloned().collect();
+            self.transitions.push(Transition::AcceptedCommit { ballot: lowest.clone(), via, nodes });
+            self.lowest  = Some(lowest);
+            self.highest = Some(highest);
+
+            // there's no going back now, so no more nominating either
+            self.nominating = false;
+            if self.phase == Phase::Prepare { self.set_phase(Phase::Commit); }
+            return true;
         }
 
-        for value in self.nominated.iter() {
-            if self.accepted.contains(value) {
-                self.nominated.remove(value);
+        return false;
+    }
+
+    /// Confirms committing the widest range of ballots we can,
+    /// which externalizes the slot.
+    fn confirm_commit(&mut self, context: Context<T>) -> bool {
+        let (ours_lowest, ours_highest) = match (&self.lowest, &self.highest) {
+            (Some(c), Some(h)) => (c.number, h.number),
+            _                  => { return false; },
+        };
+        let value = self.highest.as_ref().map(|h| h.value.clone()).unwrap();
+
+        // we can only confirm what we've accepted ourselves
+        let boundaries = self.commit_boundaries(&value).into_iter()
+            .filter(|n| ours_lowest <= *n && *n <= ours_highest)
+            .collect::<Vec<usize>>();
+        let mut confirmed = HashSet::new();
+        let range = widest(&boundaries, |lowest, highest| {
+            let accept = value.clone();
+            let (quorum, ()) = self.confirm(
+                context,
+                Protocol::Ballot,
+                FnPredicate::new(move |m: &Message<T>| m.accepts_commit(&accept, lowest, highest)),
+            );
+            if quorum.is_empty() { return false; }
+            confirmed = quorum;
+            true
+        });
+        let (lowest, highest) = match range {
+            Some(range) => range,
+            None        => { return false; },
+        };
+
+        let lowest = Ballot { number: lowest, value: value.clone() };
+        let quorum = sorted(&confirmed).into_iter().cloned().collect();
+        self.transitions.push(Transition::ConfirmedCommit { ballot: lowest.clone(), quorum });
+        self.lowest  = Some(lowest);
+        self.highest = Some(Ballot { number: highest, value });
+        self.set_phase(Phase::Externalize);
+        return true;
+    }
+
+    /// Runs a federated voting rule over the statements for one of the sub-protocols.
+    fn accept<A, V>(
+        &mut self,
+        context:  Context<T>,
+        protocol: Protocol,
+        rule:     AcceptOrVote<T, A, V>,
+    ) -> (HashSet<NodeId>, Option<Via>, A::Final) where A: Predicate<T>, V: Predicate<T, Final=A::Final> {
+        let start = time::Instant::now();
+        let accepted = federated::accept(
+            context.node_id,
+            context.quorum,
+            self.statements(protocol),
+            self.sent(protocol).as_ref(),
+            rule,
+        );
+        self.metrics.push(Metric::QuorumSearch { duration: start.elapsed() });
+        return accepted;
+    }
+
+    /// Looks for a quorum that agrees with `predicate` for one of the sub-protocols.
+    fn confirm<P: Predicate<T>>(
+        &mut self,
+        context:   Context<T>,
+        protocol:  Protocol,
+        predicate: P,
+    ) -> (HashSet<NodeId>, P::Final) {
+        let start = time::Instant::now();
+        let confirmed = federated::confirm(
+            context.node_id,
+            context.quorum,
+            self.statements(protocol),
+            self.sent(protocol).as_ref(),
+            predicate,
+        );
+        self.metrics.push(Metric::QuorumSearch { duration: start.elapsed() });
+        return confirmed;
+    }
+
+    /// Promotes what we can from nominated to accepted, and accepted to confirmed.
+    /// Returns whether anything moved.
+    pub fn update_values(&mut self, context: Context<T>) -> bool {
+        let mut moved = false;
+
+        // move values from nominated to accepted
+        let candidates = self.candidates();
+        let (node_ids, via, mut to_promote) = self.accept(
+            context,
+            Protocol::Nomination,
+            AcceptOrVote::new(
+                HashSetPredicate::new(
+                    candidates.clone(),
+                    |message: &Message<T>, value: &T| message.accepts_nominated(value),
+                ),
+                HashSetPredicate::new(
+                    candidates,
+                    |message: &Message<T>, value: &T| message.votes_or_accepts_nominated(value),
+                ),
+            ),
+        );
+
+        if let Some(via) = via {
+            let mut promoted = to_promote.drain()
+                .filter(|value| self.accepted.insert(value.clone()))
+                .collect::<Vec<T>>();
+
+            if !promoted.is_empty() {
+                moved = true;
+                promoted.sort();
+                let nodes = sorted(&node_ids).into_iter().cloned().collect();
+                self.transitions.push(Transition::AcceptedNominated { values: promoted, via, nodes });
             }
         }
 
+        let accepted = &self.accepted;
+        self.nominated.retain(|value| !accepted.contains(value));
+
         // move values from accepted to confirmed
-        let (node_ids, to_promote) = self.find_quorum(todo!());
+        let unconfirmed = self.accepted.difference(&self.confirmed).cloned().collect::<HashSet<T>>();
+        if unconfirmed.is_empty() { return moved; }
+        let (node_ids, mut to_promote) = self.confirm(
+            context,
+            Protocol::Nomination,
+            HashSetPredicate::new(
+                unconfirmed,
+                |message: &Message<T>, value: &T| message.accepts_nominated(value),
+            ),
+        );
 
-        // TODO: is this check redundant?
         if !node_ids.is_empty() {
-            for value in to_promote.drain() {
-                self.confirmed.insert(value);
+            let mut promoted = to_promote.drain()
+                .filter(|value| self.confirmed.insert(value.clone()))
+                .collect::<Vec<T>>();
+
+            if !promoted.is_empty() {
+                moved = true;
+                promoted.sort();
+                let quorum = sorted(&node_ids).into_iter().cloned().collect();
+                self.transitions.push(Transition::ConfirmedNominated { values: promoted, quorum });
             }
         }
+
+        return moved;
+    }
+}
+
+/// 64-bit FNV-1a. Unlike the standard library's hasher,
+/// it's guaranteed not to change from one Rust release to the next.
+fn fnv(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes.iter() {
+        hash = (hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+/// The counter of a zero-able ballot, zero if it isn't set.
+fn number<T: Value>(ballot: &Option<Ballot<T>>) -> usize {
+    return ballot.as_ref().map_or(0, |b| b.number);
+}
+
+/// The ballot counter a statement is on.
+/// An externalized node is on every counter.
+fn counter<T: Value>(message: &Message<T>) -> usize {
+    return match &message.topic {
+        Topic::Prepare(p)     => p.ballot.number,
+        Topic::Commit(c)      => c.ballot.number,
+        Topic::Externalize(_) => usize::MAX,
+        Topic::Nominate(_)    => 0,
+    };
+}
+
+/// Finds the widest range of `boundaries` that `holds`,
+/// starting from the highest boundary it holds for and extending downwards.
+fn widest(boundaries: &[usize], mut holds: impl FnMut(usize, usize) -> bool) -> Option<(usize, usize)> {
+    let mut found: Option<(usize, usize)> = None;
+    for boundary in boundaries.iter().rev().copied() {
+        let range = match found {
+            Some((_, highest)) => (boundary, highest),
+            None               => (boundary, boundary),
+        };
+        if holds(range.0, range.1) {
+            found = Some(range);
+        } else if found.is_some() {
+            break;
+        }
+    }
+    return found;
+}
+
+// Model checking
+
+/// Sorts a set so it hashes the same no matter the order things went in.
+fn sorted<T: Ord>(set: &HashSet<T>) -> Vec<&T> {
+    let mut sorted = set.iter().collect::<Vec<&T>>();
+    sorted.sort();
+    return sorted;
+}
+
+impl<T: Value> Slot<T> {
+    /// Hashes everything that affects how the slot behaves from here on,
+    /// for the [model checker](crate::check) to tell states apart.
+    pub(crate) fn fingerprint<H: Hasher>(&self, state: &mut H) {
+        self.phase.hash(state);
+        self.nominating.hash(state);
+        self.priority_round.hash(state);
+
+        sorted(&self.nominated).hash(state);
+        sorted(&self.accepted).hash(state);
+        sorted(&self.confirmed).hash(state);
+
+        self.ballot.hash(state);
+        self.prepared_a.hash(state);
+        self.prepared_b.hash(state);
+        self.highest.hash(state);
+        self.lowest.hash(state);
+
+        // there's one statement per peer, so sorting by sender is enough
+        for protocol in [Protocol::Nomination, Protocol::Ballot].iter() {
+            let mut statements = self.statements(*protocol).values().collect::<Vec<&Message<T>>>();
+            statements.sort_by(|a, b| a.sender.cmp(&b.sender));
+            statements.hash(state);
+            self.sent(*protocol).as_ref().map(|m| &m.topic).hash(state);
+        }
+    }
+}
+
+/// A slot along with who's running it,
+/// for when there's only the one slot, like in the [model checker](crate::check)
+/// or the [simulator](crate::sim).
+pub struct Standalone<T: Value> {
+    pub node_id: NodeId,
+    pub quorum:  Quorum<T>,
+    pub slot:    Slot<T>,
+}
+
+impl<T: Value> Standalone<T> {
+    pub fn new(node_id: NodeId, quorum: Quorum<T>, slot_id: SlotId) -> Standalone<T> {
+        return Standalone { node_id, quorum, slot: Slot::new(slot_id) };
+    }
+}
+
+// Introspection
+
+/// A read-only snapshot of a [`Slot`], see [`Slot::info`].
+/// Sets are sorted, so two snapshots of the same state look the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInfo<T: Value> {
+    pub slot_id:    SlotId,
+    pub phase:      Phase,
+    pub nominating: bool,
+    pub nominated:  Vec<T>,
+    pub accepted:   Vec<T>,
+    pub confirmed:  Vec<T>,
+    /// Ballots are `None` until they're set.
+    pub ballot:     Option<Ballot<T>>,
+    pub prepared_a: Option<Ballot<T>>,
+    pub prepared_b: Option<Ballot<T>>,
+    pub highest:    Option<Ballot<T>>,
+    pub lowest:     Option<Ballot<T>>,
+    pub nomination: ProtocolInfo<T>,
+    pub balloting:  ProtocolInfo<T>,
+    /// Nodes in our quorum set we haven't heard anything from.
+    /// If we can't find a quorum, these are who we're waiting on.
+    pub missing:    Vec<NodeId>,
+}
+
+/// What we've heard for one of the sub-protocols.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolInfo<T: Value> {
+    /// The latest statement from each peer, by peer.
+    pub statements: Vec<(NodeId, Topic<T>)>,
+    /// A quorum of peers that have said something, including us,
+    /// empty if there isn't one yet.
+    pub quorum:     Vec<NodeId>,
+    /// A v-blocking set of peers that have said something,
+    /// empty if there isn't one yet.
+    pub blocking:   Vec<NodeId>,
+}
+
+impl<T: Value> ProtocolInfo<T> {
+    pub fn to_json(&self) -> Json {
+        let statements = self.statements.iter()
+            .map(|(node_id, topic)| Json::object(vec![
+                ("node",  json::node_id(node_id)),
+                ("topic", json::topic(topic)),
+            ]))
+            .collect();
+
+        return Json::object(vec![
+            ("statements", Json::Array(statements)),
+            ("quorum",     json::node_ids(&self.quorum)),
+            ("blocking",   json::node_ids(&self.blocking)),
+        ]);
+    }
+}
+
+/// A ballot that isn't set yet is written as `null`.
+fn ballot<T: Value>(ballot: &Option<Ballot<T>>) -> Json {
+    return match ballot {
+        Some(b) => json::ballot(b),
+        None    => Json::Null,
+    };
+}
+
+impl<T: Value> SlotInfo<T> {
+    pub fn to_json(&self) -> Json {
+        let phase = match self.phase {
+            Phase::Prepare     => "prepare",
+            Phase::Commit      => "commit",
+            Phase::Externalize => "externalize",
+        };
+
+        return Json::object(vec![
+            ("slot",       json::number(self.slot_id.number())),
+            ("phase",      Json::String(phase.to_string())),
+            ("nominating", Json::Bool(self.nominating)),
+            ("nominated",  json::values(&self.nominated)),
+            ("accepted",   json::values(&self.accepted)),
+            ("confirmed",  json::values(&self.confirmed)),
+            ("ballot",     ballot(&self.ballot)),
+            ("prepared_a", ballot(&self.prepared_a)),
+            ("prepared_b", ballot(&self.prepared_b)),
+            ("highest",    ballot(&self.highest)),
+            ("lowest",     ballot(&self.lowest)),
+            ("nomination", self.nomination.to_json()),
+            ("balloting",  self.balloting.to_json()),
+            ("missing",    json::node_ids(&self.missing)),
+        ]);
     }
 }
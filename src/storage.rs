@@ -0,0 +1,54 @@
+use crate::{
+    catchup::Proof,
+    slot::SlotId,
+    value::Value,
+};
+
+/// Somewhere to put externalized slots, so they outlive the process,
+/// and don't have to be kept in memory once they're old.
+/// A [`Node`] stores each slot as soon as it has a proof that holds up
+/// (or when it's evicted according to its [`Retention`], if that comes first),
+/// and looks it up here again if a peer asks about it after it's been evicted.
+/// What this is backed by (a file, a database, etc.) is up to you.
+pub trait Storage<T: Value> {
+    /// Store a decided slot, along with the messages proving it was decided.
+    fn store(&mut self, proof: Proof<T>);
+    /// Load a previously stored slot, if we have it.
+    fn load(&self, slot_id: SlotId) -> Option<Proof<T>>;
+}
+
+/// How much a [`Node`] holds on to in memory.
+/// The default is to keep everything,
+/// which is fine for tests but not for a long-running node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// How many of the most recent externalized slots to keep in memory.
+    /// Older slots are evicted to [`Storage`], if there is any.
+    pub externalized: usize,
+    /// How far below the latest externalized slot a pending slot can fall
+    /// before we give up on it and purge it.
+    pub pending: usize,
+    /// How far past the latest externalized slot (or the first slot, if there isn't one)
+    /// a peer can get us to start a slot, or hold on to what they said about it.
+    /// Anything further ahead is ignored,
+    /// apart from noting how far ahead they are, see [`Node::behind`](crate::node::Node::behind).
+    pub ahead: usize,
+}
+
+impl Default for Retention {
+    fn default() -> Retention {
+        return Retention {
+            externalized: usize::MAX,
+            pending:      usize::MAX,
+            ahead:        usize::MAX,
+        };
+    }
+}
+
+/// How much a [`Node`] is holding in memory right now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Usage {
+    pub pending:      usize,
+    pub externalized: usize,
+    pub messages:     usize,
+}
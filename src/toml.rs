@@ -0,0 +1,412 @@
+//! Just enough TOML to read config files, without pulling in a dependency.
+//! Supports tables, arrays of tables, dotted keys, inline tables,
+//! arrays, basic and literal strings, integers, and booleans.
+//! No floats, dates, or multi-line strings: configs here don't need them.
+
+use std::{
+    convert::TryFrom,
+    iter::Peekable,
+    str::Chars,
+};
+
+use crate::json::Json;
+
+// TODO: better error types
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Toml {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Toml>),
+    /// Keys are kept in the order they were written.
+    Table(Vec<(String, Toml)>),
+}
+
+impl Toml {
+    /// Looks up a key, if this is a table.
+    pub fn get(&self, key: &str) -> Option<&Toml> {
+        return self.as_table()?.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return if let Toml::String(s) = self { Some(s) } else { None };
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        return if let Toml::Integer(n) = self { Some(*n) } else { None };
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        return if let Toml::Bool(b) = self { Some(*b) } else { None };
+    }
+
+    pub fn as_array(&self) -> Option<&[Toml]> {
+        return if let Toml::Array(items) = self { Some(items) } else { None };
+    }
+
+    pub fn as_table(&self) -> Option<&[(String, Toml)]> {
+        return if let Toml::Table(fields) = self { Some(fields) } else { None };
+    }
+}
+
+/// Parses a whole document into a table.
+pub fn parse(input: &str) -> Result<Toml, ()> {
+    let mut parser = Parser { chars: input.chars().peekable() };
+    let mut root = Toml::Table(vec![]);
+    // the table `key = value` lines go into
+    let mut current = vec![];
+
+    loop {
+        parser.skip_blank_lines();
+        match parser.chars.peek() {
+            None => { break; },
+            Some('[') => {
+                parser.chars.next();
+                let array = parser.eat('[');
+                parser.skip_spaces();
+                let path = parser.key()?;
+                parser.skip_spaces();
+                parser.expect(']')?;
+                if array { parser.expect(']')?; }
+                parser.end_of_line()?;
+
+                if array {
+                    let (last, parent) = path.split_last().ok_or(())?;
+                    let parent = table_at(&mut root, parent)?;
+                    if !parent.iter().any(|(k, _)| k == last) {
+                        parent.push((last.clone(), Toml::Array(vec![])));
+                    }
+                    match parent.iter_mut().find(|(k, _)| k == last) {
+                        Some((_, Toml::Array(items))) => items.push(Toml::Table(vec![])),
+                        _ => { return Err(()); },
+                    }
+                } else {
+                    table_at(&mut root, &path)?;
+                }
+                current = path;
+            },
+            Some(_) => {
+                let key = parser.key()?;
+                parser.skip_spaces();
+                parser.expect('=')?;
+                parser.skip_spaces();
+                let value = parser.value()?;
+                parser.end_of_line()?;
+
+                // TODO: safe to unwrap? keys always have at least one part
+                let (last, prefix) = key.split_last().unwrap();
+                let path = current.iter().chain(prefix.iter()).cloned().collect::<Vec<String>>();
+                insert(table_at(&mut root, &path)?, last.clone(), value)?;
+            },
+        }
+    }
+
+    return Ok(root);
+}
+
+/// Converts JSON to the equivalent TOML, so the same readers work on both.
+/// There's no TOML `null`, so JSON with one in it can't be converted.
+pub fn from_json(json: &Json) -> Result<Toml, ()> {
+    return Ok(match json {
+        Json::Null         => { return Err(()); },
+        Json::Bool(b)      => Toml::Bool(*b),
+        Json::Number(n)    => Toml::Integer(i64::try_from(*n).map_err(|_| ())?),
+        Json::String(s)    => Toml::String(s.clone()),
+        Json::Array(items) => Toml::Array(items.iter().map(from_json).collect::<Result<Vec<Toml>, ()>>()?),
+        Json::Object(fields) => {
+            let mut table = vec![];
+            for (key, value) in fields.iter() { insert(&mut table, key.clone(), from_json(value)?)?; }
+            Toml::Table(table)
+        },
+    });
+}
+
+/// Finds the table at `path`, creating any tables along the way.
+/// An array of tables on the path means its last table.
+fn table_at<'t>(root: &'t mut Toml, path: &[String]) -> Result<&'t mut Vec<(String, Toml)>, ()> {
+    let mut table = match root {
+        Toml::Table(fields) => fields,
+        _                   => { return Err(()); },
+    };
+
+    for part in path.iter() {
+        if !table.iter().any(|(k, _)| k == part) {
+            table.push((part.clone(), Toml::Table(vec![])));
+        }
+
+        // TODO: safe to unwrap? we just made sure it's there
+        let next = &mut table.iter_mut().find(|(k, _)| k == part).unwrap().1;
+        table = match next {
+            Toml::Table(fields) => fields,
+            Toml::Array(items)  => match items.last_mut() {
+                Some(Toml::Table(fields)) => fields,
+                _                         => { return Err(()); },
+            },
+            _ => { return Err(()); },
+        };
+    }
+
+    return Ok(table);
+}
+
+fn insert(table: &mut Vec<(String, Toml)>, key: String, value: Toml) -> Result<(), ()> {
+    if table.iter().any(|(k, _)| *k == key) { return Err(()); }
+    table.push((key, value));
+    return Ok(());
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn eat(&mut self, c: char) -> bool {
+        if self.chars.peek() == Some(&c) { self.chars.next(); return true; }
+        return false;
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ()> {
+        return if self.eat(c) { Ok(()) } else { Err(()) };
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(' ') | Some('\t') = self.chars.peek() { self.chars.next(); }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.chars.peek() != Some(&'#') { return; }
+        while let Some(c) = self.chars.peek() {
+            if *c == '\n' { return; }
+            self.chars.next();
+        }
+    }
+
+    /// Skips whitespace, newlines and comments, e.g. between lines or inside arrays.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.chars.peek() {
+                Some('\n') | Some('\r') => { self.chars.next(); },
+                _                       => { return; },
+            }
+        }
+    }
+
+    /// Makes sure nothing but a comment is left on the line.
+    fn end_of_line(&mut self) -> Result<(), ()> {
+        self.skip_spaces();
+        self.skip_comment();
+        self.eat('\r');
+        return match self.chars.next() {
+            None | Some('\n') => Ok(()),
+            Some(_)           => Err(()),
+        };
+    }
+
+    /// A possibly dotted key, like `quorum.inner` or `"quoted key"`.
+    fn key(&mut self) -> Result<Vec<String>, ()> {
+        let mut parts = vec![];
+        loop {
+            self.skip_spaces();
+            let part = match self.chars.peek() {
+                Some('"')  => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let mut part = String::new();
+                    while let Some(c) = self.chars.peek() {
+                        if !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-') { break; }
+                        part.push(*c);
+                        self.chars.next();
+                    }
+                    if part.is_empty() { return Err(()); }
+                    part
+                },
+            };
+            parts.push(part);
+
+            self.skip_spaces();
+            if !self.eat('.') { return Ok(parts); }
+        }
+    }
+
+    fn value(&mut self) -> Result<Toml, ()> {
+        return match self.chars.peek() {
+            Some('"')  => Ok(Toml::String(self.basic_string()?)),
+            Some('\'') => Ok(Toml::String(self.literal_string()?)),
+            Some('[')  => self.array(),
+            Some('{')  => self.inline_table(),
+            Some('t') | Some('f') => self.bool(),
+            Some(c) if c.is_ascii_digit() || *c == '+' || *c == '-' => self.integer(),
+            _ => Err(()),
+        };
+    }
+
+    fn basic_string(&mut self) -> Result<String, ()> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next().ok_or(())? {
+                '"'  => { return Ok(string); },
+                '\n' => { return Err(()); },
+                '\\' => string.push(match self.chars.next().ok_or(())? {
+                    '"'  => '"',
+                    '\\' => '\\',
+                    'n'  => '\n',
+                    't'  => '\t',
+                    'r'  => '\r',
+                    'u'  => {
+                        let hex = (0..4).map(|_| self.chars.next().ok_or(())).collect::<Result<String, ()>>()?;
+                        let code = u32::from_str_radix(&hex, 16).map_err(|_| ())?;
+                        char::from_u32(code).ok_or(())?
+                    },
+                    _ => { return Err(()); },
+                }),
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, ()> {
+        self.expect('\'')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next().ok_or(())? {
+                '\'' => { return Ok(string); },
+                '\n' => { return Err(()); },
+                c    => string.push(c),
+            }
+        }
+    }
+
+    fn bool(&mut self) -> Result<Toml, ()> {
+        let mut word = String::new();
+        while let Some(c) = self.chars.peek() {
+            if !c.is_ascii_alphabetic() { break; }
+            word.push(*c);
+            self.chars.next();
+        }
+        return match word.as_str() {
+            "true"  => Ok(Toml::Bool(true)),
+            "false" => Ok(Toml::Bool(false)),
+            _       => Err(()),
+        };
+    }
+
+    fn integer(&mut self) -> Result<Toml, ()> {
+        let mut digits = String::new();
+        if let Some(c) = self.chars.peek() {
+            if *c == '+' || *c == '-' { digits.push(*c); self.chars.next(); }
+        }
+        while let Some(c) = self.chars.peek() {
+            if c.is_ascii_digit() { digits.push(*c); }
+            else if *c != '_' { break; }
+            self.chars.next();
+        }
+        return digits.parse().map(Toml::Integer).map_err(|_| ());
+    }
+
+    fn array(&mut self) -> Result<Toml, ()> {
+        self.expect('[')?;
+        let mut items = vec![];
+        loop {
+            self.skip_blank_lines();
+            if self.eat(']') { return Ok(Toml::Array(items)); }
+            items.push(self.value()?);
+            self.skip_blank_lines();
+            // a trailing comma is fine
+            if !self.eat(',') {
+                self.skip_blank_lines();
+                self.expect(']')?;
+                return Ok(Toml::Array(items));
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Toml, ()> {
+        self.expect('{')?;
+        let mut table = Toml::Table(vec![]);
+        self.skip_spaces();
+        if self.eat('}') { return Ok(table); }
+
+        loop {
+            let key = self.key()?;
+            self.skip_spaces();
+            self.expect('=')?;
+            self.skip_spaces();
+            let value = self.value()?;
+
+            // TODO: safe to unwrap? keys always have at least one part
+            let (last, prefix) = key.split_last().unwrap();
+            insert(table_at(&mut table, prefix)?, last.clone(), value)?;
+
+            self.skip_spaces();
+            if self.eat('}') { return Ok(table); }
+            self.expect(',')?;
+            self.skip_spaces();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document() {
+        let toml = parse(r#"
+            # a comment
+            id = "a"   # another
+            port = 7_000
+            verbose = false
+            peers.b = 'literal \n'
+
+            [quorum]
+            threshold = 2
+            nodes = [
+                "a",
+                "b!", # trailing comma
+            ]
+            inner = [{ threshold = 1, nodes = [] }]
+
+            [[quorum.more]]
+            threshold = 1
+            [[quorum.more]]
+            threshold = -2
+        "#).unwrap();
+
+        assert_eq!(toml.get("id"), Some(&Toml::String("a".to_string())));
+        assert_eq!(toml.get("port").and_then(Toml::as_integer), Some(7000));
+        assert_eq!(toml.get("verbose").and_then(Toml::as_bool), Some(false));
+        assert_eq!(toml.get("peers").and_then(|p| p.get("b")).and_then(Toml::as_str), Some("literal \\n"));
+
+        let quorum = toml.get("quorum").unwrap();
+        assert_eq!(quorum.get("nodes"), Some(&Toml::Array(vec![
+            Toml::String("a".to_string()),
+            Toml::String("b!".to_string()),
+        ])));
+        assert_eq!(quorum.get("inner").unwrap().as_array().unwrap()[0].get("threshold"), Some(&Toml::Integer(1)));
+
+        let more = quorum.get("more").unwrap().as_array().unwrap();
+        assert_eq!(more.len(), 2);
+        assert_eq!(more[1].get("threshold"), Some(&Toml::Integer(-2)));
+    }
+
+    #[test]
+    fn malformed() {
+        for input in [
+            "a = ",
+            "a = 1 2",
+            "a = 1\na = 2",
+            "a = \"unterminated",
+            "a = [1, 2",
+            "a = 1.5",
+            "[a\nb = 1",
+            "a = 1\n[a]",
+            "= 1",
+        ].iter() {
+            assert_eq!(parse(input), Err(()), "{:?}", input);
+        }
+    }
+}
@@ -1,26 +1,77 @@
 use std::{
     cmp::{PartialOrd, Ord, Ordering},
-    collections::HashSet
+    collections::HashSet,
+    hash::{Hash, Hasher},
 };
 
 use crate::{ballot::Ballot, value::Value};
 
-/// A totally orderable [`Topic`], i.e. something to vote on.
+/// A partially orderable [`Topic`], i.e. something to vote on.
 /// An enumeration that represents states in the state machine
 /// needed to reach consensus.
-#[derive(Debug, PartialEq, Eq)]
+/// Ballot topics are totally ordered,
+/// but two [`Nominate`] topics might not be comparable at all,
+/// see [`Topic::supersedes`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Topic<T: Value> {
     Nominate(Nominate<T>),
-    // TODO: why does this even exist?
-    NominatePrepare(Nominate<T>, Prepare<T>),
     Prepare(Prepare<T>),
     Commit(Commit<T>),
     Externalize(Externalize<T>),
 }
 
+/// SCP runs two sub-protocols side by side for each slot:
+/// nomination, which comes up with candidate values,
+/// and balloting, which decides on one of them.
+/// Every [`Topic`] belongs to exactly one of the two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Nomination,
+    Ballot,
+}
+
+/// Which variant of [`Topic`] something is, without the contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    Nominate,
+    Prepare,
+    Commit,
+    Externalize,
+}
+
+impl<T: Value> Topic<T> {
+    pub fn kind(&self) -> Kind {
+        return match self {
+            Topic::Nominate(_)    => Kind::Nominate,
+            Topic::Prepare(_)     => Kind::Prepare,
+            Topic::Commit(_)      => Kind::Commit,
+            Topic::Externalize(_) => Kind::Externalize,
+        };
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        return match self {
+            Topic::Nominate(_) => Protocol::Nomination,
+            _                  => Protocol::Ballot,
+        };
+    }
+
+    /// Whether this topic should replace `previous`
+    /// as the latest statement we have from a peer.
+    /// Errors if the two can't be ordered,
+    /// which means one of them is bogus.
+    pub fn supersedes(&self, previous: &Topic<T>) -> Result<bool, ()> {
+        return match self.partial_cmp(previous) {
+            Some(Ordering::Greater) => Ok(true),
+            Some(_)                 => Ok(false),
+            None                    => Err(()),
+        };
+    }
+}
+
 // Nominate topic implementation
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Nominate<T: Value> {
     pub nominated: HashSet<T>,
     // 1. A _quorum_ votes-or-accepts the same value;
@@ -28,32 +79,53 @@ pub struct Nominate<T: Value> {
     pub accepted:  HashSet<T>,
 }
 
-// equality is length-based - we never actually compare the sets.
-impl<T: Value> PartialEq for Nominate<T> {
-    fn eq(&self, other: &Self) -> bool {
-          self.nominated.len() == other.nominated.len()
-        && self.accepted.len() == other.accepted.len()
+impl<T: Value> Nominate<T> {
+    /// Every value this statement votes for or accepts.
+    fn voted_or_accepted(&self) -> HashSet<&T> {
+        return self.nominated.union(&self.accepted).collect();
+    }
+
+    /// Whether this statement says everything `other` does, and maybe more.
+    fn includes(&self, other: &Self) -> bool {
+        return self.accepted.is_superset(&other.accepted)
+            && self.voted_or_accepted().is_superset(&other.voted_or_accepted());
     }
 }
 
-impl<T: Value> Eq for Nominate<T> {}
+// HashSets don't hash, so we hash the values in order instead.
+impl<T: Value> Hash for Nominate<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut nominated = self.nominated.iter().collect::<Vec<&T>>();
+        let mut accepted  = self.accepted.iter().collect::<Vec<&T>>();
+        nominated.sort();
+        accepted.sort();
+        nominated.hash(state);
+        accepted.hash(state);
+    }
+}
 
-impl<T: Value> Ord for Nominate<T> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // comapare two nominated ballots
-        // one with more nominations win
-        // one with more accepteds wins in case of a tie
-        if self.accepted.len() == other.accepted.len() {
-            self.nominated.len().cmp(&other.nominated.len())
-        } else {
-            self.accepted.len().cmp(&other.accepted.len())
-        }
+// A nominate statement only ever grows:
+// values get voted for, and voted values get accepted.
+// Because values move from `nominated` to `accepted` once accepted,
+// `nominated` on its own might shrink,
+// so we compare the votes-or-accepts set instead.
+// Statements where neither includes the other can't be ordered,
+// and one of them must be bogus.
+impl<T: Value> PartialOrd for Nominate<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other { return Some(Ordering::Equal); }
+
+        return match (self.includes(other), other.includes(self)) {
+            (true, _) => Some(Ordering::Greater),
+            (_, true) => Some(Ordering::Less),
+            _         => None,
+        };
     }
 }
 
 // Prepare topic implementation
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Prepare<T: Value> {
     pub ballot:      Ballot<T>,
     pub prepared_a:  Ballot<T>,
@@ -79,13 +151,19 @@ impl<T: Value> Ord for Prepare<T> {
         }
 
         // all ballots being equal, compare sequence numbers
-        return self.highest.cmp(&other.highest);
+        match self.highest.cmp(&other.highest) {
+            Ordering::Equal => (),
+            order           => { return order; }
+        }
+
+        // only here so the order agrees with Eq
+        return self.lowest.cmp(&other.lowest);
     }
 }
 
 // Commit topic implementation
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Commit<T: Value> {
     pub ballot:   Ballot<T>,
     pub prepared: usize,
@@ -108,13 +186,19 @@ impl<T: Value> Ord for Commit<T> {
         }
 
         // all else being the same, compare highest
-        return self.highest.cmp(&other.highest);
+        match self.highest.cmp(&other.highest) {
+            Ordering::Equal => (),
+            order           => { return order; }
+        }
+
+        // only here so the order agrees with Eq
+        return self.lowest.cmp(&other.lowest);
     }
 }
 
 // Externalize topic implementation
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Externalize<T: Value> {
     pub ballot:  Ballot<T>,
     pub highest: usize,
@@ -122,7 +206,14 @@ pub struct Externalize<T: Value> {
 
 impl<T: Value> Ord for Externalize<T> {
     fn cmp(&self, other: &Self) -> Ordering {
-        return self.highest.cmp(&other.highest);
+        match self.highest.cmp(&other.highest) {
+            Ordering::Equal => (),
+            order           => { return order; }
+        }
+
+        // two externalizes with different ballots contradict each other,
+        // but the order still has to agree with Eq
+        return self.ballot.cmp(&other.ballot);
     }
 }
 
@@ -130,45 +221,130 @@ impl<T: Value> Ord for Externalize<T> {
 
 // Partial ordering
 
-impl<T: Value> PartialOrd for Topic      <T> { fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) } }
-impl<T: Value> PartialOrd for Nominate   <T> { fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) } }
 impl<T: Value> PartialOrd for Prepare    <T> { fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) } }
 impl<T: Value> PartialOrd for Commit     <T> { fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) } }
 impl<T: Value> PartialOrd for Externalize<T> { fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) } }
 
-// Total ordering for topic
+// Partial ordering for topic
 
-impl<T: Value> Ord for Topic<T> {
-    fn cmp(&self, other: &Self) -> Ordering {
+impl<T: Value> PartialOrd for Topic<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         use Topic::*;
 
         // number closure
         let number = |t: &Topic<T>| -> u8 {match t {
-            Nominate(_)           => 0,
-            NominatePrepare(_, _) => 1,
-            Prepare(_)            => 2,
-            Commit(_)             => 3,
-            Externalize(_)        => 4,
+            Nominate(_)    => 0,
+            Prepare(_)     => 1,
+            Commit(_)      => 2,
+            Externalize(_) => 3,
         }};
 
         return match (self, other) {
-            (NominatePrepare(sn, sp), NominatePrepare(on, op)) => {
-                // compare nominate topics,
-                // defaulting to prepare in case of a tie.
-                match Ord::cmp(sn, on) {
-                    Ordering::Equal => Ord::cmp(sp, op),
-                    order           => order,
-                }
-            },
-
             // defer ordering to individual structs
-            (Nominate(s),    Nominate(o))    => s.cmp(o),
-            (Prepare(s),     Prepare(o))     => s.cmp(o),
-            (Commit(s),      Commit(o))      => s.cmp(o),
-            (Externalize(s), Externalize(o)) => s.cmp(o),
+            (Nominate(s),    Nominate(o))    => s.partial_cmp(o),
+            (Prepare(s),     Prepare(o))     => Some(s.cmp(o)),
+            (Commit(s),      Commit(o))      => Some(s.cmp(o)),
+            (Externalize(s), Externalize(o)) => Some(s.cmp(o)),
 
             // not the same, just compare numbers
-            (s, o) => number(s).cmp(&number(o))
+            (s, o) => Some(number(s).cmp(&number(o)))
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arbitrary::{Arbitrary, Source},
+        fixtures::DummyValue,
+    };
+
+    fn nominate(nominated: &[usize], accepted: &[usize]) -> Topic<DummyValue> {
+        Topic::Nominate(Nominate {
+            nominated: nominated.iter().map(|v| DummyValue(*v)).collect(),
+            accepted:  accepted.iter().map(|v| DummyValue(*v)).collect(),
+        })
+    }
+
+    #[test]
+    fn same_size_different_votes() {
+        let old = nominate(&[1, 2], &[]);
+        let new = nominate(&[1, 3], &[]);
+        assert_ne!(old, new);
+        assert_eq!(new.supersedes(&old), Err(()));
+    }
+
+    #[test]
+    fn new_vote_supersedes() {
+        let old = nominate(&[1], &[]);
+        let new = nominate(&[1, 2], &[]);
+        assert_eq!(new.supersedes(&old), Ok(true));
+        assert_eq!(old.supersedes(&new), Ok(false));
+    }
+
+    #[test]
+    fn accepting_supersedes() {
+        // the value moves from nominated to accepted
+        let old = nominate(&[1, 2], &[]);
+        let new = nominate(&[2], &[1]);
+        assert_eq!(new.supersedes(&old), Ok(true));
+        assert_eq!(old.supersedes(&new), Ok(false));
+    }
+
+    #[test]
+    fn unaccepting_is_bogus() {
+        let old = nominate(&[], &[1]);
+        let new = nominate(&[1, 2], &[]);
+        assert_eq!(new.supersedes(&old), Err(()));
+    }
+
+    #[test]
+    fn same_statement_does_not_supersede() {
+        let old = nominate(&[1, 2], &[3]);
+        let new = nominate(&[2, 1], &[3]);
+        assert_eq!(old, new);
+        assert_eq!(new.supersedes(&old), Ok(false));
+    }
+
+    // Properties
+
+    const CASES: u64 = 2000;
+
+    /// Checks that `Ord` is a total order that agrees with `Eq`,
+    /// over arbitrary triples.
+    fn total_order<O: Ord + Arbitrary + std::fmt::Debug>() {
+        for seed in 0..CASES {
+            let mut source = Source::new(seed);
+            let (a, b, c) = (O::arbitrary(&mut source), O::arbitrary(&mut source), O::arbitrary(&mut source));
+
+            assert_eq!(a.cmp(&b) == Ordering::Equal, a == b, "{:?} {:?}", a, b);
+            assert_eq!(a.cmp(&b), b.cmp(&a).reverse(), "{:?} {:?}", a, b);
+            if a <= b && b <= c { assert!(a <= c, "{:?} {:?} {:?}", a, b, c); }
+        }
+    }
+
+    #[test]
+    fn ballot_order() { total_order::<Ballot<DummyValue>>(); }
+    #[test]
+    fn prepare_order() { total_order::<Prepare<DummyValue>>(); }
+    #[test]
+    fn commit_order() { total_order::<Commit<DummyValue>>(); }
+    #[test]
+    fn externalize_order() { total_order::<Externalize<DummyValue>>(); }
+
+    /// Topics are only partially ordered, because of nominate statements,
+    /// but the order still has to agree with `Eq` and be antisymmetric.
+    #[test]
+    fn topic_order() {
+        for seed in 0..CASES {
+            let mut source = Source::new(seed);
+            let a = Topic::<DummyValue>::arbitrary(&mut source);
+            let b = Topic::<DummyValue>::arbitrary(&mut source);
+
+            assert_eq!(a.partial_cmp(&b) == Some(Ordering::Equal), a == b, "{:?} {:?}", a, b);
+            assert_eq!(a.partial_cmp(&b), b.partial_cmp(&a).map(Ordering::reverse), "{:?} {:?}", a, b);
+            assert_eq!(a.partial_cmp(&a), Some(Ordering::Equal));
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Recording and replaying what a [`Node`] sees and says,
+//! so a slot that gets stuck in production can be reproduced locally.
+//! A [`Recorder`] is handed every inbound message, every timer firing,
+//! every value we propose, and every outbound message, in order.
+//! [`TraceWriter`] stamps them and writes them out with [`codec`],
+//! and [`replay`] feeds a trace back into a fresh node,
+//! checking that it says exactly the same things, byte for byte.
+//! This only works because slots are deterministic:
+//! the same inputs in the same order give the same outputs.
+
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    codec::{self, Encode, Decode},
+    message::Message,
+    node::Node,
+    slot::SlotId,
+    value::Value,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<T: Value> {
+    Inbound(Message<T>),
+    /// The timer for a slot fired.
+    Timeout(SlotId),
+    Outbound(Message<T>),
+    /// We proposed a value for a slot.
+    Propose(SlotId, T),
+}
+
+/// An [`Event`], along with when it happened,
+/// relative to when recording started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record<T: Value> {
+    pub at:    Duration,
+    pub event: Event<T>,
+}
+
+/// Somewhere to send events as a [`Node`] runs, see [`Node::set_recorder`].
+pub trait Recorder<T: Value> {
+    fn record(&mut self, event: Event<T>);
+}
+
+// Encoding
+
+impl<T: Value + Encode> Encode for Record<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.at.as_micros() as u64).encode(out);
+        match &self.event {
+            Event::Inbound(m)  => { 0u8.encode(out); m.encode(out); },
+            Event::Timeout(s)  => { 1u8.encode(out); s.encode(out); },
+            Event::Outbound(m) => { 2u8.encode(out); m.encode(out); },
+            Event::Propose(s, v) => { 3u8.encode(out); s.encode(out); v.encode(out); },
+        }
+    }
+}
+
+impl<T: Value + Decode> Decode for Record<T> {
+    fn decode(input: &mut &[u8]) -> Result<Record<T>, ()> {
+        let at = Duration::from_micros(u64::decode(input)?);
+        let event = match u8::decode(input)? {
+            0 => Event::Inbound(Message::decode(input)?),
+            1 => Event::Timeout(SlotId::decode(input)?),
+            2 => Event::Outbound(Message::decode(input)?),
+            3 => Event::Propose(SlotId::decode(input)?, T::decode(input)?),
+            _ => { return Err(()); },
+        };
+        return Ok(Record { at, event });
+    }
+}
+
+// Writing
+
+/// Writes events to a trace file (or anything else), as they happen.
+/// Each record is prefixed with its length.
+/// Writing is best-effort, a node shouldn't stop because its trace can't be written;
+/// the first error is kept, and returned by [`TraceWriter::finish`].
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    start:  Instant,
+    error:  Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> TraceWriter<W> {
+        return TraceWriter { writer, start: Instant::now(), error: None };
+    }
+
+    /// Flushes the trace, returning the writer, or the first error we hit.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error { return Err(error); }
+        self.writer.flush()?;
+        return Ok(self.writer);
+    }
+}
+
+impl<T: Value + Encode, W: Write> Recorder<T> for TraceWriter<W> {
+    fn record(&mut self, event: Event<T>) {
+        if self.error.is_some() { return; }
+
+        let record = Record { at: self.start.elapsed(), event };
+        let bytes = codec::to_bytes(&record);
+
+        let mut framed = codec::to_bytes(&bytes.len());
+        framed.extend(bytes);
+        if let Err(error) = self.writer.write_all(&framed) {
+            self.error = Some(error);
+        }
+    }
+}
+
+/// Reads back a trace written by a [`TraceWriter`].
+// TODO: better error types
+pub fn read<T: Value + Decode, R: Read>(mut reader: R) -> Result<Vec<Record<T>>, ()> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes).map_err(|_| ())?;
+
+    let mut input = &bytes[..];
+    let mut records = vec![];
+    while !input.is_empty() {
+        let len = usize::decode(&mut input)?;
+        if len > input.len() { return Err(()); }
+
+        let (record, rest) = input.split_at(len);
+        records.push(codec::from_bytes(record)?);
+        input = rest;
+    }
+
+    return Ok(records);
+}
+
+// Replaying
+
+/// Where a replay first said something different from the trace.
+/// `expected` is what the trace says was sent, and `actual` what was sent on replay;
+/// either is `None` if one side sent fewer messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence<T: Value> {
+    /// The index of the record the divergence was found at.
+    pub at:       usize,
+    pub expected: Option<Message<T>>,
+    pub actual:   Option<Message<T>>,
+}
+
+/// Feeds the inbound messages, timeouts and proposals in a trace into `node`,
+/// which should be in the same state as the recorded node was when recording started
+/// (usually, freshly built with the same id and quorum set).
+/// Every message the node sends has to encode to exactly the same bytes
+/// as the next outbound message in the trace.
+/// Inbound messages the node rejected are replayed too,
+/// and have to be rejected again (i.e. send nothing).
+// a replay only diverges once, so the size of the error doesn't matter
+#[allow(clippy::result_large_err)]
+pub fn replay<T: Value + Encode>(node: &mut Node<T>, records: &[Record<T>]) -> Result<(), Divergence<T>> {
+    let mut index = 0;
+
+    while index < records.len() {
+        let actual = match &records[index].event {
+            Event::Inbound(message) => node.handle(message).unwrap_or_default(),
+            Event::Timeout(slot_id) => node.timeout(*slot_id),
+            Event::Propose(slot_id, value) => node.propose(*slot_id, value.clone()),
+            // we should have matched this up to what caused it
+            Event::Outbound(message) => {
+                return Err(Divergence { at: index, expected: Some(message.clone()), actual: None });
+            },
+        };
+        index += 1;
+
+        // everything sent in response directly follows in the trace
+        let mut actual = actual.into_iter();
+        loop {
+            let expected = match records.get(index).map(|r| &r.event) {
+                Some(Event::Outbound(message)) => Some(message),
+                _                              => None,
+            };
+            let sent = actual.next();
+
+            match (expected, sent) {
+                (None, None) => break,
+                (Some(e), Some(a)) if codec::to_bytes(e) == codec::to_bytes(&a) => { index += 1; },
+                (expected, actual) => {
+                    return Err(Divergence { at: index, expected: expected.cloned(), actual });
+                },
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        cell::RefCell,
+        collections::HashMap,
+        rc::Rc,
+    };
+    use crate::{
+        arbitrary::{Arbitrary, Source},
+        fixtures::{node_id, DummyValue},
+        quorum::{Member, Quorum},
+    };
+
+    #[test]
+    fn write_and_read() {
+        let mut source = Source::new(3);
+        let events = vec![
+            Event::Inbound(Message::<DummyValue>::arbitrary(&mut source)),
+            Event::Outbound(Message::arbitrary(&mut source)),
+            Event::Timeout(SlotId::new(2)),
+            Event::Outbound(Message::arbitrary(&mut source)),
+            Event::Propose(SlotId::new(2), DummyValue(1)),
+        ];
+
+        let mut writer = TraceWriter::new(vec![]);
+        for event in events.iter() { writer.record(event.clone()); }
+        let bytes = writer.finish().unwrap();
+
+        let records = read::<DummyValue, _>(&bytes[..]).unwrap();
+        let read_events = records.into_iter().map(|r| r.event).collect::<Vec<Event<DummyValue>>>();
+        assert_eq!(read_events, events);
+
+        // a torn write at the end of the file
+        assert!(read::<DummyValue, _>(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    /// Keeps records where the test can still get at them once the node has the recorder.
+    struct Shared(Rc<RefCell<Vec<Record<DummyValue>>>>);
+
+    impl Recorder<DummyValue> for Shared {
+        fn record(&mut self, event: Event<DummyValue>) {
+            self.0.borrow_mut().push(Record { at: Duration::from_secs(0), event });
+        }
+    }
+
+    fn node(name: &str) -> Node<DummyValue> {
+        let members = ["a", "b", "c"].iter().map(|n| Member::Node(node_id(n))).collect();
+        Node::new(node_id(name), Quorum::new(2, members), HashMap::new())
+    }
+
+    #[test]
+    fn replays() {
+        let records = Rc::new(RefCell::new(vec![]));
+        let mut nodes = [node("a"), node("b"), node("c")];
+        nodes[0].set_recorder(Box::new(Shared(records.clone())));
+
+        // everyone proposes, a times out once, and then we let it settle
+        let slot_id = SlotId::new(1);
+        let mut inflight = vec![];
+        for (i, node) in nodes.iter_mut().enumerate() {
+            inflight.extend(node.propose(slot_id, DummyValue(i)));
+        }
+        inflight.extend(nodes[0].timeout(slot_id));
+        while !inflight.is_empty() {
+            let message = inflight.remove(0);
+            for node in nodes.iter_mut().filter(|n| n.id != message.sender) {
+                inflight.extend(node.handle(&message).unwrap_or_default());
+            }
+        }
+        assert!(nodes[0].externalized(slot_id).is_some());
+
+        let records = records.borrow().clone();
+        assert_eq!(replay(&mut node("a"), &records), Ok(()));
+
+        // a node that isn't the one recorded says different things
+        let divergence = replay(&mut node("b"), &records).unwrap_err();
+        assert_eq!(divergence.expected.map(|m| m.sender), Some(node_id("a")));
+    }
+}
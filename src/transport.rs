@@ -0,0 +1,492 @@
+//! Getting messages between nodes.
+//! A [`Transport`] knows who our peers are,
+//! and can send a message to one of them or to all of them,
+//! and wait for messages to arrive.
+//! [`Channel`] keeps everything in one process, which is what you want for tests;
+//! [`Tcp`] sends length-framed [`codec`] messages over `std::net`,
+//! so a small cluster can run across processes on one machine.
+//! It can carry [`catchup`] requests too, which other transports leave to you.
+//!
+//! This is the blocking, thread-friendly side of things;
+//! for async, see [`asynchronous`](crate::asynchronous).
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, RecvTimeoutError},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    catchup,
+    codec::{self, Encode, Decode},
+    message::Message,
+    node::{Node, NodeId},
+    value::Value,
+};
+
+// TODO: better error types
+
+pub trait Transport<T: Value> {
+    /// Who we are.
+    fn node_id(&self) -> &NodeId;
+    /// Who we can send to.
+    fn peers(&self) -> Vec<NodeId>;
+    /// Sends a message to a single peer.
+    fn send_to(&mut self, peer: &NodeId, message: &Message<T>) -> Result<(), ()>;
+    /// Waits up to `timeout` for the next message, returning `None` if none came.
+    /// Also returns the peer that handed it to us, who isn't necessarily its sender.
+    /// Errors if the transport is closed and nothing else can arrive.
+    fn recv_from(&mut self, timeout: Duration) -> Result<Option<(NodeId, Message<T>)>, ()>;
+
+    /// Like [`recv_from`](Transport::recv_from), when we don't care who delivered it.
+    fn recv(&mut self, timeout: Duration) -> Result<Option<Message<T>>, ()> {
+        return Ok(self.recv_from(timeout)?.map(|(_, message)| message));
+    }
+
+    /// Sends a message to every peer.
+    /// A peer we can't reach doesn't stop the others from getting it,
+    /// but still makes this return an error.
+    fn broadcast(&mut self, message: &Message<T>) -> Result<(), ()> {
+        let mut result = Ok(());
+        for peer in self.peers() {
+            if self.send_to(&peer, message).is_err() { result = Err(()); }
+        }
+        return result;
+    }
+}
+
+/// Waits up to `timeout` for a message, hands it to `node`,
+/// and broadcasts whatever the node says back.
+/// Returns whether a message was handled.
+/// Peers being unreachable isn't an error, they'll catch up;
+/// only the transport closing is.
+pub fn step<T: Value, R: Transport<T>>(
+    node:      &mut Node<T>,
+    transport: &mut R,
+    timeout:   Duration,
+) -> Result<bool, ()> {
+    let message = match transport.recv(timeout)? {
+        Some(message) => message,
+        None          => { return Ok(false); },
+    };
+
+    // bad messages are already counted in the node's metrics
+    for outbound in node.handle(&message).unwrap_or_default() {
+        let _ = transport.broadcast(&outbound);
+    }
+    return Ok(true);
+}
+
+// In-process channels
+
+/// A transport for nodes that all live in the same process,
+/// built on `std::sync::mpsc`.
+pub struct Channel<T: Value> {
+    node_id: NodeId,
+    inbound: Receiver<(NodeId, Message<T>)>,
+    peers:   HashMap<NodeId, Sender<(NodeId, Message<T>)>>,
+}
+
+impl<T: Value> Channel<T> {
+    /// Builds a fully connected network, one channel per node, in the same order.
+    pub fn network(node_ids: &[NodeId]) -> Vec<Channel<T>> {
+        let (senders, receivers): (Vec<_>, Vec<_>) = node_ids.iter()
+            .map(|_| mpsc::channel())
+            .unzip();
+
+        return node_ids.iter().zip(receivers).map(|(node_id, inbound)| {
+            let peers = node_ids.iter().zip(senders.iter())
+                .filter(|(peer, _)| *peer != node_id)
+                .map(|(peer, sender)| (peer.clone(), sender.clone()))
+                .collect();
+            Channel { node_id: node_id.clone(), inbound, peers }
+        }).collect();
+    }
+
+    /// Stops sending to a peer, e.g. to build a network that isn't fully connected.
+    pub fn disconnect(&mut self, peer: &NodeId) {
+        self.peers.remove(peer);
+    }
+}
+
+impl<T: Value> Transport<T> for Channel<T> {
+    fn node_id(&self) -> &NodeId {
+        return &self.node_id;
+    }
+
+    fn peers(&self) -> Vec<NodeId> {
+        return self.peers.keys().cloned().collect();
+    }
+
+    fn send_to(&mut self, peer: &NodeId, message: &Message<T>) -> Result<(), ()> {
+        let sender = self.peers.get(peer).ok_or(())?;
+        return sender.send((self.node_id.clone(), message.clone())).map_err(|_| ());
+    }
+
+    fn recv_from(&mut self, timeout: Duration) -> Result<Option<(NodeId, Message<T>)>, ()> {
+        return match self.inbound.recv_timeout(timeout) {
+            Ok(received)                        => Ok(Some(received)),
+            Err(RecvTimeoutError::Timeout)      => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(()),
+        };
+    }
+}
+
+// TCP
+
+/// The largest frame we'll read, so a bogus length can't make us allocate the world.
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// How long we wait to connect to a peer, or for a peer to take a frame,
+/// before giving up on them until next time.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn write_frame<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)?;
+    return writer.flush();
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+    }
+
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    return Ok(bytes);
+}
+
+/// What a connection is for: the first byte of its first frame.
+/// A connection for messages opens with our id, so the other end knows who they're from,
+/// and a connection for catching up opens with the [`catchup::Request`].
+const MESSAGES: u8 = 0;
+const CATCH_UP: u8 = 1;
+
+/// A catch-up request from a peer, and where to send our response.
+type Pending<T> = (catchup::Request, Sender<catchup::Response<T>>);
+
+/// Works out what a new connection is for from its first frame, and hands it off.
+fn accept<T: Value + Encode + Decode>(
+    mut stream: TcpStream,
+    inbound:    Sender<(NodeId, Message<T>)>,
+    requests:   Sender<Pending<T>>,
+) {
+    let hello = match read_frame(&mut stream) {
+        Ok(hello) => hello,
+        Err(_)    => { return; },
+    };
+    let mut input = &hello[..];
+
+    match u8::decode(&mut input) {
+        Ok(MESSAGES) => if let Ok(peer) = codec::from_bytes(input) { read_messages(stream, peer, inbound); },
+        Ok(CATCH_UP) => if let Ok(request) = codec::from_bytes(input) { answer(stream, request, requests); },
+        _            => (),
+    }
+}
+
+/// Passes a catch-up request on to whoever owns the transport,
+/// and writes back their response, if it comes in time.
+fn answer<T: Value + Encode>(mut stream: TcpStream, request: catchup::Request, requests: Sender<Pending<T>>) {
+    let (reply, response) = mpsc::channel();
+    if requests.send((request, reply)).is_err() { return; }
+
+    if let Ok(response) = response.recv_timeout(TIMEOUT) {
+        let _ = stream.set_write_timeout(Some(TIMEOUT))
+            .and_then(|()| write_frame(&mut stream, &codec::to_bytes(&response)));
+    }
+}
+
+/// Reads messages off a connection until it closes or says something we can't decode.
+fn read_messages<T: Value + Decode>(mut stream: TcpStream, peer: NodeId, inbound: Sender<(NodeId, Message<T>)>) {
+    while let Ok(bytes) = read_frame(&mut stream) {
+        let message = match codec::from_bytes(&bytes) {
+            Ok(message) => message,
+            Err(())     => { return; },
+        };
+        // the transport's gone, nobody's listening
+        if inbound.send((peer.clone(), message)).is_err() { return; }
+    }
+}
+
+/// A transport over TCP.
+/// Each message is sent as a little-endian `u64` length, followed by the [`codec`]-encoded message.
+/// A connection opens with a frame holding our [`NodeId`], so the other end knows who it's from.
+/// We listen on one address, and connect to each peer when we first send to it,
+/// reconnecting if the connection breaks.
+/// Every inbound connection gets a thread that reads from it,
+/// until the peer hangs up, or sends something after we're gone.
+/// The listener thread stops as soon as we're dropped.
+///
+/// Catching up gets a connection of its own for each request,
+/// see [`Tcp::request_catch_up`] and [`Tcp::answer_catch_ups`].
+pub struct Tcp<T: Value> {
+    node_id:     NodeId,
+    local:       SocketAddr,
+    peers:       HashMap<NodeId, SocketAddr>,
+    connections: HashMap<NodeId, TcpStream>,
+    inbound:     Receiver<(NodeId, Message<T>)>,
+    /// Catch-up requests from our peers, waiting for an answer.
+    requests:    Receiver<Pending<T>>,
+    /// Responses to our own catch-up requests, as they come in.
+    responded:   Sender<catchup::Response<T>>,
+    responses:   Receiver<catchup::Response<T>>,
+    /// Tells the listener thread to stop, once we're dropped.
+    stopping:    Arc<AtomicBool>,
+    listener:    Option<JoinHandle<()>>,
+}
+
+impl<T: Value + Encode + Decode + Send + 'static> Tcp<T> {
+    /// Starts listening on `addr`. Bind to port 0 to pick any free port.
+    pub fn bind<A: ToSocketAddrs>(
+        node_id: NodeId,
+        addr:    A,
+        peers:   HashMap<NodeId, SocketAddr>,
+    ) -> io::Result<Tcp<T>> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        let (sender, inbound) = mpsc::channel();
+        let (requested, requests) = mpsc::channel();
+        let (responded, responses) = mpsc::channel();
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        // only the listener and the readers hold on to senders,
+        // so if they're all gone, `recv` knows nothing else is coming
+        let stop = stopping.clone();
+        let listener = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop.load(Ordering::SeqCst) { return; }
+                let stream = match stream { Ok(s) => s, Err(_) => continue };
+                let (sender, requested) = (sender.clone(), requested.clone());
+                thread::spawn(move || accept(stream, sender, requested));
+            }
+        });
+
+        return Ok(Tcp {
+            node_id,
+            local,
+            peers,
+            connections: HashMap::new(),
+            inbound,
+            requests,
+            responded,
+            responses,
+            stopping,
+            listener: Some(listener),
+        });
+    }
+
+    /// The address we're actually listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        return self.local;
+    }
+
+    pub fn add_peer(&mut self, node_id: NodeId, addr: SocketAddr) {
+        self.connections.remove(&node_id);
+        self.peers.insert(node_id, addr);
+    }
+
+    fn connection(&mut self, peer: &NodeId) -> io::Result<&mut TcpStream> {
+        if !self.connections.contains_key(peer) {
+            let addr = self.peers.get(peer)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "unknown peer"))?;
+            let mut stream = TcpStream::connect_timeout(addr, TIMEOUT)?;
+            stream.set_nodelay(true)?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            let mut hello = vec![MESSAGES];
+            self.node_id.encode(&mut hello);
+            write_frame(&mut stream, &hello)?;
+            self.connections.insert(peer.clone(), stream);
+        }
+        // TODO: safe to unwrap? we just inserted it
+        return Ok(self.connections.get_mut(peer).unwrap());
+    }
+
+    /// Asks a peer to help us catch up, see [`Node::catch_up`].
+    /// The response comes back on another thread, see [`Tcp::catch_up_response`],
+    /// so a slow peer doesn't hold everything else up.
+    pub fn request_catch_up(&mut self, peer: &NodeId, request: &catchup::Request) -> Result<(), ()> {
+        let addr = *self.peers.get(peer).ok_or(())?;
+        let mut hello = vec![CATCH_UP];
+        request.encode(&mut hello);
+
+        let responded = self.responded.clone();
+        thread::spawn(move || {
+            let bytes = TcpStream::connect_timeout(&addr, TIMEOUT).and_then(|mut stream| {
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                // they get a while to answer, on top of the time it takes to send
+                stream.set_read_timeout(Some(TIMEOUT * 2))?;
+                write_frame(&mut stream, &hello)?;
+                return read_frame(&mut stream);
+            });
+            if let Ok(Ok(response)) = bytes.map(|bytes| codec::from_bytes(&bytes)) {
+                let _ = responded.send(response);
+            }
+        });
+        return Ok(());
+    }
+
+    /// A response to one of our catch-up requests, if one has come in.
+    /// Peers that couldn't be reached, or took too long, never respond.
+    pub fn catch_up_response(&mut self) -> Option<catchup::Response<T>> {
+        return self.responses.try_recv().ok();
+    }
+
+    /// Answers every catch-up request that's come in from our peers, from what `node` knows.
+    pub fn answer_catch_ups(&mut self, node: &mut Node<T>) {
+        while let Ok((request, reply)) = self.requests.try_recv() {
+            let _ = reply.send(node.handle_catch_up(&request));
+        }
+    }
+}
+
+impl<T: Value> Drop for Tcp<T> {
+    /// Stops the listener thread and waits for it.
+    /// It's stuck in `accept` until someone connects, so we connect to ourselves to wake it.
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        let wake = match self.local.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), self.local.port()),
+            IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), self.local.port()),
+            _                                     => self.local,
+        };
+        // if we can't reach ourselves, leaving it running beats hanging here
+        if TcpStream::connect_timeout(&wake, TIMEOUT).is_err() { return; }
+        if let Some(listener) = self.listener.take() { let _ = listener.join(); }
+    }
+}
+
+impl<T: Value + Encode + Decode + Send + 'static> Transport<T> for Tcp<T> {
+    fn node_id(&self) -> &NodeId {
+        return &self.node_id;
+    }
+
+    fn peers(&self) -> Vec<NodeId> {
+        return self.peers.keys().cloned().collect();
+    }
+
+    fn send_to(&mut self, peer: &NodeId, message: &Message<T>) -> Result<(), ()> {
+        let bytes = codec::to_bytes(message);
+        let sent = self.connection(peer).and_then(|stream| write_frame(stream, &bytes));
+
+        // drop broken connections, we'll reconnect next time
+        if sent.is_err() { self.connections.remove(peer); }
+        return sent.map_err(|_| ());
+    }
+
+    fn recv_from(&mut self, timeout: Duration) -> Result<Option<(NodeId, Message<T>)>, ()> {
+        return match self.inbound.recv_timeout(timeout) {
+            Ok(received)                        => Ok(Some(received)),
+            Err(RecvTimeoutError::Timeout)      => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arbitrary::{Arbitrary, Source},
+        ballot::Ballot,
+        fixtures::{node_id, DummyValue},
+        quorum::Quorum,
+        slot::SlotId,
+        topic,
+    };
+    use std::time::Instant;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn channel() {
+        let ids = [node_id("a"), node_id("b"), node_id("c")];
+        let mut channels = Channel::<DummyValue>::network(&ids);
+        let message = Message::arbitrary(&mut Source::new(1));
+
+        channels[0].broadcast(&message).unwrap();
+        assert_eq!(channels[1].recv(WAIT), Ok(Some(message.clone())));
+        assert_eq!(channels[2].recv(WAIT), Ok(Some(message.clone())));
+        assert_eq!(channels[0].recv(Duration::from_millis(1)), Ok(None));
+
+        channels[2].send_to(&ids[1], &message).unwrap();
+        assert_eq!(channels[1].recv_from(WAIT), Ok(Some((ids[2].clone(), message))));
+        assert_eq!(channels[0].recv(Duration::from_millis(1)), Ok(None));
+
+        // everyone else is gone
+        let mut last = channels.pop().unwrap();
+        drop(channels);
+        assert_eq!(last.recv(WAIT), Err(()));
+    }
+
+    #[test]
+    fn tcp_loopback() {
+        let mut a = Tcp::<DummyValue>::bind(node_id("a"), "127.0.0.1:0", HashMap::new()).unwrap();
+        let mut b = Tcp::<DummyValue>::bind(node_id("b"), "127.0.0.1:0", HashMap::new()).unwrap();
+        a.add_peer(node_id("b"), b.local_addr());
+        b.add_peer(node_id("a"), a.local_addr());
+
+        let mut source = Source::new(2);
+        let messages = (0..10).map(|_| Message::arbitrary(&mut source)).collect::<Vec<Message<DummyValue>>>();
+
+        for message in messages.iter() { a.broadcast(message).unwrap(); }
+        for message in messages.iter() { assert_eq!(b.recv(WAIT), Ok(Some(message.clone()))); }
+
+        b.send_to(&node_id("a"), &messages[0]).unwrap();
+        assert_eq!(a.recv_from(WAIT), Ok(Some((node_id("b"), messages[0].clone()))));
+        assert_eq!(a.send_to(&node_id("c"), &messages[0]), Err(()));
+    }
+
+    #[test]
+    fn tcp_catch_up() {
+        let mut a = Tcp::<DummyValue>::bind(node_id("a"), "127.0.0.1:0", HashMap::new()).unwrap();
+        let mut b = Tcp::<DummyValue>::bind(node_id("b"), "127.0.0.1:0", HashMap::new()).unwrap();
+        a.add_peer(node_id("b"), b.local_addr());
+
+        let externalize = topic::Externalize { ballot: Ballot { number: 1, value: DummyValue(1) }, highest: 1 };
+        let externalized = vec![(SlotId::new(1), externalize.clone())].into_iter().collect();
+        let mut node = Node::new(node_id("b"), Quorum::new(0, vec![]), externalized);
+
+        let request = catchup::Request { sender: node_id("a"), after: None, to: SlotId::new(1) };
+        assert_eq!(a.request_catch_up(&node_id("c"), &request), Err(()));
+        a.request_catch_up(&node_id("b"), &request).unwrap();
+
+        let deadline = Instant::now() + WAIT;
+        let response = loop {
+            b.answer_catch_ups(&mut node);
+            if let Some(response) = a.catch_up_response() { break response; }
+            assert!(Instant::now() < deadline, "no response");
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(response.sender, node_id("b"));
+        assert_eq!(response.proofs.len(), 1);
+        assert_eq!(response.proofs[0].externalize, externalize);
+
+        // and messages still get through as usual
+        let message = Message::arbitrary(&mut Source::new(3));
+        a.send_to(&node_id("b"), &message).unwrap();
+        assert_eq!(b.recv_from(WAIT), Ok(Some((node_id("a"), message))));
+    }
+
+    #[test]
+    fn tcp_stops_listening() {
+        let tcp = Tcp::<DummyValue>::bind(node_id("a"), "127.0.0.1:0", HashMap::new()).unwrap();
+        let addr = tcp.local_addr();
+        drop(tcp);
+
+        // nobody's listening any more, so the port's free again
+        assert!(TcpStream::connect_timeout(&addr, WAIT).is_err());
+        assert!(TcpListener::bind(addr).is_ok());
+    }
+}